@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::HeaderValue;
+
+/// Credentials sent to server in `Authorization` header
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// User name and password, sent with Basic scheme
+    Basic { user: String, password: String },
+    /// Token, sent with Bearer scheme
+    Bearer(String),
+}
+
+impl Credentials {
+    /// Makes value of `Authorization` header
+    pub fn header_value(&self) -> Result<HeaderValue> {
+        let value = match self {
+            Credentials::Basic { user, password } => {
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{}:{}", user, password))
+                )
+            }
+            Credentials::Bearer(token) => format!("Bearer {}", token),
+        };
+        let mut value = HeaderValue::from_str(&value).context("credentials aren't valid header")?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+impl fmt::Debug for Credentials {
+    /// Secrets are never printed
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::Basic { user, .. } => write!(f, "Basic({}:***)", user),
+            Credentials::Bearer(_) => f.write_str("Bearer(***)"),
+        }
+    }
+}
+
+/// Credentials given on command line, for all hosts or for one of them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostCredentials {
+    /// Host credentials are sent to, or `None` if they're sent to any host
+    pub host: Option<String>,
+    /// Credentials themselves
+    pub credentials: Credentials,
+}
+
+impl HostCredentials {
+    /// Parses user name and password like `user:pass` or `user:pass@host`
+    pub fn basic(spec: &str) -> Result<HostCredentials> {
+        let (userinfo, host) = split_host(spec);
+        let Some((user, password)) = userinfo.split_once(':') else {
+            bail!("expected credentials like user:password[@host]");
+        };
+        Ok(HostCredentials {
+            host,
+            credentials: Credentials::Basic {
+                user: user.to_owned(),
+                password: password.to_owned(),
+            },
+        })
+    }
+    /// Parses bearer token like `token` or `token@host`
+    pub fn bearer(spec: &str) -> Result<HostCredentials> {
+        let (token, host) = split_host(spec);
+        if token.is_empty() {
+            bail!("expected token like token[@host]");
+        }
+        let credentials = Credentials::Bearer(token.to_owned());
+        credentials.header_value()?;
+        Ok(HostCredentials { host, credentials })
+    }
+}
+/// Splits host which credentials are bound to, if any, off the last `@`
+fn split_host(spec: &str) -> (&str, Option<String>) {
+    match spec.rsplit_once('@') {
+        // Host is matched by name only, so part with colon is rather part of password
+        Some((secret, host)) if !host.is_empty() && !host.contains(':') => {
+            (secret, Some(host.to_ascii_lowercase()))
+        }
+        _ => (spec, None),
+    }
+}
+
+/// Credentials of single `.netrc` entry
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct NetrcEntry {
+    login: Option<String>,
+    password: Option<String>,
+}
+
+impl NetrcEntry {
+    /// Credentials of entry, if it has both login and password
+    fn credentials(&self) -> Option<Credentials> {
+        Some(Credentials::Basic {
+            user: self.login.clone()?,
+            password: self.password.clone()?,
+        })
+    }
+}
+
+/// Credentials from `.netrc` file, by host
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Netrc {
+    /// Credentials of `machine` entries, by lowercase host name
+    machines: HashMap<String, NetrcEntry>,
+    /// Credentials of `default` entry, used for any other host
+    default: Option<NetrcEntry>,
+}
+
+impl Netrc {
+    /// Reads `.netrc` file
+    pub fn load(path: &Path) -> Result<Netrc> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("{}: cannot read netrc file", path.display()))?;
+        text.parse()
+            .with_context(|| format!("{}: malformed netrc file", path.display()))
+    }
+    /// Finds `.netrc` file of current user: the one `NETRC` variable points to,
+    /// otherwise `.netrc` in home directory, or `_netrc` on Windows
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("NETRC") {
+            return Some(PathBuf::from(path));
+        }
+        let (home, name) = match cfg!(windows) {
+            true => (std::env::var_os("USERPROFILE")?, "_netrc"),
+            false => (std::env::var_os("HOME")?, ".netrc"),
+        };
+        Some(Path::new(&home).join(name))
+    }
+    /// Finds credentials of specified host, from its own entry or default one
+    pub fn lookup(&self, host: &str) -> Option<Credentials> {
+        match self.machines.get(&host.to_ascii_lowercase()) {
+            Some(entry) => entry.credentials(),
+            None => self.default.as_ref()?.credentials(),
+        }
+    }
+}
+
+impl FromStr for Netrc {
+    type Err = anyhow::Error;
+
+    /// Parses `.netrc` contents: `machine`, `default`, `login`, `password` and `account` tokens,
+    /// while `macdef` macros are skipped up to blank line ending them
+    fn from_str(s: &str) -> Result<Netrc> {
+        let mut netrc = Netrc::default();
+        // Entry being parsed, with its host, or `None` for default one
+        let mut current: Option<(Option<String>, NetrcEntry)> = None;
+        let finish = |current: Option<(Option<String>, NetrcEntry)>, netrc: &mut Netrc| {
+            match current {
+                Some((Some(host), entry)) => {
+                    // The first entry of host is the one used, as in other tools
+                    netrc.machines.entry(host).or_insert(entry);
+                }
+                Some((None, entry)) => netrc.default = Some(entry),
+                None => {}
+            }
+        };
+        let mut lines = s.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                match token {
+                    "machine" => {
+                        finish(current.take(), &mut netrc);
+                        let host = tokens.next().context("machine without name")?;
+                        current = Some((Some(host.to_ascii_lowercase()), NetrcEntry::default()));
+                    }
+                    "default" => {
+                        finish(current.take(), &mut netrc);
+                        current = Some((None, NetrcEntry::default()));
+                    }
+                    "login" | "password" | "account" => {
+                        let value = tokens
+                            .next()
+                            .with_context(|| format!("{} without value", token))?;
+                        let Some((_, entry)) = &mut current else {
+                            bail!("{} outside of machine entry", token);
+                        };
+                        match token {
+                            "login" => entry.login = Some(value.to_owned()),
+                            "password" => entry.password = Some(value.to_owned()),
+                            _ => {}
+                        }
+                    }
+                    "macdef" => {
+                        // Macro body lasts up to blank line, and isn't of interest
+                        finish(current.take(), &mut netrc);
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    // Comment lasts up to line end
+                    token if token.starts_with('#') => break,
+                    token => bail!("{}: unknown token", token),
+                }
+            }
+        }
+        finish(current, &mut netrc);
+        Ok(netrc)
+    }
+}
+
+/// Credentials of all hosts, from command line and `.netrc`
+///
+/// Host's own credentials are preferred, then its `.netrc` machine entry,
+/// then credentials given for any host, and finally `.netrc` default entry
+#[derive(Clone, Debug, Default)]
+pub struct Auth {
+    /// Credentials given on command line
+    pub credentials: Vec<HostCredentials>,
+    /// Contents of `.netrc`, if it's used
+    pub netrc: Option<Netrc>,
+}
+
+impl Auth {
+    /// Finds credentials to send to specified host
+    pub fn lookup(&self, host: &str) -> Option<Credentials> {
+        let given = |host: Option<&str>| {
+            self.credentials
+                .iter()
+                .find(|given| given.host.as_deref() == host)
+                .map(|given| given.credentials.clone())
+        };
+        let host = host.to_ascii_lowercase();
+        let netrc = self.netrc.as_ref();
+        given(Some(&host))
+            .or_else(|| netrc?.machines.get(&host)?.credentials())
+            .or_else(|| given(None))
+            .or_else(|| netrc?.default.as_ref()?.credentials())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Auth, Credentials, HostCredentials, Netrc};
+    use assert_matches::assert_matches;
+
+    fn basic(user: &str, password: &str) -> Credentials {
+        Credentials::Basic {
+            user: user.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_netrc() {
+        let text = "machine a.com login alice password secret\n\
+                    # comment\n\
+                    machine B.com\n  login bob\n  password pw account x\n\
+                    macdef init\ncd /pub\n\n\
+                    machine c.com login carol\n\
+                    default login anon password guest\n";
+        let netrc: Netrc = text.parse().unwrap();
+        assert_eq!(netrc.lookup("a.com"), Some(basic("alice", "secret")));
+        assert_eq!(netrc.lookup("b.COM"), Some(basic("bob", "pw")));
+        // Entry without password is useless
+        assert_eq!(netrc.lookup("c.com"), None);
+        assert_eq!(netrc.lookup("other.com"), Some(basic("anon", "guest")));
+        assert_matches!("machine a.com login".parse::<Netrc>(), Err(_));
+        assert_matches!("login a".parse::<Netrc>(), Err(_));
+    }
+
+    #[test]
+    fn host_credentials() {
+        // Part after `@` which can't be host belongs to password
+        let global = HostCredentials::basic("user:p@ss:word").unwrap();
+        assert_eq!(global.host, None);
+        assert_eq!(global.credentials, basic("user", "p@ss:word"));
+        assert_matches!(HostCredentials::basic("user@a.com"), Err(_));
+        let bearer = HostCredentials::bearer("abc==@A.com").unwrap();
+        assert_eq!(bearer.host.as_deref(), Some("a.com"));
+        assert_eq!(bearer.credentials, Credentials::Bearer("abc==".to_owned()));
+        assert_eq!(
+            basic("Aladdin", "open sesame").header_value().unwrap(),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert_eq!(format!("{:?}", basic("a", "secret")), "Basic(a:***)");
+
+        let auth = Auth {
+            credentials: vec![
+                HostCredentials::bearer("token").unwrap(),
+                HostCredentials::basic("user:pass@b.com").unwrap(),
+            ],
+            netrc: Some("machine a.com login alice password secret".parse().unwrap()),
+        };
+        assert_eq!(auth.lookup("B.com"), Some(basic("user", "pass")));
+        assert_eq!(auth.lookup("a.com"), Some(basic("alice", "secret")));
+        assert_eq!(
+            auth.lookup("c.com"),
+            Some(Credentials::Bearer("token".to_owned()))
+        );
+        assert_eq!(Auth::default().lookup("c.com"), None);
+    }
+}
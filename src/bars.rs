@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Width of progress bar itself, in characters
+const BAR_WIDTH: usize = 30;
+
+/// Progress of single running job
+struct Bar {
+    /// Destination file name
+    name: String,
+    /// Bytes received so far
+    bytes: u64,
+    /// File size, if known
+    total: Option<u64>,
+}
+
+/// Progress bars of running jobs, plus aggregate one, drawn below log messages
+///
+/// When disabled, log messages are printed as-is and progress isn't shown
+pub struct Bars {
+    /// Whether bars are drawn at all
+    enabled: bool,
+    /// Whether messages to stdout are suppressed, because stdout is used for other output
+    quiet: bool,
+    /// Running jobs, by entry index
+    jobs: BTreeMap<usize, Bar>,
+    /// Number of jobs to be run
+    total_jobs: usize,
+    /// Number of jobs which have ended
+    done_jobs: usize,
+    /// Bytes received by ended jobs
+    done_bytes: u64,
+    /// Number of lines drawn by last redraw, erased before next one
+    drawn: usize,
+}
+
+impl Bars {
+    /// Creates progress bars
+    ///
+    /// # Arguments
+    /// * enabled - whether to draw bars; should be off when output isn't a terminal
+    /// * total_jobs - number of jobs to be run, for aggregate bar
+    pub fn new(enabled: bool, total_jobs: usize) -> Bars {
+        Bars {
+            enabled,
+            quiet: false,
+            jobs: BTreeMap::new(),
+            total_jobs,
+            done_jobs: 0,
+            done_bytes: 0,
+            drawn: 0,
+        }
+    }
+    /// Creates progress bars which draw nothing and print only messages to stderr
+    pub fn quiet(total_jobs: usize) -> Bars {
+        Bars {
+            quiet: true,
+            ..Bars::new(false, total_jobs)
+        }
+    }
+    /// Whether bars are drawn
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    /// Prints message to stdout above bars
+    pub fn println(&mut self, message: &str) {
+        if self.quiet {
+            return;
+        }
+        self.erase();
+        println!("{}", message);
+        self.draw();
+    }
+    /// Prints message to stderr above bars
+    pub fn eprintln(&mut self, message: &str) {
+        self.erase();
+        eprintln!("{}", message);
+        self.draw();
+    }
+    /// Adds bar for started job
+    pub fn start(&mut self, index: usize, name: &str) {
+        let bar = Bar {
+            name: name.to_owned(),
+            bytes: 0,
+            total: None,
+        };
+        self.jobs.insert(index, bar);
+        self.redraw();
+    }
+    /// Updates amount of data received by job
+    pub fn update(&mut self, index: usize, bytes: u64, total: Option<u64>) {
+        if let Some(bar) = self.jobs.get_mut(&index) {
+            bar.bytes = bytes;
+            bar.total = total;
+        }
+        self.redraw();
+    }
+    /// Removes bar of ended job, accounting its data in aggregate bar
+    pub fn end(&mut self, index: usize, bytes: u64) {
+        self.jobs.remove(&index);
+        self.done_jobs += 1;
+        self.done_bytes += bytes;
+        self.redraw();
+    }
+    /// Erases bars, so nothing is left on screen after the run
+    pub fn clear(&mut self) {
+        self.erase();
+    }
+
+    fn redraw(&mut self) {
+        self.erase();
+        self.draw();
+    }
+    /// Moves cursor up to the first drawn line, erasing all lines below
+    fn erase(&mut self) {
+        if self.drawn > 0 {
+            print!("\x1b[{}A\x1b[J", self.drawn);
+            self.drawn = 0;
+        }
+    }
+    fn draw(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        let mut lines: Vec<_> = self
+            .jobs
+            .values()
+            .map(|bar| line(&bar.name, bar.bytes, bar.total))
+            .collect();
+        let bytes = self.done_bytes + self.jobs.values().map(|bar| bar.bytes).sum::<u64>();
+        let files = format!("{}/{} files", self.done_jobs, self.total_jobs);
+        lines.push(format!(
+            "{} {}",
+            bar(self.done_jobs as u64, Some(self.total_jobs as u64)),
+            describe(&files, bytes, None)
+        ));
+        let mut stdout = io::stdout().lock();
+        for line in &lines {
+            let _ = writeln!(stdout, "{}", line);
+        }
+        let _ = stdout.flush();
+        self.drawn = lines.len();
+    }
+}
+/// Formats progress line of single job
+fn line(name: &str, bytes: u64, total: Option<u64>) -> String {
+    format!("{} {}", bar(bytes, total), describe(name, bytes, total))
+}
+/// Formats bar filled according to progress; bar is empty if total isn't known
+fn bar(done: u64, total: Option<u64>) -> String {
+    let filled = match total {
+        Some(total) if total > 0 => (done.min(total) * BAR_WIDTH as u64 / total) as usize,
+        _ => 0,
+    };
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled))
+}
+/// Formats label with amount of data
+fn describe(label: &str, bytes: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) => format!("{} {}/{}", label, human_size(bytes), human_size(total)),
+        None => format!("{} {}", label, human_size(bytes)),
+    }
+}
+/// Formats size in bytes with binary unit suffix
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{human_size, line};
+
+    #[test]
+    fn format_progress() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024), "3.0 MiB");
+        assert_eq!(
+            line("file", 512, Some(1024)),
+            format!("[{}{}] file 512 B/1.0 KiB", "#".repeat(15), " ".repeat(15))
+        );
+        assert_eq!(
+            line("file", 2048, None),
+            format!("[{}] file 2.0 KiB", " ".repeat(30))
+        );
+    }
+}
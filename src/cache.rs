@@ -0,0 +1,132 @@
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio::fs;
+
+use crate::digest::Algorithm;
+use crate::validators::{validators_path, Validators};
+
+/// Storage of previously downloaded files, keyed by source URL
+///
+/// Downloader asks cache before downloading file; if cached copy has the same validators
+/// as remote file, it's restored from cache instead. Embedders can plug their own storage,
+/// like object store or key-value database
+pub trait Cache: Debug + Send + Sync {
+    /// Returns validators of cached copy of file from URL, if there's one
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Option<Validators>>>;
+    /// Writes cached copy of file from URL into destination path
+    ///
+    /// # Returns
+    /// Number of bytes written
+    fn restore<'a>(&'a self, url: &'a str, dest_path: &'a Path) -> BoxFuture<'a, Result<u64>>;
+    /// Stores copy of downloaded file from URL, along with its validators
+    fn put<'a>(
+        &'a self,
+        url: &'a str,
+        validators: &'a Validators,
+        src_path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>>;
+    /// Drops cached copy of file from URL, e.g. because it turned out to be damaged
+    fn remove<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Cache which keeps files in local directory, named by hash of their URL,
+/// with validators in `.meta` files next to them
+#[derive(Debug)]
+pub struct FsCache {
+    dir: PathBuf,
+}
+
+impl FsCache {
+    /// Creates cache in specified directory, which must exist
+    pub fn new(dir: impl Into<PathBuf>) -> FsCache {
+        FsCache { dir: dir.into() }
+    }
+    /// Path of cached copy of file from URL
+    fn entry_path(&self, url: &str) -> PathBuf {
+        let mut hasher = Algorithm::Sha256.hasher();
+        hasher.update(url.as_bytes());
+        self.dir.join(hex::encode(hasher.finalize()))
+    }
+}
+
+impl Cache for FsCache {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Option<Validators>>> {
+        Box::pin(async move {
+            let path = self.entry_path(url);
+            // Validators without data are useless
+            if fs::metadata(&path).await.is_err() {
+                return Ok(None);
+            }
+            Ok(Validators::load(&path).await)
+        })
+    }
+
+    fn restore<'a>(&'a self, url: &'a str, dest_path: &'a Path) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move { Ok(fs::copy(self.entry_path(url), dest_path).await?) })
+    }
+
+    fn put<'a>(
+        &'a self,
+        url: &'a str,
+        validators: &'a Validators,
+        src_path: &'a Path,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = self.entry_path(url);
+            fs::copy(src_path, &path).await?;
+            validators.save(&path).await
+        })
+    }
+
+    fn remove<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = self.entry_path(url);
+            // Validators without data are ignored anyway
+            let _ = fs::remove_file(validators_path(&path)).await;
+            fs::remove_file(&path).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, FsCache};
+    use crate::validators::Validators;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn fs_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = FsCache::new(dir.path().join("cache"));
+        std::fs::create_dir(dir.path().join("cache")).unwrap();
+        let src = dir.path().join("src");
+        let dest = dir.path().join("dest");
+        std::fs::write(&src, b"data").unwrap();
+        let validators = Validators {
+            etag: Some("\"a\"".to_owned()),
+            last_modified: None,
+            length: Some(4),
+        };
+
+        Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                assert_eq!(cache.get("http://a/file").await.unwrap(), None);
+                cache.put("http://a/file", &validators, &src).await.unwrap();
+                assert_eq!(
+                    cache.get("http://a/file").await.unwrap(),
+                    Some(validators.clone())
+                );
+                assert_eq!(cache.get("http://a/other").await.unwrap(), None);
+                assert_eq!(cache.restore("http://a/file", &dest).await.unwrap(), 4);
+                assert_eq!(std::fs::read(&dest).unwrap(), b"data");
+                cache.remove("http://a/file").await.unwrap();
+                assert_eq!(cache.get("http://a/file").await.unwrap(), None);
+            });
+    }
+}
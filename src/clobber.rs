@@ -0,0 +1,274 @@
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use tokio::sync::Mutex;
+
+/// What to do when destination file already exists
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clobber {
+    /// Replace existing file
+    Overwrite,
+    /// Keep existing file, don't download entry
+    Skip,
+    /// Download into new file next to existing one
+    Rename,
+    /// Ask user about each conflicting file
+    Ask,
+}
+
+impl Clobber {
+    /// All known modes
+    pub const ALL: [Clobber; 4] = [
+        Clobber::Overwrite,
+        Clobber::Skip,
+        Clobber::Rename,
+        Clobber::Ask,
+    ];
+    /// Name of mode, as used in CLI
+    pub fn name(self) -> &'static str {
+        match self {
+            Clobber::Overwrite => "overwrite",
+            Clobber::Skip => "skip",
+            Clobber::Rename => "rename",
+            Clobber::Ask => "ask",
+        }
+    }
+}
+
+impl FromStr for Clobber {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Clobber> {
+        match Clobber::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+        {
+            Some(mode) => Ok(mode),
+            None => bail!("{}: unknown clobber mode", s),
+        }
+    }
+}
+impl fmt::Display for Clobber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Resolves conflicts between download entries and existing files, shared by all jobs
+pub struct Conflicts {
+    mode: Clobber,
+    /// Answer which user chose to apply to all further conflicts.
+    /// Lock is held while prompt is shown, so prompts don't interleave
+    answer_all: Mutex<Option<Clobber>>,
+}
+
+impl Conflicts {
+    pub fn new(mode: Clobber) -> Conflicts {
+        Conflicts {
+            mode,
+            answer_all: Mutex::new(None),
+        }
+    }
+    /// Decides where entry should be downloaded
+    ///
+    /// # Arguments
+    /// * path - entry's destination path
+    ///
+    /// # Returns
+    /// Returns `None` if destination doesn't exist, otherwise action taken, which is never `ask`,
+    /// and path to download into; path of existing file if entry should be skipped.
+    ///
+    /// In `ask` mode, only the calling job waits for user's answer, others continue.
+    /// Prompt is shown on stderr and answered on stdin; unless both are terminals,
+    /// nobody can answer, so conflicting entries are skipped
+    pub async fn resolve(&self, path: &Path) -> Result<Option<(Clobber, PathBuf)>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let resolution = match self.mode {
+            Clobber::Ask => {
+                let mut answer_all = self.answer_all.lock().await;
+                match *answer_all {
+                    Some(answer) => answer,
+                    None if !io::stdin().is_terminal() || !io::stderr().is_terminal() => {
+                        Clobber::Skip
+                    }
+                    None => {
+                        let prompted = path.to_owned();
+                        let (answer, for_all) =
+                            tokio::task::spawn_blocking(move || prompt(&prompted)).await??;
+                        if for_all {
+                            *answer_all = Some(answer);
+                        }
+                        answer
+                    }
+                }
+            }
+            mode => mode,
+        };
+        Ok(Some(match resolution {
+            Clobber::Rename => (resolution, free_path(path)),
+            _ => (resolution, path.to_owned()),
+        }))
+    }
+    /// Moves downloaded file to name learned only from response, like one suggested by server,
+    /// resolving conflict with existing file under that name the same way as for destination
+    ///
+    /// # Returns
+    /// Final path of file, with action taken if name was taken already.
+    /// If existing file is kept, downloaded one is removed
+    pub async fn rename(&self, from: &Path, to: &Path) -> Result<(PathBuf, Option<Clobber>)> {
+        loop {
+            let (action, target) = match self.resolve(to).await? {
+                None => (None, to.to_owned()),
+                Some((Clobber::Skip, existing)) => {
+                    tokio::fs::remove_file(from).await?;
+                    return Ok((existing, Some(Clobber::Skip)));
+                }
+                Some((Clobber::Overwrite, target)) => {
+                    tokio::fs::rename(from, &target).await?;
+                    return Ok((target, Some(Clobber::Overwrite)));
+                }
+                Some((action, target)) => (Some(action), target),
+            };
+            match rename_new(from, &target).await {
+                Ok(()) => return Ok((target, action)),
+                // Name was taken meanwhile, so conflict is resolved anew
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+/// Moves file to new path, unless something exists there, even if it appears concurrently
+///
+/// Hard link claims new path atomically; on filesystems without hard links,
+/// path is checked right before renaming
+pub async fn rename_new(from: &Path, to: &Path) -> io::Result<()> {
+    match tokio::fs::hard_link(from, to).await {
+        Ok(()) => tokio::fs::remove_file(from).await,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Err(err),
+        Err(_) if tokio::fs::try_exists(to).await? => Err(io::ErrorKind::AlreadyExists.into()),
+        Err(_) => tokio::fs::rename(from, to).await,
+    }
+}
+/// Asks user what to do with existing file, until valid answer is given
+///
+/// Prompt goes to stderr, so stdout holds only regular output, like JSON events
+///
+/// Returns chosen resolution, and whether it applies to all further conflicts
+fn prompt(path: &Path) -> Result<(Clobber, bool)> {
+    let stdin = io::stdin();
+    loop {
+        eprint!(
+            "{} already exists. [o]verwrite, [s]kip, [r]ename, overwrite [a]ll? ",
+            path.display()
+        );
+        io::stderr().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            // Input is closed, so no answer will ever come
+            return Ok((Clobber::Skip, true));
+        }
+        match line.trim() {
+            "o" => return Ok((Clobber::Overwrite, false)),
+            "s" => return Ok((Clobber::Skip, false)),
+            "r" => return Ok((Clobber::Rename, false)),
+            "a" => return Ok((Clobber::Overwrite, true)),
+            _ => continue,
+        }
+    }
+}
+/// Finds first non-existing path among `<name>(1).<ext>`, `<name>(2).<ext>` etc.
+fn free_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    (1..)
+        .map(|n| {
+            let mut name = stem.to_owned();
+            name.push(format!("({})", n));
+            if let Some(ext) = path.extension() {
+                name.push(".");
+                name.push(ext);
+            }
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+        .expect("some suffix is always free")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rename_new, Clobber, Conflicts};
+    use assert_matches::assert_matches;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn resolve_conflicts() {
+        assert_eq!("Skip".parse::<Clobber>().unwrap(), Clobber::Skip);
+        assert_matches!("replace".parse::<Clobber>(), Err(_));
+
+        let dir = tempfile::tempdir().unwrap();
+        let fresh = dir.path().join("fresh");
+        let existing = dir.path().join("existing.tar");
+        std::fs::write(&existing, b"data").unwrap();
+        std::fs::write(dir.path().join("existing(1).tar"), b"data").unwrap();
+
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                for mode in Clobber::ALL {
+                    let conflicts = Conflicts::new(mode);
+                    assert_eq!(conflicts.resolve(&fresh).await.unwrap(), None);
+                }
+                let existing = &existing;
+                let resolve =
+                    |mode| async move { Conflicts::new(mode).resolve(existing).await.unwrap() };
+                assert_eq!(
+                    resolve(Clobber::Overwrite).await,
+                    Some((Clobber::Overwrite, existing.clone()))
+                );
+                assert_eq!(
+                    resolve(Clobber::Skip).await,
+                    Some((Clobber::Skip, existing.clone()))
+                );
+                assert_eq!(
+                    resolve(Clobber::Rename).await,
+                    Some((Clobber::Rename, dir.path().join("existing(2).tar")))
+                );
+                // Existing file is never replaced by renaming
+                let moved = dir.path().join("moved");
+                std::fs::write(&fresh, b"fresh").unwrap();
+                assert_eq!(
+                    rename_new(&fresh, existing).await.unwrap_err().kind(),
+                    std::io::ErrorKind::AlreadyExists
+                );
+                rename_new(&fresh, &moved).await.unwrap();
+                assert!(!fresh.exists());
+                assert_eq!(std::fs::read(existing).unwrap(), b"data");
+                assert_eq!(std::fs::read(&moved).unwrap(), b"fresh");
+                // File renamed after download is subject to the same policy
+                std::fs::write(&fresh, b"fresh").unwrap();
+                assert_eq!(
+                    Conflicts::new(Clobber::Skip)
+                        .rename(&fresh, existing)
+                        .await
+                        .unwrap(),
+                    (existing.clone(), Some(Clobber::Skip))
+                );
+                assert!(!fresh.exists());
+                std::fs::write(&fresh, b"fresh").unwrap();
+                assert_eq!(
+                    Conflicts::new(Clobber::Rename)
+                        .rename(&fresh, existing)
+                        .await
+                        .unwrap(),
+                    (dir.path().join("existing(2).tar"), Some(Clobber::Rename))
+                );
+            });
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Source of current time for rate limiting and throughput measurement
+///
+/// Can be replaced to drive time deterministically, i.e. in tests or simulations
+pub trait Clock: Debug + Send + Sync {
+    /// Returns current point in time
+    fn now(&self) -> Instant;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+/// Clock which reports actual system time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock which stands still until it's advanced explicitly
+#[derive(Debug)]
+pub struct ManualClock {
+    /// Point in time when clock was created
+    origin: Instant,
+    /// How far clock was advanced since creation
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Creates clock which starts at current system time
+    pub fn new() -> ManualClock {
+        ManualClock {
+            origin: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+    /// Moves clock forward by specified duration
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.origin + *self.elapsed.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn manual_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let shared: Arc<dyn Clock> = clock.clone();
+        let start = shared.now();
+        assert_eq!(shared.now(), start);
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(shared.now() - start, Duration::from_millis(1500));
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use anyhow::Result;
+use futures::StreamExt;
+use reqwest::{header::CONTENT_LENGTH, Client};
+use tokio::{fs, io::AsyncReadExt};
+
+use crate::digest::Checksum;
+use crate::integrity;
+use crate::resume::hash_prefix;
+
+/// Result of byte-by-byte comparison of remote and local file
+#[derive(Debug, PartialEq, Eq)]
+pub enum Comparison {
+    /// Files are the same, with their length
+    Identical(u64),
+    /// Files differ, first at specified offset;
+    /// if one of them is prefix of another, it's the length of shorter one
+    Differs(u64),
+}
+
+/// Result of comparing remote and local file by metadata only
+#[derive(Debug)]
+pub struct QuickComparison {
+    /// Size of remote file, if server reports it
+    pub remote_size: Option<u64>,
+    /// Size of local file
+    pub local_size: u64,
+    /// Checksum of remote file announced by server, if any
+    pub remote_checksum: Option<Checksum>,
+    /// Whether local file matches announced checksum
+    pub checksum_matches: Option<bool>,
+}
+
+impl QuickComparison {
+    /// Tells whether files are the same, as far as metadata allows
+    ///
+    /// # Returns
+    /// `Some(false)` if sizes or checksums differ, `Some(true)` if checksums match,
+    /// `None` if there's nothing to compare but sizes, and those match
+    pub fn same(&self) -> Option<bool> {
+        if self
+            .remote_size
+            .is_some_and(|remote_size| remote_size != self.local_size)
+        {
+            return Some(false);
+        }
+        self.checksum_matches
+    }
+}
+/// Streams remote file and compares it against local one, byte by byte
+///
+/// Comparison stops at the first difference, so remote file isn't downloaded whole
+/// unless files are the same
+pub async fn compare(client: &Client, url: &str, path: &Path) -> Result<Comparison> {
+    let mut local = fs::File::open(path).await?;
+    let response = client.get(url).send().await?.error_for_status()?;
+    let mut body = response.bytes_stream();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut offset = 0u64;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        let mut remote = &chunk[..];
+        while !remote.is_empty() {
+            let len = remote.len().min(buf.len());
+            let read = local.read(&mut buf[..len]).await?;
+            // Local file is shorter
+            if read == 0 {
+                return Ok(Comparison::Differs(offset));
+            }
+            if let Some(pos) = (0..read).find(|&pos| buf[pos] != remote[pos]) {
+                return Ok(Comparison::Differs(offset + pos as u64));
+            }
+            offset += read as u64;
+            remote = &remote[read..];
+        }
+    }
+    // Remote file is shorter
+    match local.read(&mut buf[..1]).await? {
+        0 => Ok(Comparison::Identical(offset)),
+        _ => Ok(Comparison::Differs(offset)),
+    }
+}
+/// Compares remote and local file by size and checksum, which server reports for HEAD request
+///
+/// Checksum is compared only if server announces it with integrity field like `Content-Digest`
+pub async fn compare_quick(client: &Client, url: &str, path: &Path) -> Result<QuickComparison> {
+    let local_size = fs::metadata(path).await?.len();
+    let response = client.head(url).send().await?.error_for_status()?;
+    let headers = response.headers();
+    // Body of HEAD response is empty, so length is taken from header itself
+    let remote_size = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok());
+    let remote_checksum = integrity::announced(headers);
+    let checksum_matches = match &remote_checksum {
+        Some(checksum) => {
+            let digest = hash_prefix(path, local_size, checksum.algorithm.hasher())
+                .await?
+                .finalize();
+            Some(checksum.verify(&digest).is_ok())
+        }
+        None => None,
+    };
+    Ok(QuickComparison {
+        remote_size,
+        local_size,
+        remote_checksum,
+        checksum_matches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, compare_quick, Comparison};
+    use crate::test_utils::{spawn_server, write_random_file};
+    use reqwest::Client;
+    use std::fs;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn compare_files() {
+        let src_dir = tempfile::tempdir().unwrap();
+        let data = write_random_file(&src_dir.path().join("sample"), 100_000);
+        let local_dir = tempfile::tempdir().unwrap();
+        let local = |name: &str, data: &[u8]| {
+            let path = local_dir.path().join(name);
+            fs::write(&path, data).unwrap();
+            path
+        };
+        let same = local("same", &data);
+        let mut corrupted = data.clone();
+        corrupted[70_000] ^= 1;
+        let corrupted = local("corrupted", &corrupted);
+        let shorter = local("shorter", &data[..5_000]);
+        let longer = local("longer", &[&data[..], b"tail"].concat());
+        let src_path = src_dir.path().to_owned();
+
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let (port, tx, jh) = spawn_server(src_path);
+                let url = format!("http://127.0.0.1:{}/files/sample", port);
+                let client = Client::new();
+
+                for (path, expected) in [
+                    (&same, Comparison::Identical(100_000)),
+                    (&corrupted, Comparison::Differs(70_000)),
+                    (&shorter, Comparison::Differs(5_000)),
+                    (&longer, Comparison::Differs(100_000)),
+                ] {
+                    assert_eq!(compare(&client, &url, path).await.unwrap(), expected);
+                }
+                // Without announced checksum, only sizes can be compared
+                let quick = compare_quick(&client, &url, &same).await.unwrap();
+                assert_eq!(quick.remote_size, Some(100_000));
+                assert_eq!(quick.same(), None);
+                let quick = compare_quick(&client, &url, &longer).await.unwrap();
+                assert_eq!(quick.same(), Some(false));
+
+                tx.send(()).unwrap();
+                jh.await.unwrap();
+            });
+    }
+}
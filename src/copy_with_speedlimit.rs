@@ -1,23 +1,127 @@
 use std::io::{ErrorKind, Result};
+use std::time::Duration;
 
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::task::yield_now;
 /// Size of buffer in bytes, used by asynchronous copy
 /// Public to whole crate because of use in tests for main download function
 pub(crate) const BUFFER_SIZE: usize = 8 * 1_024;
+/// Size of file above which it's copied in large chunks, by default
+const CHUNK_THRESHOLD: u64 = 1_024 * 1_024;
+/// Speed limiter consulted by `copy_with_speedlimit`
+///
+/// Implemented for plain functions which only grant amounts
+pub trait SpeedLimit {
+    /// Takes up to specified amount of bytes for copying
+    ///
+    /// # Returns
+    /// Number of bytes which can be copied right now
+    fn take(&self, amount: usize) -> usize;
+    /// How long copying should pause when nothing was granted, so it doesn't spin
+    ///
+    /// # Arguments
+    /// * amount - number of bytes copying waits for
+    ///
+    /// # Returns
+    /// Time until limiter refills; zero means it's unknown, copying just yields to other tasks
+    fn wait(&self, _amount: usize) -> Duration {
+        Duration::ZERO
+    }
+    /// Accounts bytes actually copied, which may be fewer than granted
+    fn consumed(&self, _amount: usize) {}
+    /// How many bytes copying asks for at once; smaller chunks let limiter react sooner,
+    /// bigger ones cost less per byte
+    fn chunk_size(&self) -> usize {
+        BUFFER_SIZE
+    }
+}
+
+impl<F: Fn(usize) -> usize> SpeedLimit for F {
+    fn take(&self, amount: usize) -> usize {
+        self(amount)
+    }
+}
+
+/// Sizes of copy chunks by size class of file, so limiter stays responsive for small files
+/// while large ones avoid per-chunk overhead
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkSizes {
+    /// Files smaller than this many bytes are copied in small chunks
+    pub threshold: u64,
+    /// Chunk size for small files
+    pub small: usize,
+    /// Chunk size for other files, including ones of unknown size
+    pub large: usize,
+}
+
+impl ChunkSizes {
+    /// Picks chunk size for file of specified length, if it's known
+    pub fn for_length(&self, length: Option<u64>) -> usize {
+        match length {
+            Some(length) if length < self.threshold => self.small,
+            _ => self.large,
+        }
+    }
+}
+
+impl Default for ChunkSizes {
+    /// Files of all sizes are copied in chunks of the same size
+    fn default() -> ChunkSizes {
+        ChunkSizes {
+            threshold: CHUNK_THRESHOLD,
+            small: BUFFER_SIZE,
+            large: BUFFER_SIZE,
+        }
+    }
+}
+
+/// Speed limiter which copies in chunks of specified size, and otherwise defers to wrapped one
+pub struct Chunked<'a, L: ?Sized> {
+    limiter: &'a L,
+    size: usize,
+}
+
+impl<'a, L: SpeedLimit + ?Sized> Chunked<'a, L> {
+    /// Wraps limiter; chunk size is at least a byte
+    pub fn new(limiter: &'a L, size: usize) -> Chunked<'a, L> {
+        Chunked {
+            limiter,
+            size: size.max(1),
+        }
+    }
+}
+
+impl<L: SpeedLimit + ?Sized> SpeedLimit for Chunked<'_, L> {
+    fn take(&self, amount: usize) -> usize {
+        self.limiter.take(amount)
+    }
+
+    fn wait(&self, amount: usize) -> Duration {
+        self.limiter.wait(amount)
+    }
+
+    fn consumed(&self, amount: usize) {
+        self.limiter.consumed(amount)
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.size
+    }
+}
 /// Performs asynchronous copying from one byte stream into another, with respect to specified speed limiter
 ///
 /// # Arguments
 /// * reader  - source asynchronous reader
 /// * writer  - destination asynchronous writer
 /// * limiter - speed limiter func, specifies how many bytes
-///             can be read and then written on each iteration of copying
+///   can be read and then written on each iteration of copying
 ///
 /// Reads data from reader and writes into writer in a loop,
 /// until reader returns 0, or any error occurs.
-/// On each iteration, limiter func is supplied with buffer size,
+/// On each iteration, limiter func is supplied with buffer size, which limiter chooses,
 /// then minimum of buffer size and its return value is used
-/// as actual buffer size, then copy operation is performed on that buffer slice
+/// as actual buffer size, then copy operation is performed on that buffer slice.
+/// When limiter grants nothing, copying sleeps as long as limiter tells
 pub async fn copy_with_speedlimit<R, W, L>(
     reader: &mut R,
     writer: &mut W,
@@ -26,14 +130,17 @@ pub async fn copy_with_speedlimit<R, W, L>(
 where
     R: AsyncRead + Unpin + ?Sized,
     W: AsyncWrite + Unpin + ?Sized,
-    L: Fn(usize) -> usize,
+    L: SpeedLimit + ?Sized,
 {
-    let mut buf = [0u8; BUFFER_SIZE];
+    let mut buf = vec![0u8; limiter.chunk_size().max(1)];
     let mut written = 0u64;
     loop {
-        let limit = limiter(buf.len()).min(buf.len());
+        let limit = limiter.take(buf.len()).min(buf.len());
         if limit == 0 {
-            yield_now().await;
+            match limiter.wait(buf.len()) {
+                wait if wait.is_zero() => yield_now().await,
+                wait => tokio::time::sleep(wait).await,
+            }
             continue;
         }
         let part = &mut buf[..limit];
@@ -44,6 +151,7 @@ where
             Err(e) => Err(e)?,
         };
         writer.write_all(&part[..len]).await?;
+        limiter.consumed(len);
         written += len as u64;
     }
 }
@@ -51,7 +159,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::copy_with_speedlimit;
-    use super::BUFFER_SIZE;
+    use super::{ChunkSizes, Chunked, BUFFER_SIZE};
     use assert_matches::assert_matches;
     use rand::{thread_rng, Rng, RngCore};
     use tokio_test::{block_on, io};
@@ -102,4 +210,43 @@ mod tests {
             }
         });
     }
+
+    #[test]
+    fn chunk_sizes() {
+        let sizes = ChunkSizes {
+            threshold: 1_024 * 1_024,
+            small: 16 * 1_024,
+            large: 256 * 1_024,
+        };
+        assert_eq!(sizes.for_length(Some(1_000)), 16 * 1_024);
+        assert_eq!(sizes.for_length(Some(1_024 * 1_024)), 256 * 1_024);
+        assert_eq!(sizes.for_length(None), 256 * 1_024);
+        assert_eq!(ChunkSizes::default().for_length(Some(1)), BUFFER_SIZE);
+
+        // Limiter is asked for chunks of chosen size
+        let sample = [7u8; 100];
+        let asked = std::sync::Mutex::new(Vec::new());
+        let recording = |amount| {
+            asked.lock().unwrap().push(amount);
+            amount
+        };
+        block_on(async {
+            let mut reader = io::Builder::new()
+                .read(&sample[..50])
+                .read(&sample[50..])
+                .build();
+            let mut writer = io::Builder::new()
+                .write(&sample[..30])
+                .write(&sample[30..50])
+                .write(&sample[50..80])
+                .write(&sample[80..])
+                .build();
+            let limiter = Chunked::new(&recording, 30);
+            assert_matches!(
+                copy_with_speedlimit(&mut reader, &mut writer, &limiter).await,
+                Ok(100)
+            );
+        });
+        assert!(asked.lock().unwrap().iter().all(|&amount| amount == 30));
+    }
 }
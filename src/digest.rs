@@ -0,0 +1,285 @@
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use anyhow::{bail, Result};
+use tokio::io::AsyncWrite;
+
+/// Incremental hash function, used to verify downloaded data
+///
+/// Abstracts over concrete hashing implementations,
+/// so adding new algorithm only requires implementing this trait
+/// and registering it in `Algorithm`
+pub trait Digest: Send {
+    /// Feeds next chunk of data into hash function
+    fn update(&mut self, data: &[u8]);
+    /// Consumes hasher and returns resulting digest bytes
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
+// All RustCrypto hashers share the same trait, so implement ours for them in one go
+macro_rules! impl_digest {
+    ($($hasher:ty),*) => {
+        $(
+            impl Digest for $hasher {
+                fn update(&mut self, data: &[u8]) {
+                    sha2::Digest::update(self, data)
+                }
+
+                fn finalize(self: Box<Self>) -> Vec<u8> {
+                    sha2::Digest::finalize(*self).to_vec()
+                }
+            }
+        )*
+    };
+}
+
+impl_digest!(md5::Md5, sha1::Sha1, sha2::Sha256, sha2::Sha512);
+
+impl Digest for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        blake3::Hasher::finalize(&self).as_bytes().to_vec()
+    }
+}
+
+/// Supported hash algorithms
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Algorithm {
+    /// All known algorithms
+    pub const ALL: [Algorithm; 5] = [
+        Algorithm::Md5,
+        Algorithm::Sha1,
+        Algorithm::Sha256,
+        Algorithm::Sha512,
+        Algorithm::Blake3,
+    ];
+    /// Name of algorithm, as used in list file and CLI
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "md5",
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+    /// Length of produced digest, in bytes
+    pub fn digest_len(self) -> usize {
+        match self {
+            Algorithm::Md5 => 16,
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha512 => 64,
+            Algorithm::Blake3 => 32,
+        }
+    }
+    /// Creates new hasher instance for this algorithm
+    pub fn hasher(self) -> Box<dyn Digest> {
+        match self {
+            Algorithm::Md5 => Box::new(<md5::Md5 as sha2::Digest>::new()),
+            Algorithm::Sha1 => Box::new(<sha1::Sha1 as sha2::Digest>::new()),
+            Algorithm::Sha256 => Box::new(<sha2::Sha256 as sha2::Digest>::new()),
+            Algorithm::Sha512 => Box::new(<sha2::Sha512 as sha2::Digest>::new()),
+            Algorithm::Blake3 => Box::new(blake3::Hasher::new()),
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Algorithm> {
+        match Algorithm::ALL
+            .into_iter()
+            .find(|algo| algo.name().eq_ignore_ascii_case(s))
+        {
+            Some(algo) => Ok(algo),
+            None => bail!("{}: unknown checksum algorithm", s),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Expected checksum of some file, along with algorithm used to compute it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub value: Vec<u8>,
+}
+
+impl Checksum {
+    /// Parses hex-encoded digest and checks that its length matches algorithm
+    pub fn parse(algorithm: Algorithm, hex_str: &str) -> Result<Checksum> {
+        let value = hex::decode(hex_str)?;
+        if value.len() != algorithm.digest_len() {
+            bail!(
+                "{}: expected {} digest of {} bytes, got {}",
+                hex_str,
+                algorithm,
+                algorithm.digest_len(),
+                value.len()
+            );
+        }
+        Ok(Checksum { algorithm, value })
+    }
+    /// Compares computed digest against expected one
+    ///
+    /// # Returns
+    /// `Mismatch` error if digests differ
+    pub fn verify(&self, actual: &[u8]) -> Result<()> {
+        if actual != self.value.as_slice() {
+            return Err(Mismatch {
+                expected: self.clone(),
+                actual: actual.to_vec(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Error of downloaded data not matching expected checksum, i.e. corrupted copy
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    /// Checksum data should have
+    pub expected: Checksum,
+    /// Digest data actually has
+    pub actual: Vec<u8>,
+}
+
+impl Mismatch {
+    /// Checks whether error, or any of its causes, is checksum mismatch
+    pub fn is_cause_of(err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| cause.is::<Mismatch>())
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} checksum mismatch: expected {}, got {}",
+            self.expected.algorithm,
+            hex::encode(&self.expected.value),
+            hex::encode(&self.actual)
+        )
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+impl fmt::Display for Checksum {
+    /// Formats checksum the same way it's written in list file, i.e. `sha256=<hex>`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.algorithm, hex::encode(&self.value))
+    }
+}
+
+/// Writer adapter which feeds all successfully written data into hasher
+pub struct DigestWriter<'a, W: ?Sized> {
+    inner: &'a mut W,
+    digest: Box<dyn Digest>,
+}
+
+impl<'a, W: AsyncWrite + Unpin + ?Sized> DigestWriter<'a, W> {
+    pub fn new(inner: &'a mut W, digest: Box<dyn Digest>) -> DigestWriter<'a, W> {
+        DigestWriter { inner, digest }
+    }
+    /// Returns digest of all data written so far
+    pub fn finalize(self) -> Vec<u8> {
+        self.digest.finalize()
+    }
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> AsyncWrite for DigestWriter<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut *this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(len)) = poll {
+            this.digest.update(&buf[..len]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Algorithm, Checksum, Mismatch};
+    use assert_matches::assert_matches;
+
+    // Digests of "abc" string, from respective algorithm specifications
+    const ABC_DIGESTS: [(Algorithm, &str); 5] = [
+        (Algorithm::Md5, "900150983cd24fb0d6963f7d28e17f72"),
+        (Algorithm::Sha1, "a9993e364706816aba3e25717850c26c9cd0d89d"),
+        (
+            Algorithm::Sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ),
+        (
+            Algorithm::Sha512,
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+        ),
+        (
+            Algorithm::Blake3,
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+        ),
+    ];
+
+    #[test]
+    fn known_digests() {
+        for (algo, hex_str) in ABC_DIGESTS {
+            let mut hasher = algo.hasher();
+            hasher.update(b"a");
+            hasher.update(b"bc");
+            let checksum = Checksum::parse(algo, hex_str).unwrap();
+            assert_matches!(checksum.verify(&hasher.finalize()), Ok(()));
+            let err = checksum.verify(b"abc").unwrap_err().context("corrupted");
+            assert!(Mismatch::is_cause_of(&err));
+        }
+    }
+
+    #[test]
+    fn parse_failures() {
+        assert_matches!("sha3".parse::<Algorithm>(), Err(_));
+        assert_matches!("SHA256".parse::<Algorithm>(), Ok(Algorithm::Sha256));
+        // Not a hex string
+        assert_matches!(Checksum::parse(Algorithm::Md5, "xyz"), Err(_));
+        // Digest length doesn't match algorithm
+        assert_matches!(
+            Checksum::parse(Algorithm::Sha256, "900150983cd24fb0d6963f7d28e17f72"),
+            Err(_)
+        );
+    }
+}
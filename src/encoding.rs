@@ -0,0 +1,102 @@
+use std::pin::Pin;
+
+use anyhow::{bail, Result};
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZstdDecoder};
+use reqwest::header::HeaderValue;
+use tokio::io::{AsyncBufRead, AsyncRead};
+
+/// Value of `Accept-Encoding` header of requests for compressed transfer, most efficient first
+pub const ACCEPTED: &str = "zstd, br, gzip";
+
+/// Compression server applied to response body for transfer, per `Content-Encoding` header;
+/// received data is decoded before it's written, so files land on disk as they're stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentEncoding {
+    /// `gzip`, or its legacy alias `x-gzip`
+    Gzip,
+    /// `br`
+    Brotli,
+    /// `zstd`
+    Zstd,
+}
+
+impl ContentEncoding {
+    /// Parses `Content-Encoding` header of response
+    ///
+    /// # Returns
+    /// Returns encoding of body, or `None` if body isn't encoded
+    pub fn parse(header: Option<&HeaderValue>) -> Result<Option<ContentEncoding>> {
+        let Some(header) = header else {
+            return Ok(None);
+        };
+        let value = header.to_str().unwrap_or_default().trim();
+        Ok(Some(match value.to_ascii_lowercase().as_str() {
+            "" | "identity" => return Ok(None),
+            "gzip" | "x-gzip" => ContentEncoding::Gzip,
+            "br" => ContentEncoding::Brotli,
+            "zstd" => ContentEncoding::Zstd,
+            _ => bail!("{}: unsupported content encoding", value),
+        }))
+    }
+    /// Wraps reader of encoded body, so decoded data is read from returned one
+    pub fn decode<'a>(
+        self,
+        body: impl AsyncBufRead + Send + 'a,
+    ) -> Pin<Box<dyn AsyncRead + Send + 'a>> {
+        match self {
+            ContentEncoding::Gzip => Box::pin(GzipDecoder::new(body)),
+            ContentEncoding::Brotli => Box::pin(BrotliDecoder::new(body)),
+            ContentEncoding::Zstd => Box::pin(ZstdDecoder::new(body)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContentEncoding;
+    use assert_matches::assert_matches;
+    use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZstdEncoder};
+    use reqwest::header::HeaderValue;
+    use tokio::io::{AsyncRead, AsyncReadExt};
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn decode_bodies() {
+        let parse = |value| ContentEncoding::parse(Some(&HeaderValue::from_static(value)));
+        assert_matches!(ContentEncoding::parse(None), Ok(None));
+        assert_matches!(parse("identity"), Ok(None));
+        assert_matches!(parse("ZSTD"), Ok(Some(ContentEncoding::Zstd)));
+        assert_matches!(parse("x-gzip"), Ok(Some(ContentEncoding::Gzip)));
+        assert_matches!(parse("compress"), Err(_));
+
+        let data = b"compressible ".repeat(1000);
+        Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                async fn read_all(mut reader: impl AsyncRead + Unpin) -> Vec<u8> {
+                    let mut buf = Vec::new();
+                    reader.read_to_end(&mut buf).await.unwrap();
+                    buf
+                }
+                let encoded = [
+                    (
+                        ContentEncoding::Gzip,
+                        read_all(GzipEncoder::new(&data[..])).await,
+                    ),
+                    (
+                        ContentEncoding::Brotli,
+                        read_all(BrotliEncoder::new(&data[..])).await,
+                    ),
+                    (
+                        ContentEncoding::Zstd,
+                        read_all(ZstdEncoder::new(&data[..])).await,
+                    ),
+                ];
+                for (encoding, body) in encoded {
+                    assert!(body.len() < data.len());
+                    assert_eq!(read_all(encoding.decode(&body[..])).await, data);
+                }
+            });
+    }
+}
@@ -0,0 +1,301 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use anyhow::{bail, Context as _, Result};
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use crate::transform::{BoxWriter, Transform};
+
+/// Marks files encrypted by httpdl, and version of format
+const MAGIC: &[u8; 8] = b"httpdlE1";
+/// Length of random nonce prefix stored in file header
+const PREFIX_LEN: usize = 7;
+/// Size of plaintext chunk, each is encrypted and authenticated separately
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Size of authentication tag appended to each encrypted chunk
+const TAG_LEN: usize = 16;
+
+/// AES-256 key used to encrypt downloaded files
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    /// Parses key from 64 hex digits
+    pub fn from_hex(s: &str) -> Result<Key> {
+        let bytes = hex::decode(s.trim()).context("key must be hex-encoded")?;
+        match bytes.try_into() {
+            Ok(bytes) => Ok(Key(bytes)),
+            Err(_) => bail!("key must be 32 bytes long"),
+        }
+    }
+}
+// Key material is never printed
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// Transform which encrypts files on the fly with AES-256-GCM, so they're stored encrypted
+///
+/// Data is split into 64 KiB chunks, each sealed separately, so file is never held
+/// in memory whole and truncation or reordering of chunks is detected on decryption.
+/// Files are read back with `httpdl decrypt`, or `decrypt`; their layout is:
+///
+/// * magic `httpdlE1`, 8 bytes, which also marks version of format
+/// * random nonce prefix, 7 bytes
+/// * sealed chunks: 64 KiB of ciphertext, or less for the last one, which may be empty,
+///   each followed by 16-byte authentication tag
+///
+/// Nonce of chunk is 12 bytes: nonce prefix, chunk counter starting at 0 as 4-byte
+/// big-endian number, then last-chunk flag byte, 1 for the last chunk and 0 for others.
+/// Chunks have no associated data, so any AES-256-GCM implementation can open them
+#[derive(Debug)]
+pub struct Encrypt {
+    key: Key,
+}
+
+impl Encrypt {
+    pub fn new(key: Key) -> Encrypt {
+        Encrypt { key }
+    }
+}
+
+impl Transform for Encrypt {
+    fn wrap<'a>(&self, inner: BoxWriter<'a>) -> BoxWriter<'a> {
+        let mut prefix = [0u8; PREFIX_LEN];
+        openssl::rand::rand_bytes(&mut prefix).expect("system random generator failed");
+        let mut pending = MAGIC.to_vec();
+        pending.extend_from_slice(&prefix);
+        Box::pin(EncryptWriter {
+            inner,
+            key: self.key.clone(),
+            prefix,
+            counter: 0,
+            plain: Vec::with_capacity(CHUNK_SIZE),
+            pending,
+            sealed: false,
+        })
+    }
+}
+
+/// Writer which encrypts data in chunks and passes them into inner writer
+struct EncryptWriter<'a> {
+    inner: BoxWriter<'a>,
+    key: Key,
+    prefix: [u8; PREFIX_LEN],
+    /// Number of chunks encrypted so far
+    counter: u32,
+    /// Plaintext of current chunk
+    plain: Vec<u8>,
+    /// Encrypted data not yet written into inner writer
+    pending: Vec<u8>,
+    /// Whether last chunk was encrypted
+    sealed: bool,
+}
+
+impl EncryptWriter<'_> {
+    /// Encrypts current chunk, appending it to pending data
+    fn seal(&mut self, last: bool) -> io::Result<()> {
+        let nonce = nonce(&self.prefix, self.counter, last);
+        let mut tag = [0u8; TAG_LEN];
+        let sealed = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key.0,
+            Some(&nonce),
+            &[],
+            &self.plain,
+            &mut tag,
+        )
+        .map_err(io::Error::other)?;
+        self.pending.extend_from_slice(&sealed);
+        self.pending.extend_from_slice(&tag);
+        self.plain.clear();
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("file is too big to encrypt"))?;
+        Ok(())
+    }
+    /// Writes all pending data into inner writer
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let len = ready!(self.inner.as_mut().poll_write(cx, &self.pending))?;
+            if len == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.drain(..len);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl tokio::io::AsyncWrite for EncryptWriter<'_> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_drain(cx))?;
+        // Full chunk is sealed only once more data arrives, since the last one is marked
+        if this.plain.len() == CHUNK_SIZE {
+            this.seal(false)?;
+            ready!(this.poll_drain(cx))?;
+        }
+        let len = buf.len().min(CHUNK_SIZE - this.plain.len());
+        this.plain.extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_drain(cx))?;
+        self.inner.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.sealed {
+            ready!(self.poll_drain(cx))?;
+            self.seal(true)?;
+            self.sealed = true;
+        }
+        ready!(self.poll_drain(cx))?;
+        self.inner.as_mut().poll_shutdown(cx)
+    }
+}
+/// Builds nonce of chunk
+fn nonce(prefix: &[u8; PREFIX_LEN], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..PREFIX_LEN].copy_from_slice(prefix);
+    nonce[PREFIX_LEN..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+/// Decrypts data encrypted by `Encrypt`, chunk by chunk, so it's never held in memory whole
+///
+/// Each chunk is authenticated before it's written, but damage of later chunks is found
+/// only once they're reached, so output must be discarded if decryption fails
+///
+/// # Arguments
+/// * key - key data was encrypted with
+/// * source - encrypted data
+/// * output - where original data is written
+///
+/// # Returns
+/// Number of bytes of original data, or error if data is damaged, truncated or key is wrong
+pub fn decrypt(key: &Key, mut source: impl Read, mut output: impl Write) -> Result<u64> {
+    let mut header = [0u8; MAGIC.len() + PREFIX_LEN];
+    if read_full(&mut source, &mut header)? < header.len() {
+        bail!("not a file encrypted by httpdl, or it's truncated");
+    }
+    let (magic, prefix) = header.split_at(MAGIC.len());
+    if magic != MAGIC {
+        bail!("not a file encrypted by httpdl");
+    }
+    let prefix = prefix.try_into().expect("prefix has fixed length");
+    // Chunk is known to be the last one only once the next one turns out to be empty
+    let mut chunk = vec![0u8; CHUNK_SIZE + TAG_LEN];
+    let mut next = vec![0u8; CHUNK_SIZE + TAG_LEN];
+    let mut len = read_full(&mut source, &mut chunk)?;
+    let (mut counter, mut written) = (0u32, 0u64);
+    loop {
+        let next_len = match len == chunk.len() {
+            true => read_full(&mut source, &mut next)?,
+            false => 0,
+        };
+        let last = next_len == 0;
+        if len < TAG_LEN {
+            bail!("encrypted file is truncated");
+        }
+        let (sealed, tag) = chunk[..len].split_at(len - TAG_LEN);
+        let plain = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key.0,
+            Some(&nonce(prefix, counter, last)),
+            &[],
+            sealed,
+            tag,
+        )
+        .context("encrypted file is damaged or key is wrong")?;
+        output.write_all(&plain)?;
+        written += plain.len() as u64;
+        if last {
+            output.flush()?;
+            return Ok(written);
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+        counter += 1;
+    }
+}
+/// Reads into buffer until it's full or source ends
+///
+/// # Returns
+/// Number of bytes read, less than buffer length only at end of source
+fn read_full(source: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match source.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(len) => filled += len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encrypt, Key, CHUNK_SIZE};
+    use crate::transform::Transform;
+    use tokio::io::AsyncWriteExt;
+    use tokio_test::block_on;
+
+    fn encrypt(key: &Key, data: &[u8]) -> Vec<u8> {
+        let mut encrypted = Vec::new();
+        block_on(async {
+            let mut writer = Encrypt::new(key.clone()).wrap(Box::pin(&mut encrypted));
+            // Odd-sized writes cross chunk boundaries
+            for part in data.chunks(1000) {
+                writer.write_all(part).await.unwrap();
+            }
+            writer.shutdown().await.unwrap();
+        });
+        encrypted
+    }
+
+    fn decrypt(key: &Key, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut plain = Vec::new();
+        let len = super::decrypt(key, data, &mut plain)?;
+        assert_eq!(len, plain.len() as u64);
+        Ok(plain)
+    }
+
+    #[test]
+    fn encrypt_roundtrip() {
+        let key = Key::from_hex(&"ab".repeat(32)).unwrap();
+        for len in [0, 10, CHUNK_SIZE, CHUNK_SIZE * 2 + 5] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let encrypted = encrypt(&key, &data);
+            assert_ne!(&encrypted[15..], &data[..]);
+            assert_eq!(decrypt(&key, &encrypted).unwrap(), data);
+        }
+        let data = vec![7u8; CHUNK_SIZE + 1];
+        let encrypted = encrypt(&key, &data);
+        // Wrong key, damage and truncation at chunk boundary are detected
+        let other = Key::from_hex(&"cd".repeat(32)).unwrap();
+        assert!(decrypt(&other, &encrypted).is_err());
+        let mut damaged = encrypted.clone();
+        damaged[20] ^= 1;
+        assert!(decrypt(&key, &damaged).is_err());
+        assert!(decrypt(&key, &encrypted[..15 + CHUNK_SIZE + 16]).is_err());
+        assert!(decrypt(&key, &encrypted[..10]).is_err());
+        assert!(decrypt(&key, b"not encrypted at all").is_err());
+
+        assert!(Key::from_hex("abcd").is_err());
+        assert!(Key::from_hex("xyz").is_err());
+    }
+}
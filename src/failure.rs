@@ -0,0 +1,166 @@
+use std::fmt;
+use std::io;
+
+use crate::preflight::NoSpace;
+use crate::retry::Busy;
+
+/// Stage at which download job failed, to tell network problems from server ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FailureKind {
+    /// Host name couldn't be resolved
+    Dns,
+    /// TCP connection couldn't be established
+    Connect,
+    /// TLS handshake failed, i.e. due to invalid certificate
+    Tls,
+    /// Server responded with error status
+    Status(u16),
+    /// Connection broke while response body was received
+    Body,
+    /// Destination filesystem ran out of free space, or wouldn't have enough of it
+    NoSpace,
+    /// Destination couldn't be written for lack of permissions, or because it's read-only
+    Permission,
+    /// Anything else, like mismatched checksum or local file system error
+    Other,
+}
+
+impl FailureKind {
+    /// Classifies error which failed download job
+    pub fn classify(err: &anyhow::Error) -> FailureKind {
+        for cause in err.chain() {
+            if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+                return classify_request(err);
+            }
+            if cause.is::<NoSpace>() {
+                return FailureKind::NoSpace;
+            }
+            if let Some(busy) = cause.downcast_ref::<Busy>() {
+                return FailureKind::Status(busy.status);
+            }
+            if let Some(err) = cause.downcast_ref::<io::Error>() {
+                match err.kind() {
+                    io::ErrorKind::StorageFull => return FailureKind::NoSpace,
+                    io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+                        return FailureKind::Permission
+                    }
+                    _ => {}
+                }
+                // Response body errors are wrapped into I/O ones while streaming
+                if let Some(err) = err.get_ref().and_then(|err| err.downcast_ref()) {
+                    return classify_request(err);
+                }
+            }
+        }
+        FailureKind::Other
+    }
+}
+/// Classifies HTTP client error
+fn classify_request(err: &reqwest::Error) -> FailureKind {
+    if let Some(status) = err.status() {
+        return FailureKind::Status(status.as_u16());
+    }
+    if err.is_body() || err.is_decode() {
+        return FailureKind::Body;
+    }
+    if !err.is_connect() {
+        return FailureKind::Other;
+    }
+    // Underlying connector errors aren't exposed as types, only their messages
+    let mut source = std::error::Error::source(err);
+    while let Some(cause) = source {
+        let message = cause.to_string().to_ascii_lowercase();
+        if message.contains("dns error") || message.contains("lookup address") {
+            return FailureKind::Dns;
+        }
+        if ["tls", "ssl", "certificate", "handshake"]
+            .iter()
+            .any(|word| message.contains(word))
+        {
+            return FailureKind::Tls;
+        }
+        source = cause.source();
+    }
+    FailureKind::Connect
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureKind::Dns => f.write_str("dns"),
+            FailureKind::Connect => f.write_str("connect"),
+            FailureKind::Tls => f.write_str("tls"),
+            FailureKind::Status(status) => write!(f, "http {}", status),
+            FailureKind::Body => f.write_str("body"),
+            FailureKind::NoSpace => f.write_str("no space"),
+            FailureKind::Permission => f.write_str("permission"),
+            FailureKind::Other => f.write_str("other"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FailureKind;
+    use crate::preflight::check_space;
+    use crate::retry::Busy;
+    use anyhow::anyhow;
+    use std::io;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn classify_failures() {
+        assert_eq!(
+            FailureKind::classify(&anyhow!("checksum mismatch")),
+            FailureKind::Other
+        );
+        let full = io::Error::from(io::ErrorKind::StorageFull);
+        assert_eq!(
+            FailureKind::classify(&anyhow::Error::from(full).context("write")),
+            FailureKind::NoSpace
+        );
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            FailureKind::classify(&anyhow::Error::from(denied).context("create directory")),
+            FailureKind::Permission
+        );
+        let busy = Busy {
+            status: 429,
+            retry_after: std::time::Duration::from_secs(1),
+        };
+        assert_eq!(
+            FailureKind::classify(&anyhow::Error::from(busy)),
+            FailureKind::Status(429)
+        );
+        #[cfg(unix)]
+        {
+            let no_space = check_space(std::path::Path::new("."), u64::MAX).unwrap_err();
+            assert_eq!(
+                FailureKind::classify(&no_space.into()),
+                FailureKind::NoSpace
+            );
+        }
+
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let client = reqwest::Client::new();
+                let classify = |result: reqwest::Result<reqwest::Response>| {
+                    FailureKind::classify(
+                        &result
+                            .and_then(|r| r.error_for_status())
+                            .unwrap_err()
+                            .into(),
+                    )
+                };
+                // Nothing listens on port 1
+                let refused = client.get("http://127.0.0.1:1/").send().await;
+                assert_eq!(classify(refused), FailureKind::Connect);
+                // Reserved top-level domain never resolves
+                let unresolved = client.get("http://host.invalid/").send().await;
+                assert_eq!(classify(unresolved), FailureKind::Dns);
+            });
+    }
+}
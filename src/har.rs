@@ -0,0 +1,296 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use reqwest::{
+    header::{
+        HeaderMap, AUTHORIZATION, CONTENT_TYPE, COOKIE, LOCATION, PROXY_AUTHORIZATION, SET_COOKIE,
+    },
+    Request, Response, Url,
+};
+use serde_json::{json, Value};
+
+use crate::timestamp::format_timestamp;
+
+/// Query parameters which carry credentials, like signatures of presigned URLs; lowercase
+const SECRET_PARAMS: [&str; 12] = [
+    "x-amz-signature",
+    "x-amz-credential",
+    "x-amz-security-token",
+    "x-goog-signature",
+    "x-goog-credential",
+    "signature",
+    "sig",
+    "token",
+    "access_token",
+    "api_key",
+    "apikey",
+    "password",
+];
+
+/// Log of HTTP exchanges, written in HTTP Archive (HAR) 1.2 format for debugging
+///
+/// Every request which got response is recorded, including each hop of redirect chain.
+/// Response bodies are streamed into files and aren't part of log, and credentials
+/// are redacted, so log can be shared
+#[derive(Debug, Default)]
+pub struct Har {
+    entries: Mutex<Vec<Value>>,
+    /// Time every exchange is logged as started at, with no timings, if log must be reproducible
+    fixed_time: Option<SystemTime>,
+}
+
+impl Har {
+    pub fn new() -> Har {
+        Har::default()
+    }
+    /// Creates log which doesn't depend on when and how fast exchanges happen:
+    /// all of them are logged as started at specified time and taking no time
+    pub fn reproducible(time: SystemTime) -> Har {
+        Har {
+            fixed_time: Some(time),
+            ..Har::default()
+        }
+    }
+    /// Describes request before it's sent, since sending consumes it
+    pub fn describe(request: &Request) -> Value {
+        let url = redact_url(request.url());
+        let query: Vec<_> = url
+            .query_pairs()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+        json!({
+            "method": request.method().as_str(),
+            "url": url.as_str(),
+            "httpVersion": format!("{:?}", request.version()),
+            "cookies": [],
+            "headers": headers(request.headers()),
+            "queryString": query,
+            "headersSize": -1,
+            "bodySize": 0,
+        })
+    }
+    /// Records single exchange
+    ///
+    /// # Arguments
+    /// * request - request description, made by `describe`
+    /// * response - response received for it
+    /// * started - when request was sent
+    /// * wait - time until response headers were received
+    pub fn record(&self, request: Value, response: &Response, started: SystemTime, wait: Duration) {
+        let (started, wait) = match self.fixed_time {
+            Some(time) => (time, 0.0),
+            None => (started, wait.as_secs_f64() * 1000.0),
+        };
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let redirect = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let entry = json!({
+            "startedDateTime": format_timestamp(started),
+            "time": wait,
+            "request": request,
+            "response": {
+                "status": response.status().as_u16(),
+                "statusText": response.status().canonical_reason().unwrap_or_default(),
+                "httpVersion": format!("{:?}", response.version()),
+                "cookies": [],
+                "headers": headers(response.headers()),
+                "content": {
+                    "size": response.content_length().unwrap_or(0),
+                    "mimeType": content_type,
+                },
+                "redirectURL": redirect,
+                "headersSize": -1,
+                "bodySize": response.content_length().map_or(-1, |len| len as i64),
+            },
+            "cache": {},
+            "timings": { "send": 0, "wait": wait, "receive": 0 },
+        });
+        self.entries.lock().unwrap().push(entry);
+    }
+    /// Serializes log as HAR document; reproducible log lists exchanges by URL,
+    /// since concurrent ones happen in different order each run
+    pub fn to_json(&self) -> Value {
+        let mut entries = self.entries.lock().unwrap().clone();
+        if self.fixed_time.is_some() {
+            entries.sort_by(|a, b| {
+                a["request"]["url"]
+                    .as_str()
+                    .cmp(&b["request"]["url"].as_str())
+            });
+        }
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "httpdl", "version": env!("CARGO_PKG_VERSION") },
+                "entries": entries,
+            }
+        })
+    }
+}
+/// Copies URL with credentials redacted: user name and password, and values of query
+/// parameters which carry them
+fn redact_url(url: &Url) -> Url {
+    let mut url = url.clone();
+    if !url.username().is_empty() {
+        let _ = url.set_username("redacted");
+    }
+    if url.password().is_some() {
+        let _ = url.set_password(Some("redacted"));
+    }
+    let secret = |name: &str| SECRET_PARAMS.contains(&name.to_ascii_lowercase().as_str());
+    if url.query_pairs().any(|(name, _)| secret(&name)) {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| match secret(&name) {
+                true => (name.into_owned(), "redacted".to_owned()),
+                false => (name.into_owned(), value.into_owned()),
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url
+}
+/// Lists headers as HAR name-value pairs, with credentials redacted
+fn headers(headers: &HeaderMap) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let secret = *name == AUTHORIZATION
+                || *name == PROXY_AUTHORIZATION
+                || *name == COOKIE
+                || *name == SET_COOKIE
+                || name.as_str() == "x-amz-security-token";
+            let value = match secret {
+                true => "[redacted]",
+                false => value.to_str().unwrap_or_default(),
+            };
+            json!({ "name": name.as_str(), "value": value })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact_url, Har};
+    use crate::redirects::{self, Trail};
+    use reqwest::Client;
+    use tokio::runtime::Builder;
+    use tokio::sync::oneshot::channel;
+    use warp::{http::Response, Filter};
+
+    #[test]
+    fn record_exchanges() {
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let routes = warp::path!("start")
+                    .map(|| {
+                        Response::builder()
+                            .status(302)
+                            .header("location", "/final")
+                            .body("")
+                    })
+                    .or(warp::path!("final").map(|| {
+                        Response::builder()
+                            .header("set-cookie", "session=secret")
+                            .body("data")
+                    }));
+                let (tx, rx) = channel();
+                let (addr, server) =
+                    warp::serve(routes).bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+                        rx.await.ok();
+                    });
+                let jh = tokio::spawn(server);
+                let client = redirects::client(Client::builder());
+                let har = Har::new();
+                let request = client
+                    .get(format!(
+                        "http://127.0.0.1:{}/start?a=1&X-Amz-Signature=secret",
+                        addr.port()
+                    ))
+                    .basic_auth("user", Some("secret"))
+                    .header("cookie", "session=secret");
+                redirects::send(
+                    request,
+                    &Trail::default(),
+                    Some(&har),
+                    redirects::MAX_REDIRECTS,
+                )
+                .await
+                .unwrap();
+
+                // Each hop is logged, without credentials
+                let log = har.to_json();
+                let entries = log["log"]["entries"].as_array().unwrap();
+                assert_eq!(entries.len(), 2);
+                assert_eq!(entries[0]["response"]["status"], 302);
+                assert_eq!(entries[0]["response"]["redirectURL"], "/final");
+                assert_eq!(
+                    entries[0]["request"]["queryString"][0],
+                    serde_json::json!({ "name": "a", "value": "1" })
+                );
+                assert_eq!(
+                    entries[0]["request"]["headers"][0],
+                    serde_json::json!({ "name": "authorization", "value": "[redacted]" })
+                );
+                assert_eq!(entries[1]["response"]["status"], 200);
+                assert_eq!(entries[1]["response"]["bodySize"], 4);
+                // Cookies carry sessions, so are redacted both ways
+                let text = log.to_string();
+                assert!(!text.contains("secret"));
+                assert!(entries[0]["request"]["headers"]
+                    .as_array()
+                    .unwrap()
+                    .contains(&serde_json::json!({ "name": "cookie", "value": "[redacted]" })));
+                assert!(entries[1]["response"]["headers"]
+                    .as_array()
+                    .unwrap()
+                    .contains(&serde_json::json!({ "name": "set-cookie", "value": "[redacted]" })));
+
+                // Reproducible log has fixed times
+                let har = Har::reproducible(std::time::UNIX_EPOCH);
+                let request = client.get(format!("http://127.0.0.1:{}/final", addr.port()));
+                redirects::send(
+                    request,
+                    &Trail::default(),
+                    Some(&har),
+                    redirects::MAX_REDIRECTS,
+                )
+                .await
+                .unwrap();
+                let log = har.to_json();
+                let entry = &log["log"]["entries"][0];
+                assert_eq!(entry["startedDateTime"], "1970-01-01T00:00:00.000Z");
+                assert_eq!(entry["time"], 0.0);
+
+                tx.send(()).unwrap();
+                jh.await.unwrap();
+            });
+    }
+
+    #[test]
+    fn redact_urls() {
+        let redact = |url| redact_url(&url::Url::parse(url).unwrap()).to_string();
+        assert_eq!(redact("http://a/f?x=1"), "http://a/f?x=1");
+        // User info is redacted, both user name and password
+        assert_eq!(
+            redact("https://user:secret@a/f"),
+            "https://redacted:redacted@a/f"
+        );
+        assert_eq!(redact("ftp://token@a/f"), "ftp://redacted@a/f");
+        // Signatures of presigned URLs are redacted, whatever their case, other parameters aren't
+        assert_eq!(
+            redact("https://b/f?X-Amz-Credential=AKIA%2F1&x-amz-signature=ff&part=2"),
+            "https://b/f?X-Amz-Credential=redacted&x-amz-signature=redacted&part=2"
+        );
+    }
+}
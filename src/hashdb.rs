@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use tokio::fs;
+
+use crate::digest::{Algorithm, Checksum};
+
+/// What database knows about single local file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Checksum of file, if it was verified or computed
+    pub checksum: Option<Checksum>,
+    /// File size, in bytes
+    pub size: u64,
+    /// Modification time of file when it was recorded
+    pub modified: SystemTime,
+    /// URL file was downloaded from, if it was
+    pub url: Option<String>,
+}
+
+/// Database of checksums of local files, so runs over the same tree don't rehash it every time
+///
+/// Record of file is trusted only while file has the same size and modification time
+/// as when it was recorded. Records are kept in memory, and written to database file
+/// as JSON object per line by `save`
+#[derive(Debug)]
+pub struct HashDb {
+    /// Database file
+    path: PathBuf,
+    /// Records by file path
+    records: Mutex<HashMap<String, Record>>,
+}
+
+impl HashDb {
+    /// Loads database from file; database which doesn't exist yet is empty
+    pub fn open(path: impl Into<PathBuf>) -> Result<HashDb> {
+        let path = path.into();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let records = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(num, line)| {
+                parse_record(line).with_context(|| {
+                    format!("{} line {}: malformed record", path.display(), num + 1)
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(HashDb {
+            path,
+            records: Mutex::new(records),
+        })
+    }
+    /// Finds record of file, if file didn't change since it was recorded
+    pub async fn lookup(&self, file: &Path) -> Option<Record> {
+        let record = self.records.lock().unwrap().get(&key(file))?.clone();
+        let (size, modified) = stat(file).await?;
+        (size == record.size && modified == record.modified).then_some(record)
+    }
+    /// Finds checksum of file computed with specified algorithm,
+    /// if file didn't change since it was recorded
+    pub async fn checksum(&self, file: &Path, algo: Algorithm) -> Option<Checksum> {
+        self.lookup(file)
+            .await?
+            .checksum
+            .filter(|checksum| checksum.algorithm == algo)
+    }
+    /// Checks whether file was changed, or removed, since it was recorded;
+    /// file without record isn't considered changed
+    pub async fn changed(&self, file: &Path) -> bool {
+        let recorded = self.records.lock().unwrap().contains_key(&key(file));
+        recorded && self.lookup(file).await.is_none()
+    }
+    /// Records current size and modification time of file, along with its checksum
+    ///
+    /// # Arguments
+    /// * checksum - checksum of file as it's now, if it's known
+    /// * url - URL file was downloaded from, if it was
+    pub async fn record(
+        &self,
+        file: &Path,
+        checksum: Option<Checksum>,
+        url: Option<&str>,
+    ) -> Result<()> {
+        let (size, modified) = stat(file)
+            .await
+            .with_context(|| format!("{}: can't read file metadata", file.display()))?;
+        let record = Record {
+            checksum,
+            size,
+            modified,
+            url: url.map(str::to_owned),
+        };
+        self.records.lock().unwrap().insert(key(file), record);
+        Ok(())
+    }
+    /// Writes all records to database file, replacing it atomically
+    pub fn save(&self) -> Result<()> {
+        let mut lines: Vec<_> = self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, record)| format_record(path, record))
+            .collect();
+        // Stable order keeps database diffable
+        lines.sort();
+        let mut staging = self.path.as_os_str().to_owned();
+        staging.push(".tmp");
+        std::fs::write(&staging, lines.concat())?;
+        std::fs::rename(&staging, &self.path)?;
+        Ok(())
+    }
+}
+/// Key of file's record
+fn key(file: &Path) -> String {
+    file.to_string_lossy().into_owned()
+}
+/// Reads size and modification time of file
+async fn stat(file: &Path) -> Option<(u64, SystemTime)> {
+    let meta = fs::metadata(file).await.ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+/// Parses database line into file path and its record
+fn parse_record(line: &str) -> Result<(String, Record)> {
+    let value: Value = serde_json::from_str(line)?;
+    let field = |name: &str| value.get(name).and_then(Value::as_str);
+    let number = |name: &str| value.get(name).and_then(Value::as_u64);
+    let path = field("path").context("no path")?.to_owned();
+    let checksum = match (field("algorithm"), field("hash")) {
+        (Some(algo), Some(hash)) => Some(Checksum::parse(algo.parse()?, hash)?),
+        _ => None,
+    };
+    let modified = UNIX_EPOCH
+        + Duration::new(
+            number("modified_secs").context("no modification time")?,
+            number("modified_nanos").unwrap_or(0) as u32,
+        );
+    let record = Record {
+        checksum,
+        size: number("size").context("no size")?,
+        modified,
+        url: field("url").map(str::to_owned),
+    };
+    Ok((path, record))
+}
+/// Formats record of file as database line
+fn format_record(path: &str, record: &Record) -> String {
+    // Files dated before epoch are clamped to it, and thus never trusted
+    let modified = record
+        .modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let value = json!({
+        "path": path,
+        "algorithm": record.checksum.as_ref().map(|checksum| checksum.algorithm.name()),
+        "hash": record.checksum.as_ref().map(|checksum| hex::encode(&checksum.value)),
+        "size": record.size,
+        "modified_secs": modified.as_secs(),
+        "modified_nanos": modified.subsec_nanos(),
+        "url": record.url,
+    });
+    format!("{}\n", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashDb;
+    use crate::digest::{Algorithm, Checksum};
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn recorded_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("sums.db");
+        let file = dir.path().join("file");
+        let checksum = Checksum::parse(Algorithm::Md5, "8d777f385d3dfec8815d20f7496026dc").unwrap();
+
+        Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                std::fs::write(&file, b"data").unwrap();
+                let db = HashDb::open(&db_path).unwrap();
+                assert_eq!(db.lookup(&file).await, None);
+                assert!(!db.changed(&file).await);
+                db.record(&file, Some(checksum.clone()), Some("http://a/file"))
+                    .await
+                    .unwrap();
+                db.save().unwrap();
+                // Records survive reopening
+                let db = HashDb::open(&db_path).unwrap();
+                assert_eq!(db.checksum(&file, Algorithm::Md5).await, Some(checksum));
+                assert_eq!(db.checksum(&file, Algorithm::Sha256).await, None);
+                let record = db.lookup(&file).await.unwrap();
+                assert_eq!(record.url.as_deref(), Some("http://a/file"));
+                // Modified file isn't trusted anymore
+                std::fs::write(&file, b"other data").unwrap();
+                assert_eq!(db.lookup(&file).await, None);
+                assert!(db.changed(&file).await);
+            });
+        std::fs::write(&db_path, "{\"path\": \"a\"}\n").unwrap();
+        assert!(HashDb::open(&db_path).is_err());
+    }
+}
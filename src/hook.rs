@@ -0,0 +1,234 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use tokio::sync::Semaphore;
+
+/// Placeholders substituted in hook command
+const PLACEHOLDERS: [&str; 5] = ["url", "dest", "status", "sha256", "bytes"];
+
+/// Outcome of finished job, which hook command is given
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobContext {
+    /// Source URL
+    pub url: String,
+    /// Path of destination file
+    pub dest: PathBuf,
+    /// Whether job succeeded
+    pub success: bool,
+    /// Number of bytes downloaded
+    pub bytes: u64,
+}
+
+/// Shell command run after each job finishes
+///
+/// Job context is passed both as `{name}` placeholders in command, substituted with
+/// shell-quoted values, and as `HTTPDL_<NAME>` environment variables, so scripts
+/// needn't parse arguments. Clones share limit on number of commands running at once
+#[derive(Clone, Debug)]
+pub struct Hook {
+    command: String,
+    running: Arc<Semaphore>,
+}
+
+impl Hook {
+    /// Creates hook from command, which is run with `sh -c`, and which has at most
+    /// `max_running` instances running at once; zero means one
+    pub fn new(command: impl Into<String>, max_running: usize) -> Hook {
+        Hook {
+            command: command.into(),
+            running: Arc::new(Semaphore::new(max_running.max(1))),
+        }
+    }
+    /// Runs command for finished job on blocking thread, once number of running
+    /// commands is below limit, and waits for it to exit
+    pub async fn spawn(&self, job: JobContext) -> Result<()> {
+        let _permit = self.running.acquire().await?;
+        let hook = self.clone();
+        tokio::task::spawn_blocking(move || hook.run(&job)).await?
+    }
+    /// Runs command for finished job and waits for it to exit
+    ///
+    /// Blocks, so is meant to run on blocking thread
+    pub fn run(&self, job: &JobContext) -> Result<()> {
+        let vars = job_vars(job)?;
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(self.render(&vars))
+            .envs(
+                vars.iter()
+                    .map(|(name, value)| (format!("HTTPDL_{}", name.to_uppercase()), value)),
+            )
+            .status()
+            .with_context(|| format!("{}: cannot run hook", self.command))?;
+        if !status.success() {
+            bail!("{}: hook failed with {}", self.command, status);
+        }
+        Ok(())
+    }
+    /// Substitutes placeholders in command with shell-quoted values; unknown ones are left as is
+    ///
+    /// Command is scanned once, so placeholders appearing in substituted values stay intact
+    fn render(&self, vars: &[(&str, String)]) -> String {
+        let mut rendered = String::with_capacity(self.command.len());
+        let mut rest = self.command.as_str();
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest.find('}').and_then(|end| {
+                let value = vars.iter().find(|(name, _)| *name == &rest[1..end])?;
+                Some((end, &value.1))
+            });
+            match value {
+                Some((end, value)) => {
+                    rendered.push_str(&shell_quote(value));
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+/// Collects values of placeholders for job, in order of `PLACEHOLDERS`
+///
+/// Checksum is computed from destination file, and is empty if job failed;
+/// fails if destination of successful job can't be read
+fn job_vars(job: &JobContext) -> Result<Vec<(&'static str, String)>> {
+    let sha256 = match job.success {
+        true => file_sha256(&job.dest)
+            .with_context(|| format!("{}: cannot compute checksum for hook", job.dest.display()))?,
+        false => String::new(),
+    };
+    let status = match job.success {
+        true => "finished",
+        false => "failed",
+    };
+    let values = [
+        job.url.clone(),
+        job.dest.display().to_string(),
+        status.to_owned(),
+        sha256,
+        job.bytes.to_string(),
+    ];
+    Ok(PLACEHOLDERS.into_iter().zip(values).collect())
+}
+
+/// Computes SHA-256 digest of file, hex-encoded
+fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Quotes value for POSIX shell, so it's passed as single word whatever it contains
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hook, JobContext};
+
+    #[test]
+    fn hook_context() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("it's here");
+        std::fs::write(&dest, b"abc").unwrap();
+        let out = dir.path().join("out");
+        let hook = Hook::new(format!(
+            "printf '%s|%s|%s\\n' {{dest}} {{status}} \"$HTTPDL_STATUS $HTTPDL_BYTES $HTTPDL_URL $HTTPDL_SHA256\" > '{}'",
+            out.display()
+        ), 1);
+        let mut job = JobContext {
+            url: "http://host/file?a=1&b=2".to_owned(),
+            dest: dest.clone(),
+            success: true,
+            bytes: 3,
+        };
+        hook.run(&job).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            format!(
+                "{}|finished|finished 3 http://host/file?a=1&b=2 \
+                 ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n",
+                dest.display()
+            )
+        );
+
+        job.success = false;
+        hook.run(&job).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            format!(
+                "{}|failed|failed 3 http://host/file?a=1&b=2 \n",
+                dest.display()
+            )
+        );
+
+        assert!(Hook::new("exit 3", 1).run(&job).is_err());
+
+        // Values are substituted once, even if they look like placeholders
+        let braced = dir.path().join("{url}");
+        std::fs::write(&braced, b"abc").unwrap();
+        let hook = Hook::new(
+            format!(
+                "printf '%s|%s|%s' {{dest}} {{url}} {{other}} > '{}'",
+                out.display()
+            ),
+            1,
+        );
+        job.dest = braced.clone();
+        hook.run(&job).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            format!("{}|http://host/file?a=1&b=2|{{other}}", braced.display())
+        );
+
+        // Successful job whose file is missing fails hook instead of passing empty checksum
+        job.success = true;
+        job.dest = dir.path().join("missing");
+        assert!(hook.run(&job).is_err());
+    }
+
+    #[tokio::test]
+    async fn hook_concurrency() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+        // Each command notes its start and end; with limit of 2, no third starts before end
+        let hook = Hook::new(
+            format!(
+                "echo start >> '{0}'; sleep 0.1; echo end >> '{0}'",
+                log.display()
+            ),
+            2,
+        );
+        let job = JobContext {
+            url: "http://host/file".to_owned(),
+            dest: log.clone(),
+            success: false,
+            bytes: 0,
+        };
+        let runs: Vec<_> = (0..4).map(|_| hook.spawn(job.clone())).collect();
+        for result in futures::future::join_all(runs).await {
+            result.unwrap();
+        }
+        let mut running = 0;
+        for line in std::fs::read_to_string(&log).unwrap().lines() {
+            running += match line {
+                "start" => 1,
+                _ => -1,
+            };
+            assert!(running <= 2);
+        }
+    }
+}
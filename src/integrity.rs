@@ -0,0 +1,94 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::HeaderMap;
+
+use crate::digest::{Algorithm, Checksum};
+
+/// Integrity fields, from most to least preferred
+const FIELDS: [&str; 4] = ["content-digest", "digest", "x-goog-hash", "content-md5"];
+
+/// Extracts checksum of response body announced by server, if it sends any supported one
+///
+/// Recognized fields, in order of preference:
+/// * `Content-Digest` (RFC 9530), i.e. `sha-256=:<base64>:`
+/// * `Digest` (RFC 3230), i.e. `SHA-256=<base64>`
+/// * `x-goog-hash`, i.e. `crc32c=<base64>,md5=<base64>`
+/// * `Content-MD5`, i.e. `<base64>`
+///
+/// Servers may send these fields as trailers after chunked body too,
+/// but HTTP client doesn't expose trailers, so only headers are checked.
+/// Unknown algorithms and malformed values are ignored
+pub fn announced(headers: &HeaderMap) -> Option<Checksum> {
+    FIELDS.iter().find_map(|field| {
+        headers
+            .get_all(*field)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(|item| match *field {
+                "content-md5" => checksum("md5", item),
+                _ => {
+                    let (algo, value) = item.split_once('=')?;
+                    checksum(algo, value.trim().trim_matches(':'))
+                }
+            })
+    })
+}
+/// Converts algorithm name and base64-encoded digest into checksum
+fn checksum(algo: &str, value: &str) -> Option<Checksum> {
+    let algorithm = match algo.trim().to_ascii_lowercase().as_str() {
+        "md5" => Algorithm::Md5,
+        "sha" => Algorithm::Sha1,
+        "sha-256" => Algorithm::Sha256,
+        "sha-512" => Algorithm::Sha512,
+        _ => return None,
+    };
+    let value = STANDARD.decode(value.trim()).ok()?;
+    Checksum::parse(algorithm, &hex::encode(value)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::announced;
+    use crate::digest::Algorithm;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    // MD5 and SHA-256 of "abc"
+    const MD5: &str = "kAFQmDzST7DWlj99KOF/cg==";
+    const SHA256: &str = "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=";
+
+    fn headers(fields: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in fields {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn announced_checksums() {
+        assert!(announced(&HeaderMap::new()).is_none());
+
+        let checksum = announced(&headers(&[("content-md5", MD5.to_owned())])).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Md5);
+        assert_eq!(
+            hex::encode(checksum.value),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+
+        let goog = format!("crc32c=n03x6A==,md5={}", MD5);
+        let checksum = announced(&headers(&[("x-goog-hash", goog)])).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Md5);
+        // Content-Digest is preferred over older fields
+        let checksum = announced(&headers(&[
+            ("content-md5", MD5.to_owned()),
+            ("content-digest", format!("sha-256=:{}:", SHA256)),
+        ]))
+        .unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        let checksum = announced(&headers(&[("digest", format!("SHA-256={}", SHA256))])).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        // Unknown algorithms and malformed values are ignored
+        assert!(announced(&headers(&[("digest", "UNIXsum=30637".to_owned())])).is_none());
+        assert!(announced(&headers(&[("content-md5", "not base64".to_owned())])).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+//! Asynchronous HTTP/HTTPS file downloader, with concurrency and speed limits
+//!
+//! Download engine behind `httpdl` utility, usable by other projects directly.
+//! Downloader is created from sequence of list entries, destination directory
+//! and options, and reports state of each job through notification stream:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use httpdl::{list::Entry, new_downloader, Options, Progress};
+//!
+//! # async fn run() {
+//! let files = [Entry::new("https://example.com/file.bin", "file.bin")];
+//! let options = Options {
+//!     threads_num: 4,
+//!     ..Options::default()
+//! };
+//! let (downloader, mut notifier) = new_downloader(files, "downloads", options);
+//! let progress = tokio::spawn(async move {
+//!     while let Some((_, url, name, status)) = notifier.next().await {
+//!         if let Progress::Finished(Err(err)) = status {
+//!             eprintln!("{} -> {}: {}", url, name, err);
+//!         }
+//!     }
+//! });
+//! downloader.await;
+//! let _ = progress.await;
+//! # }
+//! ```
+//
+// Submodules
+//
+pub mod auth;
+
+pub mod cache;
+
+pub mod clock;
+
+pub mod compare;
+
+pub mod token_bucket;
+
+pub mod digest;
+
+pub mod encoding;
+
+pub mod failure;
+
+pub mod har;
+
+pub mod ftp;
+
+pub mod sftp;
+
+pub mod protocol;
+
+pub mod hashdb;
+
+pub mod hook;
+
+mod integrity;
+
+mod mime;
+
+pub mod names;
+
+pub mod limiter;
+
+pub mod list;
+
+pub mod nested;
+
+pub mod resume;
+
+mod schedule;
+
+pub mod sums;
+
+pub mod retry;
+
+pub mod segments;
+
+mod oci;
+
+mod s3;
+
+mod pacing;
+
+pub mod pattern;
+
+pub mod peers;
+
+mod preflight;
+
+pub mod simulate;
+
+pub mod probe;
+
+pub mod proxy;
+
+pub mod redirects;
+
+pub mod validators;
+
+pub mod vars;
+
+pub mod transform;
+
+pub mod tls;
+
+pub mod timestamp;
+
+pub mod timeline;
+
+pub mod stall;
+
+pub mod reproducible;
+
+pub mod encrypt;
+
+mod warmup;
+
+#[cfg(test)]
+mod test_utils;
+
+pub mod clobber;
+
+pub mod copy_with_speedlimit;
+pub use copy_with_speedlimit::copy_with_speedlimit;
+
+pub mod downloader;
+pub use downloader::{
+    new_downloader, Downloader, DownloaderHandle, Grace, Notifier, Options, Progress, SkipReason,
+    SmallFiles, Stamped, TcpOptions,
+};
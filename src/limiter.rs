@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::clock::Clock;
+use crate::token_bucket::TokenBucket;
+
+/// Marks control which wasn't used yet
+const UNSET: usize = usize::MAX;
+/// How long host allocation takes to refill; host which claimed its allocation
+/// within this time is busy, so its allocation isn't lent to others
+const REFILL_PERIOD: Duration = Duration::from_secs(1);
+/// How long transfers with later deadlines give way after transfer with nearer one
+/// was short of bandwidth
+const STARVATION_WINDOW: Duration = Duration::from_millis(500);
+
+/// Handle which changes overall speed limit of download process while it's running
+///
+/// Clones share the same limit, so one clone is passed to downloader and another one is kept
+#[derive(Clone, Debug)]
+pub struct SpeedControl {
+    rate: Arc<AtomicUsize>,
+}
+
+impl SpeedControl {
+    /// Creates control which doesn't change initial limit until it's set
+    pub fn new() -> SpeedControl {
+        SpeedControl {
+            rate: Arc::new(AtomicUsize::new(UNSET)),
+        }
+    }
+    /// Sets new overall speed limit, in bytes per second; 0 means no limit
+    ///
+    /// Limit applies to running transfers right away
+    pub fn set(&self, rate: usize) {
+        self.rate.store(rate.min(UNSET - 1), Ordering::Relaxed);
+    }
+    /// Returns limit which was set last, if any
+    pub fn get(&self) -> Option<usize> {
+        match self.rate.load(Ordering::Relaxed) {
+            UNSET => None,
+            rate => Some(rate),
+        }
+    }
+}
+
+impl Default for SpeedControl {
+    fn default() -> SpeedControl {
+        SpeedControl::new()
+    }
+}
+
+/// Hierarchical speed limiter: overall limit shared by all transfers,
+/// and optional limit for each source host under it
+///
+/// With borrowing enabled, host which exhausted its own allocation may use bandwidth
+/// left unused by idle hosts, as long as overall limit isn't exceeded.
+/// Allocation of host which is still busy is never lent
+pub struct Limiter {
+    inner: Mutex<Inner>,
+}
+
+/// Limiter state, guarded by mutex
+struct Inner {
+    /// Overall limit, shared by all hosts
+    global: TokenBucket<Arc<dyn Clock>>,
+    /// Whether overall limit is set at all
+    global_limited: bool,
+    /// Size of overall burst; `None` if it follows overall limit
+    burst: Option<usize>,
+    /// Handle which may change overall limit
+    control: SpeedControl,
+    /// Per-host limits, created on first transfer from host
+    hosts: HashMap<String, Host>,
+    /// Max speed per host, in bytes per second; 0 means no per-host limit
+    host_rate: usize,
+    /// Whether hosts may borrow allocation of idle ones
+    borrow: bool,
+    /// Source of time for all buckets
+    clock: Arc<dyn Clock>,
+}
+
+/// Allocation of single host
+struct Host {
+    /// Host's own limit
+    bucket: TokenBucket<Arc<dyn Clock>>,
+    /// When host last asked for bandwidth
+    claimed: Instant,
+}
+
+impl Limiter {
+    /// Creates new limiter
+    ///
+    /// # Arguments
+    /// * rate - overall speed limit, in bytes per second; 0 means no limit
+    /// * host_rate - speed limit of single host, in bytes per second; 0 means no limit
+    /// * borrow - allow hosts to exceed their limit using bandwidth unused by idle hosts,
+    ///   up to overall limit; has no effect unless both limits are set
+    /// * clock - source of time
+    pub fn new(rate: usize, host_rate: usize, borrow: bool, clock: Arc<dyn Clock>) -> Limiter {
+        Limiter::with_burst(rate, None, host_rate, borrow, clock)
+    }
+    /// Creates new limiter whose overall limit allows bursts of specified size
+    ///
+    /// # Arguments
+    /// * rate - overall speed limit, in bytes per second; 0 means no limit
+    /// * burst - how many bytes can be transferred at once when overall limit was unused
+    ///   for a while, must be nonzero; `None` means one second worth of overall limit.
+    ///   Bigger than rate allows short bursts above it, smaller one smooths transfers
+    /// * host_rate - speed limit of single host, in bytes per second; 0 means no limit
+    /// * borrow - allow hosts to exceed their limit using bandwidth unused by idle hosts,
+    ///   up to overall limit; has no effect unless both limits are set
+    /// * clock - source of time
+    pub fn with_burst(
+        rate: usize,
+        burst: Option<usize>,
+        host_rate: usize,
+        borrow: bool,
+        clock: Arc<dyn Clock>,
+    ) -> Limiter {
+        Limiter {
+            inner: Mutex::new(Inner {
+                global: TokenBucket::with_clock(rate, burst.unwrap_or(rate), clock.clone()),
+                global_limited: rate > 0,
+                burst,
+                control: SpeedControl::new(),
+                hosts: HashMap::new(),
+                host_rate,
+                borrow,
+                clock,
+            }),
+        }
+    }
+    /// Makes overall limit follow specified control, once it's set
+    pub fn with_control(self, control: SpeedControl) -> Limiter {
+        self.inner.lock().unwrap().control = control;
+        self
+    }
+    /// Attempts to take specified amount of bytes for transfer from specified host
+    ///
+    /// # Returns
+    /// Number of bytes which can be transferred right now; 0 if limiter is busy
+    pub fn take(&self, host: &str, amount: usize) -> usize {
+        match self.inner.try_lock() {
+            Ok(mut inner) => {
+                inner.update_rate();
+                inner.take(host, amount)
+            }
+            Err(_) => 0,
+        }
+    }
+    /// Returns bytes which were taken for transfer from specified host, but weren't used
+    pub fn put_back(&self, host: &str, amount: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.global.put_back(amount);
+        if let Some(own) = inner.hosts.get_mut(host) {
+            own.bucket.put_back(amount);
+        }
+    }
+    /// Tells whether overall limit is used up right now, so transfers compete for it
+    pub fn exhausted(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        inner.update_rate();
+        inner.global_limited && inner.global.available() == 0
+    }
+    /// Computes how long transfer from host must wait until specified amount of bytes
+    /// can be taken
+    ///
+    /// # Returns
+    /// Time to wait; zero if it's unknown because limiter is busy
+    pub fn wait(&self, host: &str, amount: usize) -> Duration {
+        let Ok(mut inner) = self.inner.try_lock() else {
+            return Duration::ZERO;
+        };
+        inner.update_rate();
+        let global = inner.global.wait(amount);
+        // Borrowing host isn't bound by its own allocation while there's some to borrow
+        if inner.borrow && inner.global_limited && inner.spare(host) > 0 {
+            return global;
+        }
+        match inner.hosts.get(host) {
+            Some(own) => global.max(own.bucket.wait(amount)),
+            None => global,
+        }
+    }
+}
+
+/// Overall limit shared by several download processes, like ones of different tenants,
+/// each of which gets equal share of it
+///
+/// With borrowing enabled, process which exhausted its share may use bandwidth
+/// left unused by idle ones
+#[derive(Clone)]
+pub struct FairShare {
+    /// Limiter where each process is a separate host
+    limiter: Arc<Limiter>,
+    /// Key of process in limiter
+    tenant: String,
+}
+
+impl FairShare {
+    /// Splits overall limit between specified number of processes
+    ///
+    /// # Arguments
+    /// * rate - overall speed limit, in bytes per second; 0 means no limit
+    /// * tenants - number of processes
+    /// * borrow - allow processes to use shares of idle ones
+    /// * clock - source of time
+    ///
+    /// # Returns
+    /// Share of each process, in order
+    pub fn split(
+        rate: usize,
+        tenants: usize,
+        borrow: bool,
+        clock: Arc<dyn Clock>,
+    ) -> Vec<FairShare> {
+        let share = match rate {
+            0 => 0,
+            _ => (rate / tenants.max(1)).max(1),
+        };
+        let limiter = Arc::new(Limiter::new(rate, share, borrow, clock));
+        (0..tenants)
+            .map(|tenant| FairShare {
+                limiter: limiter.clone(),
+                tenant: tenant.to_string(),
+            })
+            .collect()
+    }
+    /// Attempts to take specified amount of bytes from process's share
+    ///
+    /// # Returns
+    /// Number of bytes which can be transferred right now
+    pub fn take(&self, amount: usize) -> usize {
+        self.limiter.take(&self.tenant, amount)
+    }
+    /// Computes how long process must wait until specified amount of bytes can be taken
+    pub fn wait(&self, amount: usize) -> Duration {
+        self.limiter.wait(&self.tenant, amount)
+    }
+}
+
+impl fmt::Debug for FairShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FairShare")
+            .field("tenant", &self.tenant)
+            .finish()
+    }
+}
+
+/// Deadlines of running transfers, so ones due sooner get bandwidth first
+///
+/// While transfer with deadline is held back by overall limit, transfers with later deadlines,
+/// or without any, give way to it. Transfer which is slowed down by server, or by limits
+/// it doesn't share with others, doesn't hold others up
+#[derive(Debug)]
+pub struct Deadlines {
+    /// Deadlines of running transfers which have them, by key,
+    /// with time each one was last short of bandwidth
+    running: Mutex<HashMap<usize, (SystemTime, Option<Instant>)>>,
+    /// Source of time for starvation window
+    clock: Arc<dyn Clock>,
+}
+
+impl Deadlines {
+    /// Creates empty registry of deadlines
+    pub fn new(clock: Arc<dyn Clock>) -> Deadlines {
+        Deadlines {
+            running: Mutex::new(HashMap::new()),
+            clock,
+        }
+    }
+    /// Registers running transfer with deadline
+    pub fn start(&self, key: usize, deadline: SystemTime) {
+        self.running.lock().unwrap().insert(key, (deadline, None));
+    }
+    /// Removes transfer which isn't running anymore
+    pub fn finish(&self, key: usize) {
+        self.running.lock().unwrap().remove(&key);
+    }
+    /// Records that transfer got less bandwidth than it asked for, because overall limit
+    /// is used up
+    pub fn starved(&self, key: usize) {
+        if let Some((_, starved)) = self.running.lock().unwrap().get_mut(&key) {
+            *starved = Some(self.clock.now());
+        }
+    }
+    /// Checks whether transfer with specified deadline, if any, should give way
+    /// to transfer with nearer deadline
+    pub fn yields(&self, deadline: Option<SystemTime>) -> bool {
+        let now = self.clock.now();
+        self.running
+            .lock()
+            .unwrap()
+            .values()
+            .any(|&(other, starved)| {
+                deadline.is_none_or(|deadline| other < deadline)
+                    && starved.is_some_and(|starved| now - starved < STARVATION_WINDOW)
+            })
+    }
+}
+
+impl Inner {
+    /// Applies overall limit set through control, if it was changed
+    fn update_rate(&mut self) {
+        let Some(rate) = self.control.get() else {
+            return;
+        };
+        if rate != self.global.rate() {
+            self.global.set_rate(rate, self.burst.unwrap_or(rate));
+            self.global_limited = rate > 0;
+        }
+    }
+    fn take(&mut self, host: &str, amount: usize) -> usize {
+        if self.host_rate == 0 {
+            return self.global.take(amount);
+        }
+        let (host_rate, now) = (self.host_rate, self.clock.now());
+        let clock = &self.clock;
+        let own = self.hosts.entry(host.to_owned()).or_insert_with(|| Host {
+            bucket: TokenBucket::with_clock(host_rate, host_rate, clock.clone()),
+            claimed: now,
+        });
+        own.claimed = now;
+        let wanted = own.bucket.take(amount);
+        let granted = self.global.take(wanted);
+        own.bucket.put_back(wanted - granted);
+        // Whatever host can't take from its own allocation may be borrowed
+        // from allocations of idle hosts
+        if granted < amount && self.borrow && self.global_limited {
+            let borrowed = self.spare(host).min(amount - granted);
+            granted + self.global.take(borrowed)
+        } else {
+            granted
+        }
+    }
+    /// Computes how much of overall allocation specified host may borrow,
+    /// which is what's left after allocations other busy hosts may still claim
+    fn spare(&mut self, host: &str) -> usize {
+        let now = self.clock.now();
+        let reserved = self
+            .hosts
+            .iter_mut()
+            .filter(|(name, other)| *name != host && now - other.claimed <= REFILL_PERIOD)
+            .map(|(_, other)| other.bucket.available())
+            .sum();
+        self.global.available().saturating_sub(reserved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Deadlines, FairShare, Limiter, SpeedControl};
+    use crate::clock::ManualClock;
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn drain(limiter: &Limiter, host: &str) -> usize {
+        limiter.take(host, 10_000)
+    }
+
+    #[test]
+    fn host_limits() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = Limiter::new(300, 100, false, clock.clone());
+        // Like overall one, host allocation starts empty
+        assert_eq!(drain(&limiter, "a"), 0);
+        assert_eq!(drain(&limiter, "b"), 0);
+        assert_eq!(drain(&limiter, "c"), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(drain(&limiter, "a"), 100);
+        assert_eq!(drain(&limiter, "b"), 100);
+        assert_eq!(drain(&limiter, "a"), 0);
+        // Hosts are held back by their own limits, not by overall one
+        assert!(!limiter.exhausted());
+        assert_eq!(drain(&limiter, "c"), 100);
+        assert!(limiter.exhausted());
+        // Without overall limit, hosts are limited on their own
+        let limiter = Limiter::new(0, 100, true, clock.clone());
+        assert_eq!(drain(&limiter, "a"), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(drain(&limiter, "a"), 100);
+    }
+
+    #[test]
+    fn bandwidth_borrowing() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = Limiter::new(300, 100, true, clock.clone());
+        assert_eq!(drain(&limiter, "b"), 0);
+        clock.advance(Duration::from_secs(1));
+        // Other hosts are idle, so their allocation is borrowed, up to overall limit,
+        // while busy host keeps its own
+        assert_eq!(drain(&limiter, "a"), 200);
+        assert_eq!(drain(&limiter, "b"), 100);
+        assert_eq!(drain(&limiter, "a"), 0);
+        // Busy host may still claim the rest of its allocation
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.take("b", 50), 50);
+        assert_eq!(drain(&limiter, "a"), 200);
+        assert_eq!(drain(&limiter, "b"), 50);
+        // Host which stays idle for a while lends its allocation again
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(drain(&limiter, "a"), 300);
+    }
+
+    #[test]
+    fn waiting_time() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = Limiter::new(300, 100, false, clock.clone());
+        assert_eq!(limiter.wait("a", 30), Duration::from_millis(100));
+        assert_eq!(drain(&limiter, "a"), 0);
+        // Host allocation is the narrower one
+        assert_eq!(limiter.wait("a", 50), Duration::from_millis(500));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.wait("a", 50), Duration::ZERO);
+        // Unlimited transfers never wait
+        let limiter = Limiter::new(0, 0, false, clock.clone());
+        assert_eq!(limiter.wait("a", 50), Duration::ZERO);
+    }
+
+    #[test]
+    fn burst_size() {
+        let clock = Arc::new(ManualClock::new());
+        // Idle period lets whole burst through, above the rate
+        let limiter = Limiter::with_burst(100, Some(500), 0, false, clock.clone());
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(drain(&limiter, "a"), 500);
+        // Small burst keeps transfers smooth
+        let limiter = Limiter::with_burst(100, Some(10), 0, false, clock.clone());
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(drain(&limiter, "a"), 10);
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(drain(&limiter, "a"), 5);
+    }
+
+    #[test]
+    fn runtime_limit() {
+        let clock = Arc::new(ManualClock::new());
+        let control = SpeedControl::new();
+        let limiter = Limiter::new(100, 0, false, clock.clone()).with_control(control.clone());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(drain(&limiter, "a"), 100);
+        // New limit applies from the next transfer on, and can be lifted altogether
+        control.set(1_000);
+        assert_eq!(drain(&limiter, "a"), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(drain(&limiter, "a"), 1_000);
+        control.set(0);
+        assert_eq!(drain(&limiter, "a"), 10_000);
+        assert_eq!(control.get(), Some(0));
+    }
+
+    #[test]
+    fn fair_shares() {
+        let clock = Arc::new(ManualClock::new());
+        let shares = FairShare::split(300, 3, false, clock.clone());
+        // Like host allocations, shares start empty
+        assert_eq!(shares[0].take(10_000), 0);
+        assert_eq!(shares[1].take(10_000), 0);
+        clock.advance(Duration::from_secs(1));
+        // Busy process can't take more than its share
+        assert_eq!(shares[0].take(10_000), 100);
+        assert_eq!(shares[0].take(10_000), 0);
+        assert_eq!(shares[1].take(10_000), 100);
+        assert_eq!(shares[0].wait(50), Duration::from_millis(500));
+        // Unless it borrows shares of idle ones, while busy ones keep theirs
+        let shares = FairShare::split(300, 3, true, clock.clone());
+        assert_eq!(shares[1].take(10_000), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(shares[0].take(10_000), 200);
+        assert_eq!(shares[1].take(10_000), 100);
+        // Without overall limit, there's nothing to share
+        let shares = FairShare::split(0, 2, false, clock.clone());
+        assert_eq!(shares[1].take(10_000), 10_000);
+    }
+
+    #[test]
+    fn deadline_preemption() {
+        let clock = Arc::new(ManualClock::new());
+        let deadlines = Deadlines::new(clock.clone());
+        let (soon, late) = (UNIX_EPOCH, UNIX_EPOCH + Duration::from_secs(60));
+        deadlines.start(0, soon);
+        deadlines.start(1, late);
+        // Transfer which gets all it asks for doesn't hold others up
+        assert!(!deadlines.yields(None));
+        deadlines.starved(0);
+        assert!(deadlines.yields(None));
+        assert!(deadlines.yields(Some(late)));
+        assert!(!deadlines.yields(Some(soon)));
+        // Once transfer is satisfied for a while, or finishes, others proceed
+        clock.advance(Duration::from_secs(1));
+        assert!(!deadlines.yields(None));
+        deadlines.starved(0);
+        deadlines.finish(0);
+        assert!(!deadlines.yields(None));
+    }
+}
@@ -1,86 +1,1174 @@
 //
 // Uses from stdlib
 //
-use std::io::Read;
-use std::path::Path;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
 //
 // Uses from external crates
 //
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 use futures::StreamExt;
+use tokio_util::sync::CancellationToken;
+//
+// Uses from library part of the crate
+//
+use httpdl::auth::{Auth, Netrc};
+use httpdl::clobber::Clobber;
+use httpdl::clock::SystemClock;
+use httpdl::compare::{self, Comparison};
+use httpdl::copy_with_speedlimit::ChunkSizes;
+use httpdl::encrypt::{self, Encrypt};
+use httpdl::har::Har;
+use httpdl::hashdb::HashDb;
+use httpdl::hook::{Hook, JobContext};
+use httpdl::limiter::{FairShare, SpeedControl};
+use httpdl::list::{derive_name, mirror_path, parse_urls, Entry, ListFormat, UrlMode};
+use httpdl::nested;
+use httpdl::peers::Peers;
+use httpdl::probe::{format_table, probe_hosts};
+use httpdl::reproducible::Reproducible;
+use httpdl::resume::part_path;
+use httpdl::retry::RetryPolicy;
+use httpdl::simulate::Simulation;
+use httpdl::stall::LowSpeed;
+use httpdl::sums::{self, Document};
+use httpdl::timeline::Timeline;
+use httpdl::tls::TlsOptions;
+use httpdl::vars::Variables;
+use httpdl::{
+    new_downloader, DownloaderHandle, Grace, Options, Progress, SkipReason, SmallFiles, Stamped,
+    TcpOptions,
+};
 //
 // Submodules
 //
-mod token_bucket;
+mod statsd;
+use statsd::Statsd;
 
 mod config;
-use config::Config;
+use config::{
+    CmpConfig, Command, CommandLine, Config, DecryptConfig, Manifest, MultiConfig, ProbeConfig,
+    ServeSumsConfig,
+};
+
+mod bars;
+use bars::Bars;
+
+mod report;
+use report::{completed_names, recorded_sizes, Report};
+
+mod output;
+use output::{event_json, stamp_json, OutputFormat, ProgressPipe};
+
+/// Name of file in destination directory, where unfinished entries are saved on termination
+const SESSION_FILE: &str = ".httpdl-session";
+/// How often progress bars are updated
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+/// Process exit code when download was terminated by SIGTERM, as shells report it
+const EXIT_TERMINATED: i32 = 128 + 15;
+/// Process exit code when download was interrupted by Ctrl-C, as shells report it
+const EXIT_INTERRUPTED: i32 = 128 + 2;
 
-mod copy_with_speedlimit;
+/// Signal which cancelled the run; the first one received is recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stop {
+    /// Termination request, SIGTERM
+    Terminated,
+    /// Ctrl-C, SIGINT
+    Interrupted,
+}
 
-mod downloader;
-use downloader::{new_downloader, Progress};
+impl Stop {
+    /// Reads signal which cancelled the run, taking termination for cancellation without one
+    fn of(stopped: &OnceLock<Stop>) -> Stop {
+        stopped.get().copied().unwrap_or(Stop::Terminated)
+    }
+    /// Process exit code the run ends with
+    fn exit_code(self) -> i32 {
+        match self {
+            Stop::Terminated => EXIT_TERMINATED,
+            Stop::Interrupted => EXIT_INTERRUPTED,
+        }
+    }
+    /// Word which tells user how the run ended
+    fn describe(self) -> &'static str {
+        match self {
+            Stop::Terminated => "Terminated",
+            Stop::Interrupted => "Interrupted",
+        }
+    }
+}
 
 // Program starting point, as usual
 fn main() -> Result<()> {
+    // Auxiliary commands have their own set of arguments
+    if Command::is_requested(std::env::args()) {
+        let CommandLine { command } = CommandLine::try_parse()?;
+        return match command {
+            Command::Probe(config) => probe(config),
+            Command::Cmp(config) => cmp(config),
+            Command::ServeSums(config) => serve_sums(config),
+            Command::Multi(config) => multi(config),
+            Command::Decrypt(config) => decrypt(config),
+        };
+    }
     // First, parse arguments
     let Config {
         dest_dir,
         list_file,
         threads_num,
         speed_limit,
+        checksum_algo,
+        statsd,
+        warmup,
+        skip,
+        range,
+        report: report_file,
+        report_bucket,
+        clobber,
+        no_clobber,
+        overwrite,
+        rename_on_conflict,
+        tmp_dir,
+        fallback_dir,
+        segments,
+        small_files,
+        small_slots,
+        skip_unchanged,
+        max_connections,
+        control_connections,
+        verbose,
+        max_attempts,
+        retry_delay,
+        retry_jitter,
+        max_retry_after,
+        simulate,
+        simulate_speed,
+        sizes_from,
+        host_limit,
+        borrow_bandwidth,
+        no_progress,
+        output_format,
+        encrypt_key,
+        no_tcp_nodelay,
+        tcp_keepalive,
+        burst,
+        har,
+        discard_partial,
+        fix_extension,
+        preflight,
+        lan_peers,
+        peer_port,
+        ttfb_timeout,
+        max_redirects,
+        no_follow_redirects,
+        small_chunk,
+        large_chunk,
+        chunk_threshold,
+        speed_min,
+        speed_time,
+        compress,
+        strict_urls,
+        list_format,
+        grace,
+        grace_threshold,
+        discover_sums,
+        on_finish,
+        urls: url_args,
+        name_encoding,
+        content_disposition,
+        unlimited_hosts,
+        preserve_path,
+        hash_db,
+        headers,
+        users,
+        bearers,
+        netrc,
+        netrc_file,
+        defines,
+        proxies,
+        progress_fd,
+        ca_certs,
+        client_cert,
+        client_key,
+        insecure,
+        ssh_key,
+        reproducible,
     } = Config::try_parse()?;
-    // Now, we read whole list file and then fill files mapping
-    let all_text = {
-        // Open file with list of files to download
-        let mut fd = std::fs::File::open(&list_file)?;
-        // Then read all of its contents into buffer
-        let mut text = String::new();
-        fd.read_to_string(&mut text)?;
-        text
+    // Reproducible run takes its timestamp from environment, like reproducible builds do
+    let reproducible = reproducible.then(Reproducible::from_env).transpose()?;
+    // Report tells how much was received over time, unless runs must be reproducible
+    let timeline = (report_file.is_some() && reproducible.is_none()).then(|| {
+        Arc::new(Timeline::new(
+            report_bucket,
+            Arc::new(SystemClock),
+            SystemTime::now(),
+        ))
+    });
+    // Now, we parse list file into download entries, line by line as it's read;
+    // `-` reads it from stdin, so it can be piped from another program,
+    // and URL downloads it first, so mirrors can publish canonical list.
+    // Malformed entry options are reported before any download starts
+    let urls = match strict_urls {
+        true => UrlMode::Strict,
+        false => UrlMode::Encode,
+    };
+    // Lists are templates, whose placeholders are substituted as they're read
+    let vars = Variables::new(defines);
+    // Servers with private CAs or client certificates are trusted the same way
+    // by lists and downloads
+    let tls = TlsOptions {
+        ca_certs: ca_certs
+            .iter()
+            .map(|path| TlsOptions::load_ca_certs(Path::new(path)))
+            .collect::<Result<Vec<_>>>()?
+            .concat(),
+        identity: match (client_cert, client_key) {
+            (Some(cert), Some(key)) => Some(TlsOptions::load_identity(
+                Path::new(&cert),
+                Path::new(&key),
+            )?),
+            _ => None,
+        },
+        insecure,
+    };
+    // Lists are fetched by the same client, whether they're top-level or nested
+    let list_client = tls.configure(reqwest::Client::builder()).build()?;
+    let list_runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let mut files_seq = match list_file.as_deref() {
+        None => Vec::new(),
+        Some("-") => list_format.unwrap_or(ListFormat::Text).read(
+            vars.reader(std::io::stdin().lock()),
+            checksum_algo,
+            urls,
+        )?,
+        Some(url) if nested::is_remote(url) => list_runtime.block_on(nested::fetch(
+            &list_client,
+            url,
+            list_format,
+            checksum_algo,
+            urls,
+            &vars,
+        ))?,
+        Some(path) => list_format
+            .unwrap_or_else(|| ListFormat::detect(Path::new(path)))
+            .read(
+                vars.reader(std::io::BufReader::new(std::fs::File::open(path)?)),
+                checksum_algo,
+                urls,
+            )?,
+    };
+    // URLs from command line follow entries of list file
+    let url_args = url_args
+        .iter()
+        .map(|url| vars.substitute(url))
+        .collect::<Result<Vec<_>>>()?;
+    files_seq.extend(parse_urls(&url_args, urls)?);
+    // Nested lists are downloaded upfront, so their entries are scheduled as any other
+    let files_seq = match files_seq.iter().any(|entry| entry.list) {
+        true => list_runtime.block_on(nested::expand(
+            &list_client,
+            files_seq,
+            checksum_algo,
+            &vars,
+        ))?,
+        false => files_seq,
+    };
+    // Entries named after their URLs get mirrored paths instead of flat names
+    let files_seq = match preserve_path {
+        true => files_seq
+            .into_iter()
+            .map(|entry| match entry.name == derive_name(&entry.url) {
+                true => Entry {
+                    name: mirror_path(&entry.url),
+                    ..entry
+                },
+                false => entry,
+            })
+            .collect(),
+        false => files_seq,
+    };
+    // Shorthand flags override default mode, since they can't be combined with explicit one
+    let clobber = match (no_clobber, overwrite, rename_on_conflict) {
+        (true, _, _) => Clobber::Skip,
+        (_, true, _) => Clobber::Overwrite,
+        (_, _, true) => Clobber::Rename,
+        _ => clobber,
+    };
+    if simulate {
+        return run_simulation(
+            &files_seq,
+            range.unwrap_or(skip.unwrap_or(0)..usize::MAX),
+            sizes_from.as_deref(),
+            Simulation {
+                threads_num,
+                speed_limit,
+                connection_speed: simulate_speed,
+            },
+        );
+    }
+    // Metrics are optional, and sent from notification handler
+    let statsd = statsd.as_deref().map(Statsd::connect).transpose()?;
+    // Progress bars and JSON lines would garble each other on stdout,
+    // and redrawn bars would wipe out conflict prompts along with typed answers
+    let json = output_format == OutputFormat::Json;
+    let progress =
+        !json && !no_progress && clobber != Clobber::Ask && std::io::stdout().is_terminal();
+    // Parent process which passed descriptor gets structured events, progress included
+    let mut progress_pipe = progress_fd.map(ProgressPipe::open).transpose()?;
+    // Checksums recorded by previous runs spare rehashing destinations
+    let hash_db = hash_db.map(HashDb::open).transpose()?.map(Arc::new);
+    // Netrc file which doesn't exist is fine, unless it's specified explicitly
+    let netrc = match (netrc_file, netrc) {
+        (Some(path), _) => Some(Netrc::load(Path::new(&path))?),
+        (None, true) => match Netrc::default_path() {
+            Some(path) if path.exists() => Some(Netrc::load(&path)?),
+            _ => None,
+        },
+        (None, false) => None,
+    };
+    // Hooks need paths of destination files, which jobs report relative to directory
+    let on_finish = on_finish.map(|command| Hook::new(command, threads_num));
+    let hook_dir = PathBuf::from(&dest_dir);
+    let options = Options {
+        threads_num,
+        speed_limit,
+        host_speed_limit: host_limit,
+        unlimited_hosts,
+        proxies,
+        tls,
+        ssh_key: ssh_key.map(PathBuf::from),
+        headers: headers.into_iter().collect(),
+        auth: Auth {
+            credentials: users.into_iter().chain(bearers).collect(),
+            netrc,
+        },
+        borrow_bandwidth,
+        burst,
+        warmup,
+        entries: range.unwrap_or(skip.unwrap_or(0)..usize::MAX),
+        clobber,
+        names: name_encoding,
+        tmp_dir: tmp_dir.map(PathBuf::from),
+        fallback_dir: fallback_dir.map(PathBuf::from),
+        segments,
+        small_files: small_files.map(|threshold| SmallFiles {
+            threshold: threshold as u64,
+            slots: small_slots,
+        }),
+        skip_unchanged,
+        fix_extension,
+        content_disposition,
+        preflight,
+        max_connections,
+        control_connections,
+        peers: lan_peers
+            .then(|| Peers::start(peer_port))
+            .transpose()?
+            .map(Arc::new),
+        ttfb_timeout,
+        max_redirects: match no_follow_redirects {
+            true => 0,
+            false => max_redirects,
+        },
+        chunk_sizes: ChunkSizes {
+            threshold: chunk_threshold as u64,
+            small: small_chunk,
+            large: large_chunk,
+        },
+        low_speed: speed_min.map(|min| LowSpeed {
+            min: min as u64,
+            time: speed_time,
+        }),
+        compress,
+        grace: grace.map(|period| Grace {
+            period,
+            threshold: grace_threshold,
+        }),
+        discover_sums: discover_sums.then_some(checksum_algo),
+        retry: RetryPolicy {
+            max_attempts,
+            base_delay: retry_delay,
+            jitter: retry_jitter,
+        },
+        max_retry_after,
+        progress_interval: (progress || progress_pipe.is_some()).then_some(PROGRESS_INTERVAL),
+        transform: encrypt_key.map(|key| Arc::new(Encrypt::new(key)) as _),
+        har: har.as_ref().map(|_| {
+            Arc::new(match reproducible {
+                Some(reproducible) => Har::reproducible(reproducible.mtime),
+                None => Har::new(),
+            })
+        }),
+        reproducible,
+        timeline: timeline.clone(),
+        hash_db: hash_db.clone(),
+        tcp: TcpOptions {
+            nodelay: !no_tcp_nodelay,
+            keepalive: tcp_keepalive,
+        },
+        discard_partial,
+        ..Options::default()
+    };
+    let entries = options.entries.clone();
+    let selected = files_seq
+        .iter()
+        .skip(entries.start)
+        .take(entries.len())
+        .count();
+    let cancel = options.cancel.clone();
+    let stopped = Arc::new(OnceLock::new());
+    let speed_control = options.speed_control.clone();
+    let har_log = options.har.clone();
+    // Outcomes of all jobs, for summary at the end, with bandwidth accounted per group
+    // and deadlines checked
+    let report = list_report(&files_seq);
+    let report = match reproducible {
+        Some(_) => report.reproducible(),
+        None => report,
+    };
+    // Event times differ between runs, so reproducible output leaves them out
+    let event_times = reproducible.is_none();
+    let report = match timeline {
+        Some(timeline) => report.with_timeline(timeline),
+        None => report,
     };
-    // Next, we split the whole file into lines in-place
-    // And for each line which contains proper url-filename tuple,
-    // We yield that tuple
-    let files_seq = all_text
-        .lines()
-        .filter_map(|line| {
-            let mut pieces = line
-                .split(|c| " \r\n\t".contains(c))
-                .filter(|s| !s.is_empty());
-            let url = pieces.next()?;
-            let filename = pieces.next()?;
-            Some((url, filename))
-        })
-        .fuse();
 
-    tokio::runtime::Builder::new_multi_thread()
+    let report = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()?
-        .block_on(async move {
-            let files_seq = files_seq;
-            let (dl, mut notify) =
-                new_downloader(files_seq, Path::new(&dest_dir), threads_num, speed_limit);
+        .block_on(async {
+            // Termination request stops scheduling and interrupts running jobs
+            watch_termination(cancel.clone(), stopped.clone())?;
+            watch_interrupt(cancel.clone(), stopped.clone());
+            watch_speed_signals(speed_control, speed_limit)?;
+            let (dl, mut notify) = new_downloader(files_seq.clone(), Path::new(&dest_dir), options);
+            watch_pause_signals(dl.handle())?;
+            // Progress bars are drawn only on terminal, and only if not disabled
+            let mut bars = match json {
+                true => Bars::quiet(selected),
+                false => Bars::new(progress, selected),
+            };
             let notifier = tokio::spawn(async move {
-                while let Some((i, src, dst, status)) = notify.next().await {
+                let mut report = report;
+                // Job start times, to report job durations
+                let mut started = HashMap::new();
+                // Final paths of jobs saved under other than their destinations, for hooks
+                let mut saved = HashMap::new();
+                // Running hooks, whose failures are reported once they exit
+                let mut hooks = Vec::new();
+                // Final URLs of redirected jobs, reported on their completion
+                let mut effective_urls = HashMap::new();
+                while let Some(Stamped {
+                    seq,
+                    time,
+                    event: (i, src, dst, status),
+                }) = notify.next_stamped().await
+                {
+                    report.record(i, &src, &dst, &status);
+                    let effective_url = match &status {
+                        Progress::Redirected(hops) => {
+                            if let Some(hop) = hops.last() {
+                                effective_urls.insert(i, hop.url.clone());
+                            }
+                            None
+                        }
+                        Progress::Finished(_) => effective_urls.remove(&i),
+                        _ => None,
+                    };
+                    if json || progress_pipe.is_some() {
+                        let mut event = event_json(i, &src, &dst, &status);
+                        if let Some(url) = &effective_url {
+                            event["effective_url"] = url.as_str().into();
+                        }
+                        stamp_json(&mut event, seq, event_times.then_some(time));
+                        if let Some(pipe) = &mut progress_pipe {
+                            pipe.send(&event);
+                        }
+                        if json {
+                            println!("{}", event);
+                        }
+                    }
                     match status {
                         Progress::Started => {
-                            println!("#{} {} -> {}: Download started", i, src, dst)
+                            started.insert(i, Instant::now());
+                            match bars.enabled() {
+                                true => bars.start(i, &dst),
+                                false => bars.println(&format!(
+                                    "#{} {} -> {}: Download started",
+                                    i, src, dst
+                                )),
+                            }
+                        }
+                        Progress::Received { bytes, total } => bars.update(i, bytes, total),
+                        Progress::Finished(result) => {
+                            if let Some(statsd) = &statsd {
+                                if let Some(start) = started.remove(&i) {
+                                    statsd.timing("job.duration", start.elapsed());
+                                }
+                                match &result {
+                                    Ok(bytes) => {
+                                        statsd.count("bytes", *bytes);
+                                        statsd.count("jobs.finished", 1);
+                                    }
+                                    Err(_) => statsd.count("jobs.failed", 1),
+                                }
+                            }
+                            bars.end(i, *result.as_ref().unwrap_or(&0));
+                            if let Some(hook) = &on_finish {
+                                let job = JobContext {
+                                    url: src.clone(),
+                                    dest: saved
+                                        .remove(&i)
+                                        .unwrap_or_else(|| name_encoding.dest_path(&hook_dir, &dst)),
+                                    success: result.is_ok(),
+                                    bytes: *result.as_ref().unwrap_or(&0),
+                                };
+                                let hook = hook.clone();
+                                let run = tokio::spawn(async move { hook.spawn(job).await });
+                                hooks.push((i, src.clone(), dst.clone(), run));
+                            }
+                            match (result, effective_url) {
+                                (Ok(_), Some(url)) => bars.println(&format!(
+                                    "#{} {} -> {}: Download finished, from {}",
+                                    i, src, dst, url
+                                )),
+                                (Ok(_), None) => bars.println(&format!(
+                                    "#{} {} -> {}: Download finished",
+                                    i, src, dst
+                                )),
+                                (Err(err), _) => bars.eprintln(&format!(
+                                    "#{} {} -> {}: Download failed due to {}",
+                                    i, src, dst, err
+                                )),
+                            }
+                        }
+                        Progress::Cancelled => {
+                            if let Some(statsd) = &statsd {
+                                statsd.count("jobs.cancelled", 1);
+                            }
+                            bars.end(i, 0);
+                            bars.eprintln(&format!("#{} {} -> {}: Download cancelled", i, src, dst))
+                        }
+                        Progress::Changed(status) => {
+                            if let Some(statsd) = &statsd {
+                                statsd.count("jobs.changed", 1);
+                            }
+                            bars.end(i, 0);
+                            bars.eprintln(&format!(
+                                "#{} {} -> {}: Remote file changed during download (HTTP {}), destination left intact",
+                                i, src, dst, status
+                            ))
+                        }
+                        Progress::Skipped(reason) => {
+                            bars.end(i, 0);
+                            bars.println(&format!(
+                                "#{} {} -> {}: {}, skipped",
+                                i,
+                                src,
+                                dst,
+                                skip_message(reason)
+                            ))
+                        }
+                        Progress::Deferred(err) => {
+                            if let Some(statsd) = &statsd {
+                                statsd.count("jobs.deferred", 1);
+                            }
+                            bars.end(i, 0);
+                            bars.eprintln(&format!(
+                                "#{} {} -> {}: Download deferred, since {}",
+                                i, src, dst, err
+                            ))
+                        }
+                        Progress::Retrying {
+                            attempt,
+                            error,
+                            delay,
+                        } => {
+                            if let Some(statsd) = &statsd {
+                                statsd.count("jobs.retried", 1);
+                            }
+                            bars.eprintln(&format!(
+                                "#{} {} -> {}: Attempt {} failed due to {}, retrying in {:.1}s",
+                                i,
+                                src,
+                                dst,
+                                attempt,
+                                error,
+                                delay.as_secs_f64()
+                            ))
+                        }
+                        Progress::Corrupted { url, error } => {
+                            if let Some(statsd) = &statsd {
+                                statsd.count("jobs.corrupted", 1);
+                            }
+                            bars.eprintln(&format!(
+                                "#{} {} -> {}: Copy from {} is corrupted, {}; downloading from next mirror",
+                                i, src, dst, url, error
+                            ))
+                        }
+                        Progress::Conflict { action, path } => match action {
+                            // Skipped job reports itself on end
+                            Clobber::Skip => {}
+                            Clobber::Rename => {
+                                bars.println(&format!(
+                                    "#{} {} -> {}: Destination exists, downloading into {}",
+                                    i,
+                                    src,
+                                    dst,
+                                    path.display()
+                                ));
+                            }
+                            _ if verbose => bars.println(&format!(
+                                "#{} {} -> {}: Destination exists, overwriting",
+                                i, src, dst
+                            )),
+                            _ => {}
+                        },
+                        Progress::Relocated { path, error } => {
+                            if let Some(statsd) = &statsd {
+                                statsd.count("jobs.relocated", 1);
+                            }
+                            bars.eprintln(&format!(
+                                "#{} {} -> {}: Destination isn't writable, {}; downloading into {}",
+                                i,
+                                src,
+                                dst,
+                                error,
+                                path.display()
+                            ));
+                        }
+                        Progress::Saved(path) => {
+                            saved.insert(i, path);
                         }
-                        Progress::Finished(Ok(_)) => {
-                            println!("#{} {} -> {}: Download finished", i, src, dst)
+                        Progress::Resumed(stats) => {
+                            if verbose {
+                                bars.println(&format!(
+                                    "#{} {} -> {}: Resumed from {} bytes of partial data, {} bytes kept by retries, {} bytes downloaded again",
+                                    i, src, dst, stats.reused, stats.retried, stats.redownloaded
+                                ))
+                            }
                         }
-                        Progress::Finished(Err(err)) => {
-                            eprintln!("#{} {} -> {}: Download failed due to {}", i, src, dst, err)
+                        Progress::Throttled(delay) => {
+                            if verbose {
+                                bars.println(&format!(
+                                    "#{} {} -> {}: Slowed down by {:.1}s to respect host's rate limit",
+                                    i,
+                                    src,
+                                    dst,
+                                    delay.as_secs_f64()
+                                ))
+                            }
                         }
+                        Progress::Redirected(hops) => {
+                            if verbose {
+                                let chain: Vec<_> = hops
+                                    .iter()
+                                    .map(|hop| format!("{} ({})", hop.url, hop.status))
+                                    .collect();
+                                bars.println(&format!(
+                                    "#{} {} -> {}: Redirected via {}",
+                                    i,
+                                    src,
+                                    dst,
+                                    chain.join(" -> ")
+                                ))
+                            }
+                        }
+                    }
+                }
+                for (i, src, dst, run) in hooks {
+                    match run.await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(err)) => bars.eprintln(&format!(
+                            "#{} {} -> {}: Hook failed due to {:#}",
+                            i, src, dst, err
+                        )),
+                        Err(err) => bars.eprintln(&format!(
+                            "#{} {} -> {}: Hook failed due to {}",
+                            i, src, dst, err
+                        )),
                     }
                 }
+                bars.clear();
+                report
             });
 
             dl.await;
-            let _ = notifier.await;
-        });
+            Ok::<_, anyhow::Error>(notifier.await?)
+        })?;
+    // Entries selected for this run which still need to be downloaded
+    let unfinished: Vec<_> = files_seq
+        .iter()
+        .enumerate()
+        .skip(entries.start)
+        .take(entries.len())
+        .filter(|(i, _)| !report.completed(*i))
+        .collect();
+    let pending = unfinished
+        .iter()
+        .filter(|(i, _)| !report.started(*i))
+        .count();
+    let interrupted = cancel.is_cancelled();
+
+    // Summary goes to stderr in JSON mode, so stdout has only JSON lines
+    match json {
+        true => eprintln!("{}", report.summary(pending)),
+        false => println!("{}", report.summary(pending)),
+    }
+    if let (Some(har), Some(har_log)) = (har, har_log) {
+        std::fs::write(har, serde_json::to_string_pretty(&har_log.to_json())?)?;
+    }
+    if let Some(hash_db) = hash_db {
+        hash_db.save()?;
+    }
+    if let Some(report_file) = report_file {
+        let json = report.to_json(interrupted, pending);
+        std::fs::write(report_file, serde_json::to_string_pretty(&json)?)?;
+    }
+    if interrupted {
+        // Save whatever is left as list file, so the run can be resumed later;
+        // partial files of cancelled jobs are kept unless discarded, and will be picked up too
+        let session_file = Path::new(&dest_dir).join(SESSION_FILE);
+        let session: String = unfinished
+            .iter()
+            .map(|(_, entry)| format!("{}\n", entry))
+            .collect();
+        std::fs::write(&session_file, session)?;
+        let stop = Stop::of(&stopped);
+        eprintln!(
+            "{}; to resume, run again with -f {}",
+            stop.describe(),
+            session_file.display()
+        );
+        std::process::exit(stop.exit_code());
+    }
+
+    Ok(())
+}
+/// Cancels download process when termination signal is received
+#[cfg(unix)]
+fn watch_termination(cancel: CancellationToken, stopped: Arc<OnceLock<Stop>>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
 
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::spawn(async move {
+        if terminate.recv().await.is_some() {
+            let _ = stopped.set(Stop::Terminated);
+            cancel.cancel();
+        }
+    });
+    Ok(())
+}
+/// Cancels download process when termination signal is received
+#[cfg(not(unix))]
+fn watch_termination(_cancel: CancellationToken, _stopped: Arc<OnceLock<Stop>>) -> Result<()> {
+    Ok(())
+}
+/// Cancels download process on Ctrl-C, so running jobs stop cleanly and summary is printed;
+/// second Ctrl-C exits right away
+fn watch_interrupt(cancel: CancellationToken, stopped: Arc<OnceLock<Stop>>) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("Interrupted, stopping downloads; press Ctrl-C again to exit immediately");
+            let _ = stopped.set(Stop::Interrupted);
+            cancel.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+        }
+    });
+}
+/// Changes overall speed limit on signals: SIGUSR1 doubles it, SIGUSR2 halves it
+///
+/// Unlimited download stays unlimited, since there's nothing to double or halve
+#[cfg(unix)]
+fn watch_speed_signals(control: SpeedControl, speed_limit: usize) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut raise = signal(SignalKind::user_defined1())?;
+    let mut lower = signal(SignalKind::user_defined2())?;
+    tokio::spawn(async move {
+        let mut rate = speed_limit;
+        loop {
+            let new_rate = tokio::select! {
+                Some(()) = raise.recv() => rate.saturating_mul(2),
+                Some(()) = lower.recv() => (rate / 2).max(1),
+                else => break,
+            };
+            if rate != 0 {
+                rate = new_rate;
+                control.set(rate);
+                eprintln!("Speed limit changed to {} bytes/s", rate);
+            }
+        }
+    });
+    Ok(())
+}
+/// Changes overall speed limit on signals, which are Unix-only
+#[cfg(not(unix))]
+fn watch_speed_signals(_control: SpeedControl, _speed_limit: usize) -> Result<()> {
+    Ok(())
+}
+/// Pauses download process on SIGTSTP and resumes it on SIGCONT
+///
+/// Since SIGTSTP is handled, process itself isn't stopped, only transfers are;
+/// connections stay open, so paused transfers resume where they stopped
+#[cfg(unix)]
+fn watch_pause_signals(handle: DownloaderHandle) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut pause = signal(SignalKind::from_raw(libc::SIGTSTP))?;
+    let mut resume = signal(SignalKind::from_raw(libc::SIGCONT))?;
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(()) = pause.recv() => {
+                    handle.pause();
+                    eprintln!("Paused; send SIGCONT to resume");
+                }
+                Some(()) = resume.recv() => {
+                    if handle.is_paused() {
+                        handle.resume();
+                        eprintln!("Resumed");
+                    }
+                }
+                else => break,
+            }
+        }
+    });
+    Ok(())
+}
+/// Pauses download process on signals, which are Unix-only
+#[cfg(not(unix))]
+fn watch_pause_signals(_handle: DownloaderHandle) -> Result<()> {
+    Ok(())
+}
+/// Runs simulation of download instead of actual one, and prints estimate
+///
+/// # Arguments
+/// * files - all list file entries
+/// * entries - indices of entries selected for the run
+/// * sizes_from - report of previous run, to take unknown file sizes from
+/// * simulation - simulated download conditions
+fn run_simulation(
+    files: &[Entry],
+    entries: Range<usize>,
+    sizes_from: Option<&str>,
+    simulation: Simulation,
+) -> Result<()> {
+    let recorded = sizes_from
+        .map(recorded_sizes)
+        .transpose()?
+        .unwrap_or_default();
+    let sizes = files
+        .iter()
+        .skip(entries.start)
+        .take(entries.len())
+        .map(|entry| entry.size.or_else(|| recorded.get(&entry.url).copied()));
+    let estimate = simulation.run(sizes);
+    println!(
+        "Estimated time: {:.1}s for {} files, {} bytes",
+        estimate.duration.as_secs_f64(),
+        estimate.files,
+        estimate.bytes
+    );
+    if estimate.unknown > 0 {
+        println!(
+            "{} files of unknown size aren't accounted",
+            estimate.unknown
+        );
+    }
+    Ok(())
+}
+/// Describes why job was skipped, for console output
+fn skip_message(reason: SkipReason) -> &'static str {
+    match reason {
+        SkipReason::Exists => "Destination exists",
+        SkipReason::Unchanged => "Destination is up to date",
+        SkipReason::Missing => "Optional file is missing on server",
+    }
+}
+/// Creates report of list's jobs, with bandwidth accounted per group and deadlines checked
+fn list_report(files: &[Entry]) -> Report {
+    Report::with_groups(
+        files
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((i, entry.group.clone()?))),
+    )
+    .with_deadlines(
+        files
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((i, entry.deadline?))),
+    )
+}
+/// Describes how job ended, if event is job's outcome
+///
+/// # Returns
+/// Message, with flag telling whether it's an error
+fn outcome_message(status: &Progress) -> Option<(String, bool)> {
+    match status {
+        Progress::Finished(Ok(_)) => Some(("Download finished".to_owned(), false)),
+        Progress::Finished(Err(err)) => Some((format!("Download failed due to {}", err), true)),
+        Progress::Skipped(reason) => Some((format!("{}, skipped", skip_message(*reason)), false)),
+        Progress::Deferred(err) => Some((format!("Download deferred, since {}", err), true)),
+        Progress::Cancelled => Some(("Download cancelled".to_owned(), true)),
+        Progress::Changed(status) => Some((
+            format!("Remote file changed during download (HTTP {})", status),
+            true,
+        )),
+        _ => None,
+    }
+}
+/// Downloads entries of single list of `multi` command, printing their outcomes
+async fn run_manifest(manifest: &Manifest, files: &[Entry], options: Options) -> Report {
+    let (dl, mut notify) = new_downloader(files.to_vec(), Path::new(&manifest.dest_dir), options);
+    let mut report = list_report(files);
+    // Only outcomes are printed, since lines of several lists interleave
+    let notifier = async {
+        while let Some((i, src, dst, status)) = notify.next().await {
+            report.record(i, &src, &dst, &status);
+            if let Some((message, error)) = outcome_message(&status) {
+                let line = format!("[{}] #{} {} -> {}: {}", manifest.name, i, src, dst, message);
+                match error {
+                    true => eprintln!("{}", line),
+                    false => println!("{}", line),
+                }
+            }
+        }
+    };
+    futures::join!(dl, notifier);
+    report
+}
+/// Reads whole list file into string, substituting variables into it
+fn read_list_file(list_file: &str, vars: &Variables) -> Result<String> {
+    // Open file with list of files to download
+    let mut fd = std::fs::File::open(list_file)?;
+    // Then read all of its contents into buffer
+    let mut text = String::new();
+    fd.read_to_string(&mut text)?;
+    vars.substitute(&text)
+        .with_context(|| format!("list file {}", list_file))
+}
+/// Runs `probe` command: measures all hosts from list file and prints them ranked
+fn probe(config: ProbeConfig) -> Result<()> {
+    let ProbeConfig {
+        list_file,
+        checksum_algo,
+    } = config;
+    let entries = ListFormat::detect(Path::new(&list_file)).parse(
+        &read_list_file(&list_file, &Variables::default())?,
+        checksum_algo,
+        UrlMode::Encode,
+    )?;
+
+    let reports = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(probe_hosts(
+            &reqwest::Client::new(),
+            entries.iter().map(|entry| &entry.url),
+        ));
+    print!("{}", format_table(&reports));
+
+    Ok(())
+}
+/// Runs `cmp` command: compares remote file with local one
+///
+/// Like `cmp` utility, exits with code 1 if files differ
+fn cmp(config: CmpConfig) -> Result<()> {
+    let CmpConfig { url, file, quick } = config;
+    let client = reqwest::Client::new();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let same = if quick {
+        let result = runtime.block_on(compare::compare_quick(&client, &url, Path::new(&file)))?;
+        let remote_size = match result.remote_size {
+            Some(size) => size.to_string(),
+            None => "unknown".to_owned(),
+        };
+        println!("size: remote {}, local {}", remote_size, result.local_size);
+        match (&result.remote_checksum, result.checksum_matches) {
+            (Some(checksum), Some(matches)) => println!(
+                "checksum {}: {}",
+                checksum.algorithm,
+                if matches { "matches" } else { "differs" }
+            ),
+            _ => println!("checksum: not announced by server"),
+        }
+        // Sizes alone can't prove files are the same, but that's the best quick check can tell
+        result.same().unwrap_or(true)
+    } else {
+        match runtime.block_on(compare::compare(&client, &url, Path::new(&file)))? {
+            Comparison::Identical(len) => {
+                println!("identical, {} bytes", len);
+                true
+            }
+            Comparison::Differs(offset) => {
+                println!("first difference at offset {}", offset);
+                false
+            }
+        }
+    };
+    if !same {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+/// Runs `decrypt` command: decrypts file downloaded with `--encrypt-key`
+///
+/// Decrypted data goes into partial file first, which replaces output only once
+/// the whole input is authenticated
+fn decrypt(config: DecryptConfig) -> Result<()> {
+    let DecryptConfig { key, input, output } = config;
+    let part = part_path(Path::new(&output));
+    let result: Result<()> = (|| {
+        let source = std::io::BufReader::new(std::fs::File::open(&input)?);
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&part)?);
+        encrypt::decrypt(&key, source, &mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(&part, &output)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&part);
+    }
+    result.with_context(|| format!("{}: cannot decrypt", input))
+}
+/// Runs `serve-sums` command: serves checksum manifest and report of previous run
+/// until terminated
+fn serve_sums(config: ServeSumsConfig) -> Result<()> {
+    let ServeSumsConfig {
+        dest_dir,
+        report,
+        listen,
+        checksum_algo,
+        hash_db,
+    } = config;
+    let hash_db = hash_db.map(HashDb::open).transpose()?;
+    // Skipped optional entries which are missing on server have no file
+    let names: Vec<_> = completed_names(&report)?
+        .into_iter()
+        .filter(|name| Path::new(&dest_dir).join(name).is_file())
+        .collect();
+    let manifest_name = sums::manifest_name(checksum_algo);
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let manifest = sums::manifest(
+                Path::new(&dest_dir),
+                &names,
+                checksum_algo,
+                hash_db.as_ref(),
+            )
+            .await?;
+            if let Some(hash_db) = &hash_db {
+                hash_db.save()?;
+            }
+            let documents = HashMap::from([
+                (
+                    format!("/{}", manifest_name),
+                    Document {
+                        content_type: "text/plain; charset=utf-8",
+                        body: manifest.into_bytes(),
+                    },
+                ),
+                (
+                    "/report.json".to_owned(),
+                    Document {
+                        content_type: "application/json",
+                        body: std::fs::read(&report)?,
+                    },
+                ),
+            ]);
+            let listener = tokio::net::TcpListener::bind(listen).await?;
+            println!(
+                "Serving {} files: http://{}/{} and http://{}/report.json",
+                names.len(),
+                listen,
+                manifest_name,
+                listen
+            );
+            sums::serve(listener, documents).await
+        })
+}
+/// Runs `multi` command: downloads several list files at once, each with its own limit
+/// and report, on one runtime and under one overall limit split fairly between them
+fn multi(config: MultiConfig) -> Result<()> {
+    let MultiConfig {
+        manifests,
+        threads_num,
+        speed_limit,
+        borrow_bandwidth,
+        checksum_algo,
+        defines,
+    } = config;
+    let vars = Variables::new(defines);
+    // All lists are parsed upfront, so malformed one doesn't leave others half-done
+    let lists = manifests
+        .iter()
+        .map(|manifest| {
+            ListFormat::detect(Path::new(&manifest.list_file)).parse(
+                &read_list_file(&manifest.list_file, &vars)?,
+                checksum_algo,
+                UrlMode::Encode,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let shares = FairShare::split(
+        speed_limit,
+        manifests.len(),
+        borrow_bandwidth,
+        Arc::new(SystemClock),
+    );
+    let cancel = CancellationToken::new();
+    let stopped = Arc::new(OnceLock::new());
+
+    let reports = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            watch_termination(cancel.clone(), stopped.clone())?;
+            watch_interrupt(cancel.clone(), stopped.clone());
+            let runs =
+                manifests
+                    .iter()
+                    .zip(&lists)
+                    .zip(shares)
+                    .map(|((manifest, files), share)| {
+                        let options = Options {
+                            threads_num,
+                            speed_limit: manifest.limit,
+                            share: Some(share),
+                            cancel: cancel.clone(),
+                            ..Options::default()
+                        };
+                        run_manifest(manifest, files, options)
+                    });
+            Ok::<_, anyhow::Error>(futures::future::join_all(runs).await)
+        })?;
+    let interrupted = cancel.is_cancelled();
+    for ((manifest, files), report) in manifests.iter().zip(&lists).zip(reports) {
+        let pending = (0..files.len()).filter(|i| !report.started(*i)).count();
+        println!("[{}] {}", manifest.name, report.summary(pending));
+        if let Some(report_file) = &manifest.report {
+            let json = report.to_json(interrupted, pending);
+            std::fs::write(report_file, serde_json::to_string_pretty(&json)?)?;
+        }
+    }
+    if interrupted {
+        std::process::exit(Stop::of(&stopped).exit_code());
+    }
     Ok(())
 }
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+/// Extensions of well-known media types, with media type in lowercase
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("application/gzip", "gz"),
+    ("application/json", "json"),
+    ("application/javascript", "js"),
+    ("application/pdf", "pdf"),
+    ("application/wasm", "wasm"),
+    ("application/x-7z-compressed", "7z"),
+    ("application/x-bzip2", "bz2"),
+    ("application/x-compressed-tar", "tar.gz"),
+    ("application/x-gtar", "tar.gz"),
+    ("application/x-gzip", "gz"),
+    ("application/x-tar", "tar"),
+    ("application/x-xz", "xz"),
+    ("application/xml", "xml"),
+    ("application/zip", "zip"),
+    ("application/zstd", "zst"),
+    ("audio/mpeg", "mp3"),
+    ("image/gif", "gif"),
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/svg+xml", "svg"),
+    ("image/webp", "webp"),
+    ("text/css", "css"),
+    ("text/csv", "csv"),
+    ("text/html", "html"),
+    ("text/plain", "txt"),
+    ("text/xml", "xml"),
+    ("video/mp4", "mp4"),
+];
+
+/// Finds extension of files of specified media type
+///
+/// # Arguments
+/// * content_type - value of `Content-Type` header; parameters like charset are ignored
+///
+/// # Returns
+/// Extension without leading dot, or `None` if type is unknown or says nothing about data,
+/// like `application/octet-stream`
+pub fn extension(content_type: &str) -> Option<&'static str> {
+    let media_type = content_type.split(';').next()?.trim();
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(media_type))
+        .map(|(_, extension)| *extension)
+}
+/// Appends extension of specified media type to destination path which has none
+///
+/// # Returns
+/// Path with extension, or `None` if path already has one or media type is unknown
+pub fn with_extension(path: &Path, content_type: &str) -> Option<PathBuf> {
+    if path.extension().is_some() {
+        return None;
+    }
+    let mut name = path.file_name()?.to_owned();
+    name.push(".");
+    name.push(extension(content_type)?);
+    Some(path.with_file_name(name))
+}
+
+/// Finds file name suggested by server in `Content-Disposition` header
+///
+/// Extended `filename*` parameter, in UTF-8 or Latin-1, takes precedence over plain `filename`.
+/// Name is sanitized so it can't point outside of destination directory:
+/// only its last path component is kept, and control characters are dropped
+///
+/// # Returns
+/// File name, or `None` if header has none, or it's empty or just dots
+pub fn disposition_filename(value: &str) -> Option<String> {
+    let (mut plain, mut extended) = (None, None);
+    for param in split_params(value).iter().skip(1) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => extended = decode_extended(value.trim()),
+            "filename" => plain = Some(value.trim().to_owned()),
+            _ => {}
+        }
+    }
+    let name = extended.or(plain)?;
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()?
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => None,
+        name => Some(name.to_owned()),
+    }
+}
+/// Splits header value into semicolon-separated parts, unquoting quoted strings
+fn split_params(value: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let (mut quoted, mut escaped) = (false, false);
+    for c in value.chars() {
+        let part = parts.last_mut().unwrap();
+        match c {
+            _ if escaped => {
+                part.push(c);
+                escaped = false;
+            }
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => parts.push(String::new()),
+            _ => part.push(c),
+        }
+    }
+    parts
+}
+/// Decodes extended parameter value, like `UTF-8''%e2%82%ac.txt`, as in RFC 8187
+fn decode_extended(value: &str) -> Option<String> {
+    let mut pieces = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (pieces.next()?, pieces.next()?, pieces.next()?);
+    let bytes: Vec<u8> = percent_encoding::percent_decode_str(encoded).collect();
+    match charset.to_ascii_lowercase().as_str() {
+        "utf-8" => String::from_utf8(bytes).ok(),
+        "iso-8859-1" => Some(bytes.into_iter().map(char::from).collect()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disposition_filename, extension, with_extension};
+    use std::path::Path;
+
+    #[test]
+    fn extension_by_type() {
+        assert_eq!(extension("application/json"), Some("json"));
+        assert_eq!(extension("Text/HTML; charset=utf-8"), Some("html"));
+        assert_eq!(extension("application/octet-stream"), None);
+        assert_eq!(
+            with_extension(Path::new("dir/archive"), "application/x-gtar"),
+            Some(Path::new("dir/archive.tar.gz").to_owned())
+        );
+        assert_eq!(
+            with_extension(Path::new("dir/data.bin"), "application/json"),
+            None
+        );
+        assert_eq!(with_extension(Path::new("dir/data"), "foo/bar"), None);
+    }
+
+    #[test]
+    fn disposition_names() {
+        let name = |value| disposition_filename(value);
+        assert_eq!(
+            name("attachment; filename=report.pdf"),
+            Some("report.pdf".to_owned())
+        );
+        assert_eq!(
+            name(r#"attachment; filename="a \"b\"; c.txt""#),
+            Some("a \"b\"; c.txt".to_owned())
+        );
+        // Extended name wins, whatever order parameters are in
+        assert_eq!(
+            name("attachment; filename*=UTF-8''%E2%82%AC%20rates.csv; filename=rates.csv"),
+            Some("\u{20AC} rates.csv".to_owned())
+        );
+        assert_eq!(
+            name("attachment; filename*=iso-8859-1'en'%E9.txt"),
+            Some("\u{E9}.txt".to_owned())
+        );
+        // Names can't escape destination directory
+        assert_eq!(
+            name(r#"attachment; filename="../../etc/passwd""#),
+            Some("passwd".to_owned())
+        );
+        assert_eq!(name(r#"attachment; filename="C:\\x\\..""#), None);
+        assert_eq!(name("inline"), None);
+    }
+}
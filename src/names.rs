@@ -0,0 +1,176 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+/// Characters which can't be part of file name on NTFS, besides control ones
+const ILLEGAL_CHARS: &str = ":*?\"<>|";
+
+/// How characters which can't be part of file name on Windows are treated in destination names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameEncoding {
+    /// Keep names as is
+    Keep,
+    /// Replace each such character with `_`
+    Replace,
+    /// Percent-encode each such character, like `%3A`
+    Percent,
+    /// Replace each such character with its full-width lookalike, like `：`
+    Fullwidth,
+}
+
+impl NameEncoding {
+    /// All known encodings
+    pub const ALL: [NameEncoding; 4] = [
+        NameEncoding::Keep,
+        NameEncoding::Replace,
+        NameEncoding::Percent,
+        NameEncoding::Fullwidth,
+    ];
+    /// Name of encoding, as used in CLI
+    pub fn name(self) -> &'static str {
+        match self {
+            NameEncoding::Keep => "keep",
+            NameEncoding::Replace => "replace",
+            NameEncoding::Percent => "percent",
+            NameEncoding::Fullwidth => "fullwidth",
+        }
+    }
+    /// Encodes destination name, leaving directory separators as is
+    pub fn encode(self, name: &str) -> String {
+        if self == NameEncoding::Keep {
+            return name.to_owned();
+        }
+        let mut encoded = String::with_capacity(name.len());
+        for c in name.chars() {
+            if !ILLEGAL_CHARS.contains(c) && !c.is_control() {
+                encoded.push(c);
+                continue;
+            }
+            match self {
+                NameEncoding::Keep => encoded.push(c),
+                NameEncoding::Replace => encoded.push('_'),
+                NameEncoding::Percent => encoded += &format!("%{:02X}", c as u32),
+                // Full-width forms follow ASCII ones at fixed offset, control characters have none
+                NameEncoding::Fullwidth => encoded.push(match c.is_control() {
+                    true => '_',
+                    false => char::from_u32(c as u32 + 0xFEE0).unwrap_or('_'),
+                }),
+            }
+        }
+        encoded
+    }
+    /// Makes path of destination file of specified name, see `long_path`
+    pub fn dest_path(self, dest_dir: &Path, name: &str) -> PathBuf {
+        long_path(&dest_dir.join(self.encode(name)))
+    }
+}
+
+impl Default for NameEncoding {
+    /// Names are encoded only where they'd fail otherwise
+    fn default() -> NameEncoding {
+        match cfg!(windows) {
+            true => NameEncoding::Replace,
+            false => NameEncoding::Keep,
+        }
+    }
+}
+
+impl FromStr for NameEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<NameEncoding> {
+        match NameEncoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.name().eq_ignore_ascii_case(s))
+        {
+            Some(encoding) => Ok(encoding),
+            None => bail!("{}: unknown name encoding", s),
+        }
+    }
+}
+impl fmt::Display for NameEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Max length of path which Windows API accepts without long path prefix
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Makes path usable even if it's longer than Windows allows for regular paths,
+/// by turning it into absolute one with `\\?\` prefix
+///
+/// Paths which are short enough, or can't be made absolute, are left as is
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> PathBuf {
+    use std::path::{Component, Prefix};
+
+    let verbatim = matches!(
+        path.components().next(),
+        Some(Component::Prefix(prefix))
+            if matches!(prefix.kind(), Prefix::Verbatim(_) | Prefix::VerbatimDisk(_) | Prefix::VerbatimUNC(..))
+    );
+    if verbatim || path.as_os_str().len() < MAX_PATH {
+        return path.to_owned();
+    }
+    // Separators and relative components are normalized, since verbatim paths
+    // are passed to filesystem as is
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_owned();
+    };
+    let absolute = absolute.to_string_lossy().into_owned();
+    match absolute.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", absolute)),
+    }
+}
+/// Makes path usable even if it's longer than Windows allows for regular paths;
+/// other platforms have no such limit, so path is left as is
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> PathBuf {
+    path.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NameEncoding;
+    use std::path::Path;
+
+    #[test]
+    fn encode_names() {
+        let name = "dir/a:b*c?.txt";
+        assert_eq!(NameEncoding::Keep.encode(name), name);
+        assert_eq!(NameEncoding::Replace.encode(name), "dir/a_b_c_.txt");
+        assert_eq!(
+            NameEncoding::Percent.encode("a<b>|\"\n"),
+            "a%3Cb%3E%7C%22%0A"
+        );
+        assert_eq!(
+            NameEncoding::Fullwidth.encode("a:b?\t"),
+            "a\u{FF1A}b\u{FF1F}_"
+        );
+        assert_eq!(
+            "percent".parse::<NameEncoding>().unwrap(),
+            NameEncoding::Percent
+        );
+        assert!("other".parse::<NameEncoding>().is_err());
+        #[cfg(not(windows))]
+        assert_eq!(
+            NameEncoding::Replace.dest_path(Path::new("out"), "a:b"),
+            Path::new("out/a_b")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_paths() {
+        let long = format!(r"C:\{}\file", "x".repeat(300));
+        let prefixed = super::long_path(Path::new(&long));
+        assert_eq!(prefixed, Path::new(&format!(r"\\?\{}", long)));
+        assert_eq!(super::long_path(&prefixed), prefixed);
+        assert_eq!(super::long_path(Path::new(r"C:\a")), Path::new(r"C:\a"));
+    }
+}
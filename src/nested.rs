@@ -0,0 +1,367 @@
+use std::collections::HashSet;
+use std::path::{Component, Path};
+
+use anyhow::{bail, Context, Result};
+use futures::future::BoxFuture;
+use reqwest::Client;
+use url::Url;
+
+use crate::digest::Algorithm;
+use crate::list::{parse_list, ChecksumUrl, Entry, ListFormat, UrlMode};
+use crate::protocol::Protocol;
+use crate::vars::Variables;
+
+/// How deep nested lists may refer to other lists
+const MAX_DEPTH: usize = 8;
+
+/// Downloads and parses top-level list published over HTTP
+///
+/// Relative URLs of its entries are resolved against list URL, so list can be moved
+/// together with files it refers to. Like names of nested lists, names of its entries,
+/// their copies and lists they refer to must stay within destination directory
+///
+/// # Arguments
+/// * client - HTTP client, also used to download nested lists
+/// * url - URL of list
+/// * format - format of list; detected from extension in URL path if not specified
+/// * default_algo - checksum algorithm of bare digests
+/// * urls - how to treat URLs with characters which aren't allowed in URLs
+/// * vars - variables substituted into list text
+pub async fn fetch(
+    client: &Client,
+    url: &str,
+    format: Option<ListFormat>,
+    default_algo: Algorithm,
+    urls: UrlMode,
+    vars: &Variables,
+) -> Result<Vec<Entry>> {
+    let base = Url::parse(url)?;
+    let text = download(client, &base)
+        .await
+        .and_then(|text| vars.substitute(&text))
+        .with_context(|| format!("list file {}", url))?;
+    let format = format.unwrap_or_else(|| ListFormat::detect(Path::new(base.path())));
+    let entries = format.parse(&text, default_algo, urls)?;
+    for entry in &entries {
+        check_name(&entry.name)?;
+        entry.copies.iter().try_for_each(|copy| check_name(copy))?;
+    }
+    entries
+        .into_iter()
+        .map(|entry| resolve(&base, entry))
+        .collect()
+}
+/// Tells whether list source is URL rather than local path
+pub fn is_remote(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+/// Replaces nested list entries with entries of lists they refer to
+///
+/// Each nested list is downloaded and parsed, its relative URLs are resolved against list URL,
+/// and its destination names are placed under directory named by list entry.
+/// Dependencies between entries of the same nested list are prefixed the same way.
+///
+/// # Arguments
+/// * client - HTTP client used to download nested lists
+/// * entries - entries of top-level list
+/// * default_algo - checksum algorithm of bare digests in nested lists
+/// * vars - variables substituted into text of nested lists
+///
+/// # Returns
+/// Entries in list order, with nested ones in place of their lists,
+/// or error if any nested list can't be downloaded or parsed
+pub async fn expand(
+    client: &Client,
+    entries: Vec<Entry>,
+    default_algo: Algorithm,
+    vars: &Variables,
+) -> Result<Vec<Entry>> {
+    expand_level(client, entries, default_algo, vars, Vec::new()).await
+}
+/// Expands nested lists of single level
+///
+/// # Arguments
+/// * parents - URLs of lists being expanded, from top to bottom, to detect cycles
+fn expand_level<'a>(
+    client: &'a Client,
+    entries: Vec<Entry>,
+    default_algo: Algorithm,
+    vars: &'a Variables,
+    parents: Vec<String>,
+) -> BoxFuture<'a, Result<Vec<Entry>>> {
+    Box::pin(async move {
+        let mut expanded = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if !entry.list {
+                expanded.push(entry);
+                continue;
+            }
+            if parents.contains(&entry.url) {
+                bail!("nested list {} refers to itself", entry.url);
+            }
+            if parents.len() >= MAX_DEPTH {
+                bail!("nested list {} is nested too deep", entry.url);
+            }
+            let nested = fetch_list(client, &entry, default_algo, vars)
+                .await
+                .with_context(|| format!("nested list {}", entry.url))?;
+            let mut parents = parents.clone();
+            parents.push(entry.url.clone());
+            expanded.extend(expand_level(client, nested, default_algo, vars, parents).await?);
+        }
+        Ok(expanded)
+    })
+}
+/// Downloads and parses nested list, resolving its entries relative to list entry
+async fn fetch_list(
+    client: &Client,
+    list: &Entry,
+    default_algo: Algorithm,
+    vars: &Variables,
+) -> Result<Vec<Entry>> {
+    let base = Url::parse(&list.url)?;
+    let text = download(client, &base).await?;
+    let entries = parse_list(&vars.substitute(&text)?, default_algo)?;
+    // Names are checked first, so dependencies refer to entries of this list only
+    let names: HashSet<_> = entries
+        .iter()
+        .map(|entry| check_name(&entry.name).map(|_| entry.name.clone()))
+        .collect::<Result<_>>()?;
+    for entry in &entries {
+        entry.copies.iter().try_for_each(|copy| check_name(copy))?;
+    }
+    let prefix = |name: &str| format!("{}/{}", list.name.trim_end_matches('/'), name);
+    entries
+        .into_iter()
+        .map(|entry| {
+            let entry = resolve(&base, entry)?;
+            Ok(Entry {
+                name: prefix(&entry.name),
+                copies: entry.copies.iter().map(|copy| prefix(copy)).collect(),
+                after: entry
+                    .after
+                    .iter()
+                    .map(|name| match names.contains(name) {
+                        true => prefix(name),
+                        false => name.clone(),
+                    })
+                    .collect(),
+                ..entry
+            })
+        })
+        .collect()
+}
+/// Downloads list file as text
+async fn download(client: &Client, url: &Url) -> Result<String> {
+    Ok(client
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?)
+}
+/// Resolves relative URLs of entry against URL of list it comes from
+///
+/// Lists from network can't refer to local files, either as `file://` URLs or absolute paths,
+/// so that their publishers can't read files off machine which downloads them
+fn resolve(base: &Url, entry: Entry) -> Result<Entry> {
+    let join = |url: &str| -> Result<String> {
+        let resolved = base.join(url)?;
+        if Protocol::of(url) == Protocol::File || Protocol::of(resolved.as_str()) == Protocol::File
+        {
+            bail!("{}: list from network can't refer to local file", url);
+        }
+        Ok(resolved.into())
+    };
+    let checksum_url = match entry.checksum_url {
+        Some(checksum_url) => Some(ChecksumUrl {
+            url: join(&checksum_url.url)?,
+            ..checksum_url
+        }),
+        None => None,
+    };
+    Ok(Entry {
+        url: join(&entry.url)?,
+        checksum_url,
+        ..entry
+    })
+}
+/// Ensures name from list fetched over network stays within its directory
+fn check_name(name: &str) -> Result<()> {
+    let inside = Path::new(name)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    match inside {
+        true => Ok(()),
+        false => bail!("{}: name points outside of list directory", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, fetch, is_remote};
+    use crate::digest::Algorithm;
+    use crate::list::{parse_list, Entry, ListFormat, UrlMode};
+    use crate::test_utils::spawn_server;
+    use crate::vars::Variables;
+    use assert_matches::assert_matches;
+    use reqwest::Client;
+    use std::fs;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn expand_nested_lists() {
+        let src_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(src_dir.path().join("inner")).unwrap();
+        for (path, text) in [
+            ("top", "a.bin a\nlist=inner/index sub\n"),
+            ("inner/index", "${FILE}.bin b\nhttp://other/c c after=b\n"),
+            ("loop", "list=loop again\n"),
+            ("escape", "x ../x\n"),
+            ("local", "/etc/passwd passwd\n"),
+        ] {
+            fs::write(src_dir.path().join(path), text).unwrap();
+        }
+        let src_path = src_dir.path().to_owned();
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let (port, tx, jh) = spawn_server(src_path);
+                let client = Client::new();
+                let base = format!("http://127.0.0.1:{}/files", port);
+
+                let top = parse_list(
+                    &format!("http://a/1 one\nlist={}/top data\n", base),
+                    Algorithm::Md5,
+                )
+                .unwrap();
+                // Nested lists are templates too
+                let vars = Variables::new([("FILE".to_owned(), "b".to_owned())]);
+                let entries = expand(&client, top, Algorithm::Md5, &vars).await.unwrap();
+                assert_eq!(
+                    entries,
+                    [
+                        Entry::new("http://a/1", "one"),
+                        Entry::new(format!("{}/a.bin", base), "data/a"),
+                        Entry::new(format!("{}/inner/b.bin", base), "data/sub/b"),
+                        Entry {
+                            after: vec!["data/sub/b".to_owned()],
+                            ..Entry::new("http://other/c", "data/sub/c")
+                        },
+                    ]
+                );
+                // Lists referring to themselves or local files,
+                // and names escaping list directory are rejected
+                for path in ["loop", "escape", "local"] {
+                    let list = parse_list(&format!("list={}/{} dir", base, path), Algorithm::Md5);
+                    assert_matches!(
+                        expand(&client, list.unwrap(), Algorithm::Md5, &vars).await,
+                        Err(_)
+                    );
+                }
+                tx.send(()).unwrap();
+                jh.await.unwrap();
+            });
+    }
+
+    #[test]
+    fn fetch_remote_list() {
+        assert!(is_remote("https://example.com/files.lst"));
+        assert!(!is_remote("files.lst"));
+        let src_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(src_dir.path().join("pub")).unwrap();
+        fs::write(
+            src_dir.path().join("pub/files.lst"),
+            "a.bin a\n../b.bin b\nhttp://other/c c\n",
+        )
+        .unwrap();
+        fs::write(src_dir.path().join("pub/files.csv"), "a.bin,a\n").unwrap();
+        fs::write(src_dir.path().join("pub/paths.lst"), "/etc/passwd passwd\n").unwrap();
+        fs::write(src_dir.path().join("pub/up.lst"), "a.bin ../x\n").unwrap();
+        fs::write(src_dir.path().join("pub/abs.lst"), "a.bin /abs/x\n").unwrap();
+        fs::write(src_dir.path().join("pub/copies.lst"), "a.bin a dest=../x\n").unwrap();
+        fs::write(
+            src_dir.path().join("pub/lists.lst"),
+            "list=inner.lst ../x\n",
+        )
+        .unwrap();
+        fs::write(
+            src_dir.path().join("pub/urls.lst"),
+            "file:///etc/passwd passwd\n",
+        )
+        .unwrap();
+        fs::write(
+            src_dir.path().join("pub/sums.lst"),
+            "a.bin a sha256url=file:///etc/shadow\n",
+        )
+        .unwrap();
+        let src_path = src_dir.path().to_owned();
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let (port, tx, jh) = spawn_server(src_path);
+                let client = Client::new();
+                let base = format!("http://127.0.0.1:{}/files", port);
+
+                let entries = fetch(
+                    &client,
+                    &format!("{}/pub/files.lst", base),
+                    None,
+                    Algorithm::Md5,
+                    UrlMode::Encode,
+                    &Variables::default(),
+                )
+                .await
+                .unwrap();
+                assert_eq!(
+                    entries,
+                    [
+                        Entry::new(format!("{}/pub/a.bin", base), "a"),
+                        Entry::new(format!("{}/b.bin", base), "b"),
+                        Entry::new("http://other/c", "c"),
+                    ]
+                );
+                // Format is detected from URL path, unless specified explicitly
+                let csv_url = format!("{}/pub/files.csv?v=1", base);
+                let vars = Variables::default();
+                let csv = |format| {
+                    fetch(
+                        &client,
+                        &csv_url,
+                        format,
+                        Algorithm::Md5,
+                        UrlMode::Encode,
+                        &vars,
+                    )
+                };
+                assert_eq!(
+                    csv(None).await.unwrap(),
+                    [Entry::new(format!("{}/pub/a.bin", base), "a")]
+                );
+                assert_ne!(
+                    csv(Some(ListFormat::Text)).await.unwrap(),
+                    csv(None).await.unwrap()
+                );
+                // Lists from network can't refer to local files, nor write outside of destination
+                let lists = [
+                    "missing", "paths", "urls", "sums", "up", "abs", "copies", "lists",
+                ];
+                for list in lists {
+                    let url = format!("{}/pub/{}.lst", base, list);
+                    assert_matches!(
+                        fetch(&client, &url, None, Algorithm::Md5, UrlMode::Encode, &vars).await,
+                        Err(_)
+                    );
+                }
+                // Idle connections of client would hold up graceful shutdown of server
+                drop(client);
+                tx.send(()).unwrap();
+                jh.await.unwrap();
+            });
+    }
+}
@@ -0,0 +1,301 @@
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE},
+    Client, StatusCode,
+};
+use url::Url;
+
+use crate::digest::{Algorithm, Checksum};
+use crate::downloader::Source;
+
+/// URL scheme of OCI registry blobs, fetched over HTTPS
+const SCHEME: &str = "oci://";
+/// URL scheme of OCI registry blobs, fetched over plain HTTP; meant for local registries
+const SCHEME_HTTP: &str = "oci+http://";
+
+/// Reference to blob stored in OCI registry
+///
+/// Written as `oci://registry/repository@algo:digest`, i.e.
+/// `oci://ghcr.io/owner/image@sha256:0123...`
+#[derive(Debug, PartialEq, Eq)]
+pub struct BlobRef {
+    /// Registry base URL, i.e. `https://ghcr.io`
+    pub registry: String,
+    /// Repository name, may contain slashes
+    pub repository: String,
+    /// Blob digest, in `algo:hex` form
+    pub digest: String,
+}
+
+impl BlobRef {
+    /// Parses blob reference, returns `None` if URL doesn't use OCI scheme
+    pub fn parse(url: &str) -> Option<Result<BlobRef>> {
+        let (scheme, rest) = if let Some(rest) = url.strip_prefix(SCHEME) {
+            ("https", rest)
+        } else if let Some(rest) = url.strip_prefix(SCHEME_HTTP) {
+            ("http", rest)
+        } else {
+            return None;
+        };
+        Some(BlobRef::parse_parts(scheme, rest).with_context(|| format!("{}: bad OCI blob", url)))
+    }
+
+    fn parse_parts(scheme: &str, rest: &str) -> Result<BlobRef> {
+        let (registry, rest) = rest
+            .split_once('/')
+            .ok_or_else(|| anyhow!("missing repository"))?;
+        let (repository, digest) = rest
+            .rsplit_once('@')
+            .ok_or_else(|| anyhow!("missing digest"))?;
+        if registry.is_empty() || repository.is_empty() || !digest.contains(':') {
+            bail!("expected registry/repository@algo:digest");
+        }
+        Ok(BlobRef {
+            registry: format!("{}://{}", scheme, registry),
+            repository: repository.to_owned(),
+            digest: digest.to_owned(),
+        })
+    }
+    /// URL of blob itself, as defined by OCI distribution spec
+    pub fn blob_url(&self) -> String {
+        format!(
+            "{}/v2/{}/blobs/{}",
+            self.registry, self.repository, self.digest
+        )
+    }
+    /// Checksum which downloaded blob must match, since blobs are content-addressed
+    pub fn checksum(&self) -> Result<Checksum> {
+        let (algo, hex_str) = self.digest.split_once(':').unwrap_or_default();
+        Checksum::parse(algo.parse::<Algorithm>()?, hex_str)
+    }
+}
+
+/// Resolves OCI blob reference into plain HTTP source
+///
+/// Performs registry token handshake: pings registry API endpoint and,
+/// if it requests Bearer authentication, obtains anonymous pull token
+/// from advertised authorization service
+pub async fn resolve(client: &Client, blob: &BlobRef) -> Result<Source> {
+    let mut headers = HeaderMap::new();
+    let ping = client.get(format!("{}/v2/", blob.registry)).send().await?;
+    if ping.status() == StatusCode::UNAUTHORIZED {
+        let challenge = ping
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| anyhow!("registry requires authentication but sent no challenge"))?;
+        let token = fetch_token(client, challenge, &blob.repository).await?;
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        );
+    }
+
+    Ok(Source {
+        url: blob.blob_url(),
+        headers,
+        checksum: Some(blob.checksum()?),
+        ..Source::default()
+    })
+}
+/// Requests token from authorization service described by `WWW-Authenticate` challenge
+async fn fetch_token(client: &Client, challenge: &str, repository: &str) -> Result<String> {
+    let params = challenge
+        .strip_prefix("Bearer ")
+        .map(parse_challenge_params)
+        .ok_or_else(|| anyhow!("{}: unsupported authentication challenge", challenge))?;
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+
+    let mut realm = Url::parse(param("realm").ok_or_else(|| anyhow!("challenge has no realm"))?)?;
+    {
+        let mut query = realm.query_pairs_mut();
+        if let Some(service) = param("service") {
+            query.append_pair("service", service);
+        }
+        let default_scope = format!("repository:{}:pull", repository);
+        query.append_pair("scope", param("scope").unwrap_or(&default_scope));
+    }
+
+    let reply: serde_json::Value = client
+        .get(realm)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    // Docker Hub sends both fields, other registries may send only one of them
+    reply
+        .get("token")
+        .or_else(|| reply.get("access_token"))
+        .and_then(|token| token.as_str())
+        .map(str::to_owned)
+        .ok_or_else(|| anyhow!("authorization service sent no token"))
+}
+/// Splits challenge parameters like `realm="...",scope="a,b"` into key-value pairs
+fn parse_challenge_params(params: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    let mut rest = params.trim();
+    while let Some((key, tail)) = rest.split_once('=') {
+        let (value, tail) = match tail.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, tail)) => (value, tail),
+                None => (quoted, ""),
+            },
+            None => tail.split_once(',').unwrap_or((tail, "")),
+        };
+        result.push((key.trim().to_owned(), value.to_owned()));
+        rest = tail.trim_start_matches([',', ' ']);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_challenge_params, BlobRef};
+    use crate::auth::Auth;
+    use crate::downloader::{Options, Progress};
+    use crate::list::Entry;
+    use assert_matches::assert_matches;
+    use futures::StreamExt;
+    use sha2::{Digest, Sha256};
+    use tokio::runtime::Builder;
+    use tokio::sync::oneshot::channel;
+    use tokio::task::spawn;
+    use warp::{http::Response, Filter};
+
+    #[test]
+    fn parse_blob_refs() {
+        assert_matches!(BlobRef::parse("https://host/file"), None);
+        assert_eq!(
+            BlobRef::parse("oci://ghcr.io/owner/image@sha256:abcd")
+                .unwrap()
+                .unwrap(),
+            BlobRef {
+                registry: "https://ghcr.io".to_owned(),
+                repository: "owner/image".to_owned(),
+                digest: "sha256:abcd".to_owned(),
+            }
+        );
+        assert_eq!(
+            BlobRef::parse("oci+http://localhost:5000/image@sha256:abcd")
+                .unwrap()
+                .unwrap()
+                .blob_url(),
+            "http://localhost:5000/v2/image/blobs/sha256:abcd"
+        );
+        assert_matches!(BlobRef::parse("oci://ghcr.io@sha256:abcd"), Some(Err(_)));
+        assert_matches!(BlobRef::parse("oci://ghcr.io/image"), Some(Err(_)));
+        assert_matches!(BlobRef::parse("oci://ghcr.io/image@abcd"), Some(Err(_)));
+    }
+
+    #[test]
+    fn parse_challenge() {
+        assert_eq!(
+            parse_challenge_params(
+                r#"realm="https://auth.io/token",service="registry.io",scope="repository:a/b:pull,push""#
+            ),
+            [
+                ("realm".to_owned(), "https://auth.io/token".to_owned()),
+                ("service".to_owned(), "registry.io".to_owned()),
+                ("scope".to_owned(), "repository:a/b:pull,push".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn download_blob_with_token() {
+        let blob = b"layer contents".to_vec();
+        let digest = format!("sha256:{}", hex::encode(Sha256::digest(&blob)));
+        let dest_dir = tempfile::tempdir().unwrap();
+
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                // Stub registry which requires token obtained from its own auth endpoint
+                let ping =
+                    warp::path!("v2")
+                        .and(warp::header::<String>("host"))
+                        .map(|host: String| {
+                            Response::builder()
+                                .status(401)
+                                .header(
+                                    "www-authenticate",
+                                    format!(
+                                        r#"Bearer realm="http://{}/token",service="stub""#,
+                                        host
+                                    ),
+                                )
+                                .body(String::new())
+                        });
+                let token = warp::path!("token")
+                    .and(warp::query::<std::collections::HashMap<String, String>>())
+                    .map(|query: std::collections::HashMap<String, String>| {
+                        assert_eq!(query["scope"], "repository:lib/app:pull");
+                        r#"{"token":"secret"}"#
+                    });
+                let data = blob.clone();
+                let blobs = warp::path!("v2" / "lib" / "app" / "blobs" / String)
+                    .and(warp::header::optional::<String>("authorization"))
+                    .map(move |_: String, auth: Option<String>| {
+                        let status = match auth.as_deref() {
+                            Some("Bearer secret") => 200,
+                            _ => 401,
+                        };
+                        Response::builder().status(status).body(data.clone())
+                    });
+                let (tx, rx) = channel();
+                let (addr, server) = warp::serve(ping.or(token).or(blobs))
+                    .bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+                        rx.await.ok();
+                    });
+                let jh = spawn(server);
+
+                let url = format!("oci+http://127.0.0.1:{}/lib/app@{}", addr.port(), digest);
+                let download = |name: &'static str, options| {
+                    let (dl, notify) = crate::downloader::new_downloader(
+                        [Entry::new(url.clone(), name)],
+                        &dest_dir,
+                        options,
+                    );
+                    let results = spawn(notify.collect::<Vec<_>>());
+                    async move {
+                        dl.await;
+                        results.await.unwrap()
+                    }
+                };
+
+                assert_matches!(
+                    download("layer", Options::default()).await.last(),
+                    Some((_, _, _, Progress::Finished(Ok(_))))
+                );
+                assert_eq!(std::fs::read(dest_dir.path().join("layer")).unwrap(), blob);
+                // Registry host's credentials don't replace token obtained for blob
+                let options = Options {
+                    auth: Auth {
+                        credentials: Vec::new(),
+                        netrc: Some(
+                            "machine 127.0.0.1 login user password pass"
+                                .parse()
+                                .unwrap(),
+                        ),
+                    },
+                    ..Options::default()
+                };
+                assert_matches!(
+                    download("netrc", options).await.last(),
+                    Some((_, _, _, Progress::Finished(Ok(_))))
+                );
+                assert_eq!(std::fs::read(dest_dir.path().join("netrc")).unwrap(), blob);
+
+                let _ = tx.send(());
+                let _ = jh.await;
+            });
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+
+use httpdl::{downloader::Progress, failure::FailureKind, timestamp::format_timestamp};
+
+/// How notifications about download progress are printed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable messages, with progress bars on terminal
+    Text,
+    /// One JSON object per notification per line, for scripts
+    Json,
+}
+
+impl OutputFormat {
+    /// All known formats
+    pub const ALL: [OutputFormat; 2] = [OutputFormat::Text, OutputFormat::Json];
+    /// Name of format, as used in CLI
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<OutputFormat> {
+        match OutputFormat::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+        {
+            Some(format) => Ok(format),
+            None => bail!("{}: unknown output format", s),
+        }
+    }
+}
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+/// Serializes single notification as JSON object
+///
+/// # Arguments
+/// * index - entry index
+/// * url - entry URL
+/// * name - destination file name
+/// * status - notification itself
+pub fn event_json(index: usize, url: &str, name: &str, status: &Progress) -> Value {
+    let mut event = json!({
+        "index": index,
+        "url": url,
+        "destination": name,
+    });
+    match status {
+        Progress::Started => event["status"] = json!("started"),
+        Progress::Received { bytes, total } => {
+            event["status"] = json!("progress");
+            event["bytes"] = json!(bytes);
+            event["total"] = json!(total);
+        }
+        Progress::Finished(Ok(bytes)) => {
+            event["status"] = json!("finished");
+            event["bytes"] = json!(bytes);
+        }
+        Progress::Finished(Err(err)) => {
+            event["status"] = json!("failed");
+            event["error"] = json!(err.to_string());
+            event["failure"] = json!(FailureKind::classify(err).to_string());
+        }
+        Progress::Cancelled => event["status"] = json!("cancelled"),
+        Progress::Skipped(reason) => {
+            event["status"] = json!("skipped");
+            event["reason"] = json!(reason.name());
+        }
+        Progress::Deferred(err) => {
+            event["status"] = json!("deferred");
+            event["reason"] = json!(err.to_string());
+        }
+        Progress::Changed(status) => {
+            event["status"] = json!("changed");
+            event["http_status"] = json!(status);
+        }
+        Progress::Retrying {
+            attempt,
+            error,
+            delay,
+        } => {
+            event["status"] = json!("retrying");
+            event["attempt"] = json!(attempt);
+            event["error"] = json!(error.to_string());
+            event["delay"] = json!(delay.as_secs_f64());
+        }
+        Progress::Corrupted { url, error } => {
+            event["status"] = json!("corrupted");
+            event["source"] = json!(url);
+            event["error"] = json!(error.to_string());
+        }
+        Progress::Conflict { action, path } => {
+            event["status"] = json!("conflict");
+            event["action"] = json!(action.name());
+            event["path"] = json!(path.display().to_string());
+        }
+        Progress::Throttled(delay) => {
+            event["status"] = json!("throttled");
+            event["delay"] = json!(delay.as_secs_f64());
+        }
+        Progress::Resumed(stats) => {
+            event["status"] = json!("resumed");
+            event["reused"] = json!(stats.reused);
+            event["redownloaded"] = json!(stats.redownloaded);
+            event["retried"] = json!(stats.retried);
+        }
+        Progress::Relocated { path, error } => {
+            event["status"] = json!("relocated");
+            event["path"] = json!(path.display().to_string());
+            event["error"] = json!(error.to_string());
+        }
+        Progress::Saved(path) => {
+            event["status"] = json!("saved");
+            event["path"] = json!(path.display().to_string());
+        }
+        Progress::Redirected(hops) => {
+            event["status"] = json!("redirected");
+            event["redirects"] = hops
+                .iter()
+                .map(|hop| json!({ "url": hop.url, "status": hop.status }))
+                .collect();
+        }
+    }
+    event
+}
+
+/// Adds sequence number and emission time of notification to its JSON object
+///
+/// # Arguments
+/// * seq - sequence number of notification
+/// * time - time notification was emitted at, or `None` if it's left out for reproducibility
+pub fn stamp_json(event: &mut Value, seq: u64, time: Option<SystemTime>) {
+    event["seq"] = json!(seq);
+    if let Some(time) = time {
+        event["time"] = json!(format_timestamp(time));
+    }
+}
+
+/// Max number of events waiting to be written to progress descriptor
+const PIPE_QUEUE: usize = 1024;
+/// How long queued events may take to be written once progress descriptor is closed
+const PIPE_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Progress events forwarded to parent process over inherited file descriptor,
+/// like pipe it reads from
+///
+/// Each event is JSON object, as in JSON output, prefixed with its length in bytes
+/// as 4-byte big-endian number, so reader needn't scan for delimiters.
+/// Events are written by dedicated thread, so parent which reads slowly doesn't stall
+/// downloads; events which don't fit into its queue are dropped
+#[derive(Debug)]
+pub struct ProgressPipe {
+    /// Queue of serialized events; dropped once parent stops reading
+    queue: Option<SyncSender<Vec<u8>>>,
+    /// Disconnected once thread which writes events to descriptor exits
+    finished: Receiver<()>,
+}
+
+impl ProgressPipe {
+    /// Takes ownership of inherited file descriptor
+    #[cfg(unix)]
+    pub fn open(fd: i32) -> Result<ProgressPipe> {
+        use std::os::unix::io::FromRawFd;
+
+        // SAFETY: querying flags of any descriptor number is harmless
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            bail!("{}: file descriptor isn't open", fd);
+        }
+        // SAFETY: descriptor is open, and nothing else in the process uses it
+        let file = unsafe { File::from_raw_fd(fd) };
+        Ok(ProgressPipe::spawn(file))
+    }
+    /// Takes ownership of inherited file descriptor; not supported on this platform
+    #[cfg(not(unix))]
+    pub fn open(fd: i32) -> Result<ProgressPipe> {
+        bail!("{}: progress descriptors are supported only on Unix", fd)
+    }
+    /// Starts writer thread for descriptor
+    #[cfg(unix)]
+    fn spawn(mut file: File) -> ProgressPipe {
+        let (queue, events) = mpsc::sync_channel::<Vec<u8>>(PIPE_QUEUE);
+        let (done, finished) = mpsc::channel::<()>();
+        // Writer stops once write fails, e.g. because parent closed pipe
+        std::thread::spawn(move || {
+            let _done = done;
+            for message in events {
+                if file.write_all(&message).is_err() {
+                    break;
+                }
+            }
+        });
+        ProgressPipe {
+            queue: Some(queue),
+            finished,
+        }
+    }
+    /// Queues single event; it's dropped if queue is full. Once writing fails,
+    /// further events are dropped too, since download goes on without parent
+    pub fn send(&mut self, event: &Value) {
+        if let Some(queue) = &self.queue {
+            if let Err(TrySendError::Disconnected(_)) = queue.try_send(frame(event)) {
+                self.queue = None;
+            }
+        }
+    }
+}
+
+impl Drop for ProgressPipe {
+    /// Waits until queued events are written, and closes descriptor; parent which doesn't read
+    /// them in time is left with writer thread blocked, which ends along with process
+    fn drop(&mut self) {
+        self.queue = None;
+        let _ = self.finished.recv_timeout(PIPE_DRAIN_TIMEOUT);
+    }
+}
+/// Serializes event as length-prefixed message
+fn frame(event: &Value) -> Vec<u8> {
+    let body = event.to_string();
+    let mut message = Vec::with_capacity(body.len() + 4);
+    message.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    message.extend_from_slice(body.as_bytes());
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{event_json, frame, stamp_json, OutputFormat};
+    use anyhow::anyhow;
+    use httpdl::downloader::Progress;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    #[test]
+    fn serialize_events() {
+        assert_eq!(OutputFormat::from_str("JSON").unwrap(), OutputFormat::Json);
+        assert!(OutputFormat::from_str("xml").is_err());
+        assert_eq!(
+            event_json(1, "http://a/f", "f", &Progress::Finished(Ok(10))),
+            json!({
+                "index": 1,
+                "url": "http://a/f",
+                "destination": "f",
+                "status": "finished",
+                "bytes": 10,
+            })
+        );
+        assert_eq!(
+            event_json(
+                2,
+                "http://a/g",
+                "g",
+                &Progress::Finished(Err(anyhow!("boom")))
+            ),
+            json!({
+                "index": 2,
+                "url": "http://a/g",
+                "destination": "g",
+                "status": "failed",
+                "error": "boom",
+                "failure": "other",
+            })
+        );
+        let mut event = event_json(3, "http://a/h", "h", &Progress::Started);
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        stamp_json(&mut event, 7, Some(time));
+        assert_eq!(event["seq"], 7);
+        assert_eq!(event["time"], "2023-11-14T22:13:20.000Z");
+        let mut event = event_json(3, "http://a/h", "h", &Progress::Started);
+        stamp_json(&mut event, 8, None);
+        assert!(event.get("time").is_none());
+    }
+
+    #[test]
+    fn length_prefixed_events() {
+        let event = json!({"index": 0, "status": "started"});
+        let message = frame(&event);
+        assert_eq!(message[..4], (message.len() as u32 - 4).to_be_bytes());
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&message[4..]).unwrap(),
+            event
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn progress_pipe() {
+        use super::{ProgressPipe, PIPE_DRAIN_TIMEOUT, PIPE_QUEUE};
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let mut reader = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+        let mut pipe = ProgressPipe::open(fds[1]).unwrap();
+        let event = event_json(3, "http://a/f", "f", &Progress::Started);
+        pipe.send(&event);
+        drop(pipe);
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).unwrap();
+        assert_eq!(received, frame(&event));
+
+        // Parent which doesn't read doesn't block sender, whose events are dropped instead
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let reader = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+        let mut pipe = ProgressPipe::open(fds[1]).unwrap();
+        for _ in 0..PIPE_QUEUE * 10 {
+            pipe.send(&event);
+        }
+        drop(reader);
+        drop(pipe);
+
+        // Parent which keeps descriptor open but doesn't read doesn't block exit either
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let _reader = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+        let mut pipe = ProgressPipe::open(fds[1]).unwrap();
+        for _ in 0..PIPE_QUEUE * 10 {
+            pipe.send(&event);
+        }
+        let started = std::time::Instant::now();
+        drop(pipe);
+        assert!(started.elapsed() < PIPE_DRAIN_TIMEOUT * 2);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::HeaderMap;
+
+use crate::clock::Clock;
+
+/// Reset values above this are absolute UNIX timestamps rather than number of seconds
+const EPOCH_THRESHOLD: u64 = 1_000_000_000;
+
+/// Pace of requests to single host
+#[derive(Clone, Copy, Debug)]
+struct HostPace {
+    /// Earliest time when next request may be sent
+    next: Instant,
+    /// Interval between consecutive requests
+    interval: Duration,
+}
+
+/// Paces requests to each host according to rate limit headers it sends,
+/// i.e. `RateLimit-Remaining` and `RateLimit-Reset` or their `X-` prefixed variants,
+/// so requests are spread over the rate limit window instead of tripping 429 responses
+#[derive(Debug)]
+pub struct Pacing {
+    hosts: Mutex<HashMap<String, HostPace>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Pacing {
+    /// Creates pacing which measures time with specified clock
+    pub fn new(clock: Arc<dyn Clock>) -> Pacing {
+        Pacing {
+            hosts: Mutex::new(HashMap::new()),
+            clock,
+        }
+    }
+    /// Reserves time slot for next request to host
+    ///
+    /// # Returns
+    /// How long request must wait before it's sent; zero for hosts which announced no limits
+    pub fn reserve(&self, host: &str) -> Duration {
+        let now = self.clock.now();
+        let mut hosts = self.hosts.lock().unwrap();
+        let Some(pace) = hosts.get_mut(host) else {
+            return Duration::ZERO;
+        };
+        let slot = pace.next.max(now);
+        pace.next = slot + pace.interval;
+        slot - now
+    }
+    /// Adjusts pace of requests to host according to rate limit headers of its response
+    pub fn update(&self, host: &str, headers: &HeaderMap) {
+        let Some((remaining, reset)) = rate_limit(headers) else {
+            return;
+        };
+        let now = self.clock.now();
+        let pace = match remaining {
+            // Quota is exhausted, nothing can be sent until window resets
+            0 => HostPace {
+                next: now + reset,
+                interval: Duration::ZERO,
+            },
+            // Spread remaining quota evenly over the rest of window
+            remaining => {
+                let interval = reset / remaining.try_into().unwrap_or(u32::MAX);
+                HostPace {
+                    next: now + interval,
+                    interval,
+                }
+            }
+        };
+        let mut hosts = self.hosts.lock().unwrap();
+        // Slots already reserved by other requests are kept
+        let next = hosts
+            .get(host)
+            .map_or(pace.next, |old| old.next.max(pace.next));
+        hosts.insert(host.to_owned(), HostPace { next, ..pace });
+    }
+}
+/// Extracts number of remaining requests and time until rate limit window resets
+fn rate_limit(headers: &HeaderMap) -> Option<(u64, Duration)> {
+    let header = |names: [&str; 2]| {
+        names.iter().find_map(|name| {
+            let value = headers.get(*name)?.to_str().ok()?;
+            // Structured field form may carry parameters after semicolon
+            value.split(';').next()?.trim().parse::<u64>().ok()
+        })
+    };
+    let remaining = header(["ratelimit-remaining", "x-ratelimit-remaining"])?;
+    let reset = header(["ratelimit-reset", "x-ratelimit-reset"])?;
+    let reset = match reset {
+        reset if reset > EPOCH_THRESHOLD => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+            Duration::from_secs(reset).saturating_sub(now)
+        }
+        reset => Duration::from_secs(reset),
+    };
+    Some((remaining, reset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pacing;
+    use crate::clock::ManualClock;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn headers(fields: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in fields {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn pace_requests() {
+        let clock = Arc::new(ManualClock::new());
+        let pacing = Pacing::new(clock.clone());
+        // Hosts without limits aren't paced
+        pacing.update("a", &headers(&[("content-length", "1")]));
+        assert_eq!(pacing.reserve("a"), Duration::ZERO);
+        assert_eq!(pacing.reserve("a"), Duration::ZERO);
+        // Remaining quota is spread over window
+        pacing.update(
+            "a",
+            &headers(&[("x-ratelimit-remaining", "4"), ("x-ratelimit-reset", "2")]),
+        );
+        assert_eq!(pacing.reserve("a"), Duration::from_millis(500));
+        assert_eq!(pacing.reserve("a"), Duration::from_millis(1000));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(pacing.reserve("a"), Duration::from_millis(500));
+        assert_eq!(pacing.reserve("b"), Duration::ZERO);
+        // Exhausted quota blocks requests until window resets
+        pacing.update(
+            "b",
+            &headers(&[("ratelimit-remaining", "0"), ("ratelimit-reset", "3")]),
+        );
+        assert_eq!(pacing.reserve("b"), Duration::from_secs(3));
+        assert_eq!(pacing.reserve("b"), Duration::from_secs(3));
+    }
+}
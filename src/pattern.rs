@@ -0,0 +1,260 @@
+use anyhow::{bail, Context, Result};
+
+use crate::list::url_file_name;
+
+/// Max number of strings single pattern may expand into, so typo in range bounds
+/// doesn't turn into runaway list
+const MAX_EXPANSIONS: usize = 1_000_000;
+
+/// Piece of pattern
+enum Part {
+    /// Text taken as is
+    Literal(String),
+    /// Values, one of which is picked for each expansion
+    Set(Vec<String>),
+}
+
+/// Expands pattern into every string it stands for
+///
+/// Pattern may contain sets of alternatives like `{a,b,c}` and numeric ranges like `{1..10}`;
+/// range bound with leading zero, like `{001..999}`, pads values with zeros to its width.
+/// Braces which enclose neither, like `{a}`, are taken literally. Sets can't be nested
+///
+/// # Returns
+/// Each expansion with values picked for each set of pattern, in order.
+/// The last set varies fastest, like the last digit of a number
+pub fn expand(pattern: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let parts = parse(pattern)?;
+    let total = parts
+        .iter()
+        .try_fold(1usize, |total, part| match part {
+            Part::Literal(_) => Some(total),
+            Part::Set(values) => total.checked_mul(values.len()),
+        })
+        .filter(|&total| total <= MAX_EXPANSIONS);
+    if total.is_none() {
+        bail!(
+            "{}: pattern expands into more than {} strings",
+            pattern,
+            MAX_EXPANSIONS
+        );
+    }
+    let mut expansions = vec![(String::new(), Vec::new())];
+    for part in parts {
+        expansions = match part {
+            Part::Literal(text) => expansions
+                .into_iter()
+                .map(|(expanded, picked)| (expanded + &text, picked))
+                .collect(),
+            Part::Set(values) => expansions
+                .iter()
+                .flat_map(|(expanded, picked)| {
+                    values.iter().map(move |value| {
+                        let mut picked = picked.clone();
+                        picked.push(value.clone());
+                        (format!("{}{}", expanded, value), picked)
+                    })
+                })
+                .collect(),
+        };
+    }
+    Ok(expansions)
+}
+/// Expands URL pattern of entry, generating destination name for each URL
+///
+/// # Arguments
+/// * url - source URL, possibly with sets and ranges, see `expand`
+/// * name - destination name; `#1`, `#2` and so on are replaced with value picked
+///   for the corresponding set of URL. If URL has sets but name refers to none of them,
+///   name is directory, and files in it are named after the last segment of their URLs
+///
+/// # Returns
+/// Source URL and destination name of each expansion, or just the ones specified
+/// if URL has no sets
+pub fn expand_entry(url: &str, name: &str) -> Result<Vec<(String, String)>> {
+    let expansions = expand(url)?;
+    if expansions.iter().all(|(_, picked)| picked.is_empty()) {
+        return Ok(vec![(url.to_owned(), name.to_owned())]);
+    }
+    let refers = name
+        .split('#')
+        .skip(1)
+        .any(|tail| tail.starts_with(|c: char| c.is_ascii_digit()));
+    expansions
+        .into_iter()
+        .map(|(url, picked)| {
+            let name = match refers {
+                true => substitute(name, &picked)?,
+                false => {
+                    let file = url_file_name(&url)
+                        .with_context(|| format!("{}: URL has no file name", url))?;
+                    match name.trim_end_matches('/') {
+                        "" => file,
+                        dir => format!("{}/{}", dir, file),
+                    }
+                }
+            };
+            Ok((url, name))
+        })
+        .collect()
+}
+/// Splits pattern into literal text and sets
+fn parse(pattern: &str) -> Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        literal.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let inner = after
+            .find('}')
+            .map(|end| &after[..end])
+            .filter(|inner| !inner.contains('{'));
+        match inner.map(parse_set).transpose()?.flatten() {
+            Some(values) => {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+                parts.push(Part::Set(values));
+                rest = &after[inner.unwrap_or_default().len() + 1..];
+            }
+            None => {
+                literal.push('{');
+                rest = after;
+            }
+        }
+    }
+    literal.push_str(rest);
+    parts.push(Part::Literal(literal));
+    Ok(parts)
+}
+/// Parses contents of braces as set of alternatives or numeric range
+///
+/// # Returns
+/// Values of set, or `None` if braces enclose neither
+fn parse_set(inner: &str) -> Result<Option<Vec<String>>> {
+    if inner.contains(',') {
+        return Ok(Some(inner.split(',').map(str::to_owned).collect()));
+    }
+    let Some((first, last)) = inner.split_once("..") else {
+        return Ok(None);
+    };
+    let is_number = |bound: &str| !bound.is_empty() && bound.chars().all(|c| c.is_ascii_digit());
+    if !is_number(first) || !is_number(last) {
+        return Ok(None);
+    }
+    let (from, to): (u64, u64) = (first.parse()?, last.parse()?);
+    if from.abs_diff(to) >= MAX_EXPANSIONS as u64 {
+        bail!("{{{}}}: range is too large", inner);
+    }
+    let width = match first.starts_with('0') || last.starts_with('0') {
+        true => first.len().max(last.len()),
+        false => 0,
+    };
+    let values: Vec<u64> = match from <= to {
+        true => (from..=to).collect(),
+        false => (to..=from).rev().collect(),
+    };
+    Ok(Some(
+        values
+            .into_iter()
+            .map(|value| format!("{:0width$}", value, width = width))
+            .collect(),
+    ))
+}
+/// Replaces references like `#1` in name with values picked for sets
+fn substitute(name: &str, picked: &[String]) -> Result<String> {
+    let mut substituted = String::new();
+    let mut rest = name;
+    while let Some(pos) = rest.find('#') {
+        substituted.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let digits = after
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(after.len());
+        match &after[..digits] {
+            "" => substituted.push('#'),
+            num => {
+                let value = num
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|num| num.checked_sub(1))
+                    .and_then(|index| picked.get(index))
+                    .with_context(|| {
+                        format!(
+                            "{}: name refers to #{}, but URL has {} sets",
+                            name,
+                            num,
+                            picked.len()
+                        )
+                    })?;
+                substituted.push_str(value);
+            }
+        }
+        rest = &after[digits..];
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, expand_entry};
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn expand_patterns() {
+        let strings = |pattern| {
+            expand(pattern)
+                .unwrap()
+                .into_iter()
+                .map(|(expanded, _)| expanded)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strings("http://host/a"), ["http://host/a"]);
+        assert_eq!(strings("x{a,b,c}y"), ["xay", "xby", "xcy"]);
+        assert_eq!(strings("img_{8..10}"), ["img_8", "img_9", "img_10"]);
+        assert_eq!(strings("{098..100}"), ["098", "099", "100"]);
+        assert_eq!(strings("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(strings("{a,b}{1..2}"), ["a1", "a2", "b1", "b2"]);
+        // Braces which aren't sets are kept
+        assert_eq!(strings("{a}{x..y}{"), ["{a}{x..y}{"]);
+        assert_eq!(
+            expand("{a,b}/{1..2}").unwrap()[3],
+            ("b/2".to_owned(), vec!["b".to_owned(), "2".to_owned()])
+        );
+        assert_matches!(expand("{1..999999}{1..999999}"), Err(_));
+        assert_matches!(expand("{0..99999999999}"), Err(_));
+    }
+
+    #[test]
+    fn expand_entries() {
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(url, name)| (url.to_string(), name.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            expand_entry("http://host/a#b", "a#1").unwrap(),
+            pairs(&[("http://host/a#b", "a#1")])
+        );
+        assert_eq!(
+            expand_entry("http://host/{x,y}/img_{01..02}.jpg", "#1-#2.jpg").unwrap(),
+            pairs(&[
+                ("http://host/x/img_01.jpg", "x-01.jpg"),
+                ("http://host/x/img_02.jpg", "x-02.jpg"),
+                ("http://host/y/img_01.jpg", "y-01.jpg"),
+                ("http://host/y/img_02.jpg", "y-02.jpg"),
+            ])
+        );
+        // Name without references is directory for files named after URLs
+        assert_eq!(
+            expand_entry("http://host/{a,b}.bin?v=1", "dir/").unwrap(),
+            pairs(&[
+                ("http://host/a.bin?v=1", "dir/a.bin"),
+                ("http://host/b.bin?v=1", "dir/b.bin")
+            ])
+        );
+        assert_matches!(expand_entry("http://host/{a,b}", "#3"), Err(_));
+        assert_matches!(expand_entry("http://{a,b}", "dir"), Err(_));
+    }
+}
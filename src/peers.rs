@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use reqwest::{Client, StatusCode};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use url::Url;
+
+use crate::sums::{read_request, write_head};
+
+/// Service type under which instances advertise themselves
+const SERVICE_TYPE: &str = "_httpdl._tcp.local.";
+/// How long peer may take to tell whether it has file
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Instances of httpdl on local network, which share files they've downloaded
+///
+/// Each instance advertises itself over mDNS and serves files it has completed during the run,
+/// by their source URL. Jobs ask known peers for their file before going to its origin.
+/// Peers aren't trusted to serve the same data as origin, so only entries with checksums
+/// are fetched from them
+pub struct Peers {
+    /// Socket to serve completed files on, taken by `serve`
+    listener: Mutex<Option<std::net::TcpListener>>,
+    /// Base URLs of discovered peers, by their service name
+    known: Arc<Mutex<HashMap<String, String>>>,
+    /// Files completed by this instance, by source URL
+    completed: Mutex<HashMap<String, PathBuf>>,
+    /// mDNS responder, if instance advertises itself
+    daemon: Option<ServiceDaemon>,
+}
+
+impl Peers {
+    /// Creates instance which serves on specified socket, with no peers known yet
+    pub fn new(listener: std::net::TcpListener) -> Peers {
+        Peers {
+            listener: Mutex::new(Some(listener)),
+            known: Arc::default(),
+            completed: Mutex::default(),
+            daemon: None,
+        }
+    }
+    /// Starts advertising instance over mDNS and discovering other ones
+    ///
+    /// # Arguments
+    /// * port - port to serve files on; 0 picks any free one
+    pub fn start(port: u16) -> Result<Peers> {
+        let listener = std::net::TcpListener::bind(("0.0.0.0", port))?;
+        let port = listener.local_addr()?.port();
+        let daemon = ServiceDaemon::new()?;
+        let name = format!("httpdl-{}-{}", std::process::id(), port);
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            &name,
+            &format!("{}.local.", name),
+            "",
+            port,
+            None::<HashMap<String, String>>,
+        )?
+        .enable_addr_auto();
+        let own_name = info.get_fullname().to_owned();
+        daemon.register(info)?;
+        let events = daemon.browse(SERVICE_TYPE)?;
+        let mut peers = Peers::new(listener);
+        peers.daemon = Some(daemon);
+        // Events stop once daemon shuts down
+        let known = peers.known.clone();
+        std::thread::spawn(move || {
+            while let Ok(event) = events.recv() {
+                match event {
+                    ServiceEvent::ServiceResolved(info) if info.get_fullname() != own_name => {
+                        let Some(addr) = info.get_addresses_v4().into_iter().next().copied() else {
+                            continue;
+                        };
+                        let base = format!("http://{}:{}", addr, info.get_port());
+                        known
+                            .lock()
+                            .unwrap()
+                            .insert(info.get_fullname().to_owned(), base);
+                    }
+                    ServiceEvent::ServiceRemoved(_, name) => {
+                        known.lock().unwrap().remove(&name);
+                    }
+                    _ => {}
+                }
+            }
+        });
+        Ok(peers)
+    }
+    /// Adds peer which isn't discovered over mDNS
+    ///
+    /// # Arguments
+    /// * base - base URL of peer, like `http://192.168.1.10:5000`
+    pub fn add_peer(&self, base: impl Into<String>) {
+        let base = base.into();
+        self.known.lock().unwrap().insert(base.clone(), base);
+    }
+    /// Makes file downloaded from specified URL available to peers
+    pub fn complete(&self, url: &str, path: PathBuf) {
+        self.completed.lock().unwrap().insert(url.to_owned(), path);
+    }
+    /// Finds peer which has file downloaded from specified URL
+    ///
+    /// # Returns
+    /// URL to download file from peer, or `None` if no peer has it
+    pub async fn locate(&self, client: &Client, url: &str) -> Option<String> {
+        let known: Vec<_> = self.known.lock().unwrap().values().cloned().collect();
+        for base in known {
+            let Ok(peer_url) = Url::parse_with_params(&format!("{}/file", base), [("url", url)])
+            else {
+                continue;
+            };
+            let response = client
+                .head(peer_url.clone())
+                .timeout(LOOKUP_TIMEOUT)
+                .send()
+                .await;
+            if response.is_ok_and(|response| response.status() == StatusCode::OK) {
+                return Some(peer_url.into());
+            }
+        }
+        None
+    }
+    /// Serves completed files to peers, until future is dropped
+    ///
+    /// Only `GET` and `HEAD` requests for `/file?url=<source URL>` are served
+    pub async fn serve(self: Arc<Self>) -> Result<()> {
+        let Some(listener) = self.listener.lock().unwrap().take() else {
+            return Ok(());
+        };
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let peers = self.clone();
+            // Misbehaving peer affects only its own connection
+            tokio::spawn(async move {
+                let _ = peers.respond(stream).await;
+            });
+        }
+    }
+    /// Reads single request from peer and answers it
+    async fn respond(&self, mut stream: TcpStream) -> Result<()> {
+        let Some((method, target)) = read_request(&mut stream).await? else {
+            return Ok(());
+        };
+        let url = Url::parse("http://peer")?.join(&target)?;
+        let source = url
+            .query_pairs()
+            .find(|(name, _)| name == "url")
+            .map(|(_, value)| value.into_owned());
+        let path = match (url.path(), source) {
+            ("/file", Some(source)) => self.completed.lock().unwrap().get(&source).cloned(),
+            _ => None,
+        };
+        let file = match path {
+            Some(path) => tokio::fs::File::open(path).await.ok(),
+            None => None,
+        };
+        match (method.as_str(), file) {
+            ("GET" | "HEAD", Some(mut file)) => {
+                let len = file.metadata().await?.len();
+                write_head(&mut stream, "200 OK", "application/octet-stream", len).await?;
+                if method == "GET" {
+                    tokio::io::copy(&mut file, &mut stream).await?;
+                }
+            }
+            ("GET" | "HEAD", None) => {
+                write_head(&mut stream, "404 Not Found", "text/plain", 0).await?;
+            }
+            _ => write_head(&mut stream, "405 Method Not Allowed", "text/plain", 0).await?,
+        }
+        stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Peers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Peers")
+            .field("known", &self.known.lock().unwrap().len())
+            .field("completed", &self.completed.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl Drop for Peers {
+    fn drop(&mut self) {
+        if let Some(daemon) = &self.daemon {
+            let _ = daemon.shutdown();
+        }
+    }
+}
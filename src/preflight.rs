@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use futures::StreamExt;
+use reqwest::Client;
+
+use crate::clobber::Clobber;
+use crate::list::Entry;
+
+/// Learns sizes of entries which list doesn't specify, with HEAD request per entry
+///
+/// # Arguments
+/// * client - HTTP client which follows redirects
+/// * files - entries of the run, with their indices
+/// * concurrency - max number of simultaneous requests
+///
+/// # Returns
+/// Sizes reported by servers, by entry index. Entries whose server doesn't report size,
+/// or which can't be requested with plain HTTP, are omitted; failures are left to jobs themselves
+pub async fn learn_sizes(
+    client: &Client,
+    files: &[(usize, Entry)],
+    concurrency: usize,
+) -> HashMap<usize, u64> {
+    let unknown = files.iter().filter(|(_, entry)| {
+        entry.size.is_none()
+            && !entry.list
+            && (entry.url.starts_with("http://") || entry.url.starts_with("https://"))
+    });
+    futures::stream::iter(unknown)
+        .map(|(i, entry)| async move {
+            let response = client.head(&entry.url).send().await.ok()?;
+            // Body of HEAD response is empty, so length is taken from header itself
+            let len = response
+                .error_for_status()
+                .ok()?
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)?
+                .to_str()
+                .ok()?
+                .parse()
+                .ok()?;
+            Some((*i, len))
+        })
+        .buffer_unordered(concurrency.max(1))
+        .filter_map(|size| async move { size })
+        .collect()
+        .await
+}
+/// Finds how much data entry has yet to write, given files left by previous runs
+///
+/// # Arguments
+/// * size - entry's full size
+/// * dest - entry's destination path
+/// * part - entry's partial file, whose data is resumed from
+/// * clobber - how existing destination is treated
+///
+/// # Returns
+/// Zero if existing destination is kept, otherwise size less partial data,
+/// and less destination itself if it's replaced
+pub fn remaining(size: u64, dest: &Path, part: &Path, clobber: Clobber) -> u64 {
+    let len = |path: &Path| std::fs::metadata(path).map_or(0, |meta| meta.len());
+    match (dest.exists(), clobber) {
+        (true, Clobber::Skip) => 0,
+        (true, Clobber::Overwrite) => size.saturating_sub(len(part) + len(dest)),
+        _ => size.saturating_sub(len(part)),
+    }
+}
+/// Error of filesystem not having enough free space for data about to be written
+#[derive(Clone, Debug)]
+pub struct NoSpace {
+    /// Directory where data would be written
+    pub dir: PathBuf,
+    /// Number of bytes to write
+    pub needed: u64,
+    /// Number of bytes available
+    pub available: u64,
+}
+
+impl fmt::Display for NoSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "not enough free space in {}: {} bytes needed, {} available",
+            self.dir.display(),
+            self.needed,
+            self.available
+        )
+    }
+}
+
+impl std::error::Error for NoSpace {}
+
+/// Checks that directory's filesystem has enough free space for specified amount of data
+///
+/// Passes if free space can't be determined on this platform
+pub fn check_space(dir: &Path, needed: u64) -> Result<(), NoSpace> {
+    // Relative path of file in current directory has empty parent
+    let dir = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    match available_space(dir) {
+        Some(available) if available < needed => Err(NoSpace {
+            dir: dir.to_owned(),
+            needed,
+            available,
+        }),
+        _ => Ok(()),
+    }
+}
+/// Finds amount of disk space occupied by file, including space reserved by `preallocate`
+#[cfg(unix)]
+pub fn allocated(meta: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    // Block count is in 512-byte units, regardless of filesystem block size
+    meta.blocks() * 512
+}
+/// Finds amount of disk space occupied by file, including space reserved by `preallocate`
+#[cfg(not(unix))]
+pub fn allocated(meta: &Metadata) -> u64 {
+    meta.len()
+}
+/// Finds amount of space available to unprivileged user on filesystem of specified path
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: path is valid NUL-terminated string, and stat is written by successful call
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+/// Finds amount of space available to unprivileged user on filesystem of specified path
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+/// Reserves disk space for file of specified length, without changing its size,
+/// so data appended later doesn't fragment and can't run out of space midway
+///
+/// Reservation is just an optimization, so filesystems which don't support it are fine
+#[cfg(target_os = "linux")]
+pub fn preallocate(file: &tokio::fs::File, len: u64) {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: descriptor belongs to open file, which outlives the call
+    unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            0,
+            len as libc::off_t,
+        );
+    }
+}
+/// Reserves disk space for file of specified length, without changing its size,
+/// so data appended later doesn't fragment and can't run out of space midway
+///
+/// Reservation is just an optimization, so filesystems which don't support it are fine
+#[cfg(not(target_os = "linux"))]
+pub fn preallocate(_file: &tokio::fs::File, _len: u64) {}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_space, learn_sizes, remaining};
+    use crate::clobber::Clobber;
+    use crate::list::Entry;
+    use crate::test_utils::{spawn_server, write_random_file};
+    use reqwest::Client;
+    use std::collections::HashMap;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn batch_sizes() {
+        let src_dir = tempfile::tempdir().unwrap();
+        write_random_file(&src_dir.path().join("sample"), 1000);
+        let src_path = src_dir.path().to_owned();
+        #[cfg(unix)]
+        {
+            assert!(check_space(src_dir.path(), 1).is_ok());
+            assert!(check_space(src_dir.path(), u64::MAX).is_err());
+        }
+        // Data left by previous runs is subtracted, unless it's replaced anyway
+        let dest = src_dir.path().join("sample");
+        let part = src_dir.path().join("sample.part");
+        std::fs::write(&part, [0; 300]).unwrap();
+        assert_eq!(remaining(1500, &dest, &part, Clobber::Skip), 0);
+        assert_eq!(remaining(1500, &dest, &part, Clobber::Overwrite), 200);
+        assert_eq!(remaining(1500, &dest, &part, Clobber::Rename), 1200);
+        let other = src_dir.path().join("other");
+        assert_eq!(remaining(1500, &other, &other, Clobber::Skip), 1500);
+
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let (port, tx, jh) = spawn_server(src_path);
+                let url = |name| format!("http://127.0.0.1:{}/files/{}", port, name);
+                let files = [
+                    (0, Entry::new(url("sample"), "a")),
+                    (
+                        1,
+                        Entry {
+                            size: Some(5),
+                            ..Entry::new(url("sample"), "b")
+                        },
+                    ),
+                    (2, Entry::new(url("missing"), "c")),
+                    (3, Entry::new("oci://registry/repo@sha256:00", "d")),
+                ];
+                // Only sizes not known from list are requested
+                let sizes = learn_sizes(&Client::new(), &files, 2).await;
+                assert_eq!(sizes, HashMap::from([(0, 1000)]));
+
+                tx.send(()).unwrap();
+                jh.await.unwrap();
+            });
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures::future::join_all;
+use reqwest::{
+    header::{ACCEPT_RANGES, RANGE},
+    Client, StatusCode, Version,
+};
+use url::Url;
+
+/// Number of bytes requested from each host to measure throughput
+pub const PROBE_SIZE: usize = 256 * 1024;
+
+/// Measured properties of single host
+#[derive(Debug)]
+pub struct HostReport {
+    /// Host origin, i.e. scheme, host name and port
+    pub origin: String,
+    /// Result of probing, either measurements or failure reason
+    pub result: Result<HostStats>,
+}
+
+/// Measurements of successfully probed host
+#[derive(Debug)]
+pub struct HostStats {
+    /// Time between sending request and receiving response headers
+    pub latency: Duration,
+    /// Download speed of probe request body, in bytes per second
+    pub throughput: f64,
+    /// Whether host honors Range requests
+    pub ranges: bool,
+    /// Whether host talks HTTP/2
+    pub http2: bool,
+}
+
+/// Probes every distinct host among specified URLs
+///
+/// # Arguments
+/// * client - HTTP client requests are sent with; HTTP/2 is negotiated over TLS
+/// * urls - sequence of URLs, usually taken from list file
+///
+/// # Returns
+/// Reports for all hosts, ranked from best to worst:
+/// reachable hosts ordered by throughput, then by latency, followed by failed ones
+///
+/// For each host, the first URL pointing to it is used for measurements.
+/// Hosts are probed concurrently, with single small ranged GET request each.
+pub async fn probe_hosts(
+    client: &Client,
+    urls: impl IntoIterator<Item = impl AsRef<str>>,
+) -> Vec<HostReport> {
+    // Pick first URL for each distinct origin, keeping manifest order
+    let mut origins = HashSet::new();
+    let mut samples = Vec::new();
+    for url in urls {
+        let origin = match Url::parse(url.as_ref()) {
+            Ok(url) => url.origin().ascii_serialization(),
+            Err(_) => continue,
+        };
+        if origins.insert(origin.clone()) {
+            samples.push((origin, url.as_ref().to_owned()));
+        }
+    }
+
+    let mut reports = join_all(samples.into_iter().map(|(origin, url)| {
+        let client = client.clone();
+        async move {
+            HostReport {
+                origin,
+                result: probe_url(&client, &url).await,
+            }
+        }
+    }))
+    .await;
+
+    reports.sort_by(|a, b| match (&a.result, &b.result) {
+        (Ok(a), Ok(b)) => b
+            .throughput
+            .total_cmp(&a.throughput)
+            .then(a.latency.cmp(&b.latency)),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+    });
+    reports
+}
+/// Performs single ranged request and measures host properties from it
+async fn probe_url(client: &Client, url: &str) -> Result<HostStats> {
+    let started = Instant::now();
+    let response = client
+        .get(url)
+        .header(RANGE, format!("bytes=0-{}", PROBE_SIZE - 1))
+        .send()
+        .await?
+        .error_for_status()?;
+    let latency = started.elapsed();
+
+    let ranges = response.status() == StatusCode::PARTIAL_CONTENT
+        || response
+            .headers()
+            .get(ACCEPT_RANGES)
+            .is_some_and(|value| value == "bytes");
+    let http2 = response.version() == Version::HTTP_2;
+    // Server may ignore range, so don't read more than probe size in any case
+    let mut response = response;
+    let mut received = 0usize;
+    let body_started = Instant::now();
+    while received < PROBE_SIZE {
+        match response.chunk().await? {
+            Some(chunk) => received += chunk.len(),
+            None => break,
+        }
+    }
+    let elapsed = body_started.elapsed().as_secs_f64();
+    let throughput = if elapsed > 0.0 {
+        received as f64 / elapsed
+    } else {
+        0.0
+    };
+
+    Ok(HostStats {
+        latency,
+        throughput,
+        ranges,
+        http2,
+    })
+}
+/// Formats host reports as human-readable table, one row per host
+pub fn format_table(reports: &[HostReport]) -> String {
+    let width = reports
+        .iter()
+        .map(|report| report.origin.len())
+        .chain(Some("HOST".len()))
+        .max()
+        .unwrap_or(0);
+    let mut table = String::new();
+    let _ = writeln!(
+        table,
+        "{:>4}  {:<width$}  {:>10}  {:>12}  {:>5}  {:>5}",
+        "RANK", "HOST", "LATENCY", "THROUGHPUT", "RANGE", "HTTP2"
+    );
+    for (rank, report) in reports.iter().enumerate() {
+        let _ = match &report.result {
+            Ok(stats) => writeln!(
+                table,
+                "{:>4}  {:<width$}  {:>8}ms  {:>10}/s  {:>5}  {:>5}",
+                rank + 1,
+                report.origin,
+                stats.latency.as_millis(),
+                format_bytes(stats.throughput),
+                yes_no(stats.ranges),
+                yes_no(stats.http2),
+            ),
+            Err(err) => writeln!(
+                table,
+                "{:>4}  {:<width$}  failed: {}",
+                rank + 1,
+                report.origin,
+                err
+            ),
+        };
+    }
+    table
+}
+
+fn yes_no(flag: bool) -> &'static str {
+    if flag {
+        "yes"
+    } else {
+        "no"
+    }
+}
+/// Formats number of bytes using binary suffixes, consistent with speed limit ones
+fn format_bytes(amount: f64) -> String {
+    if amount >= 1024.0 * 1024.0 {
+        format!("{:.1}M", amount / (1024.0 * 1024.0))
+    } else if amount >= 1024.0 {
+        format!("{:.1}K", amount / 1024.0)
+    } else {
+        format!("{:.0}", amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_table, probe_hosts, PROBE_SIZE};
+    use crate::test_utils::{certificate, spawn_server, write_random_file};
+    use assert_matches::assert_matches;
+    use reqwest::Client;
+    use tokio::runtime::Builder;
+    use tokio::task::spawn;
+    use warp::Filter;
+
+    #[test]
+    fn probe_local_server() {
+        let src_dir = tempfile::tempdir().unwrap();
+        write_random_file(&src_dir.path().join("sample"), PROBE_SIZE * 2);
+        let src_path = src_dir.path().to_owned();
+
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let (port, tx, jh) = spawn_server(src_path);
+                // Several URLs of the same host, plus unreachable host
+                let urls = [
+                    format!("http://127.0.0.1:{}/files/sample", port),
+                    format!("http://127.0.0.1:{}/files/other", port),
+                    "http://127.0.0.1:1/files/sample".to_owned(),
+                ];
+                let reports = probe_hosts(&Client::new(), &urls).await;
+
+                assert_eq!(reports.len(), 2);
+                assert_eq!(reports[0].origin, format!("http://127.0.0.1:{}", port));
+                assert_matches!(&reports[0].result, Ok(stats) if stats.ranges && !stats.http2);
+                assert_matches!(&reports[1].result, Err(_));
+                assert_eq!(format_table(&reports).lines().count(), 3);
+
+                let _ = tx.send(());
+                let _ = jh.await;
+            });
+    }
+
+    #[test]
+    fn probe_http2_server() {
+        let (ca, ca_key) = certificate("Test CA", None);
+        let (cert, key) = certificate("localhost", Some((&ca, &ca_key)));
+        let identity = native_tls::Identity::from_pkcs8(
+            &cert.to_pem().unwrap(),
+            &key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+        let mut acceptor = native_tls::TlsAcceptor::builder(identity);
+        acceptor.accept_alpn(&["h2", "http/1.1"]);
+        let acceptor = tokio_native_tls::TlsAcceptor::from(acceptor.build().unwrap());
+
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                // Stub server which talks HTTP/2 to clients which offer it over TLS
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let port = listener.local_addr().unwrap().port();
+                let incoming = futures::stream::unfold(
+                    (listener, acceptor),
+                    |(listener, acceptor)| async move {
+                        let stream = match listener.accept().await {
+                            Ok((stream, _)) => {
+                                acceptor.accept(stream).await.map_err(std::io::Error::other)
+                            }
+                            Err(err) => Err(err),
+                        };
+                        Some((stream, (listener, acceptor)))
+                    },
+                );
+                let jh = spawn(warp::serve(warp::any().map(|| "data")).serve_incoming(incoming));
+
+                let client = Client::builder()
+                    .danger_accept_invalid_certs(true)
+                    .build()
+                    .unwrap();
+                let urls = [format!("https://localhost:{}/file", port)];
+                let reports = probe_hosts(&client, &urls).await;
+                assert_matches!(&reports[0].result, Ok(stats) if stats.http2);
+
+                jh.abort();
+            });
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncSeekExt};
+use url::Url;
+
+use crate::ftp::FtpSession;
+use crate::sftp::SftpSession;
+
+/// Protocol source URL is downloaded over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// HTTP(S), which most sources use
+    Http,
+    /// FTP, plain or over TLS
+    Ftp,
+    /// SFTP over SSH
+    Sftp,
+    /// Local file, given as `file://` URL or absolute path, which is copied
+    /// the same way as downloaded ones, e.g. for testing lists offline
+    File,
+}
+
+impl Protocol {
+    /// Figures out protocol by scheme of URL; unknown schemes are left to HTTP client
+    pub fn of(url: &str) -> Protocol {
+        if is_local_path(url) {
+            return Protocol::File;
+        }
+        let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+        match scheme.to_ascii_lowercase().as_str() {
+            "ftp" | "ftps" => Protocol::Ftp,
+            "sftp" => Protocol::Sftp,
+            "file" => Protocol::File,
+            _ => Protocol::Http,
+        }
+    }
+    /// Parses source URL, turning absolute local path into `file://` URL
+    pub fn parse_url(url: &str) -> Result<Url> {
+        let parsed = match is_local_path(url) {
+            // Path in list is percent-encoded like URL already
+            true => Url::parse(&format!("file://{}", url)),
+            false => Url::parse(url),
+        };
+        parsed.with_context(|| format!("{}: invalid URL", url))
+    }
+    /// Whether servers report versions of files, so unchanged ones can be recognized
+    pub fn has_validators(self) -> bool {
+        self == Protocol::Http
+    }
+}
+
+/// Settings of sessions with file servers which aren't HTTP ones
+#[derive(Clone, Debug, Default)]
+pub struct SessionOptions {
+    /// Whether certificates of FTPS servers and host keys of SSH servers aren't verified
+    pub insecure: bool,
+    /// Private key to log in to SSH servers with, when URL has no password
+    pub ssh_key: Option<PathBuf>,
+}
+
+/// Data transferred from server, after which session reports whether transfer succeeded
+pub struct Transfer {
+    /// Data connection
+    pub data: Pin<Box<dyn AsyncRead + Send>>,
+    /// Offset in file at which data starts
+    pub offset: u64,
+}
+
+/// Checks whether source is absolute local path rather than URL
+fn is_local_path(url: &str) -> bool {
+    url.starts_with('/') && !url.starts_with("//")
+}
+
+/// Session with file server, which transfers whole files over single connection
+pub enum Session {
+    /// Session with FTP(S) server
+    Ftp(FtpSession),
+    /// Session with SSH server's SFTP subsystem
+    Sftp(SftpSession),
+    /// Access to local files, which needs no server
+    Local,
+}
+
+impl Session {
+    /// Connects to server of URL and logs in
+    pub async fn connect(url: &Url, options: &SessionOptions) -> Result<Session> {
+        Ok(match Protocol::of(url.as_str()) {
+            Protocol::Sftp => Session::Sftp(
+                SftpSession::connect(url, options.ssh_key.as_deref(), options.insecure).await?,
+            ),
+            Protocol::File => Session::Local,
+            _ => Session::Ftp(FtpSession::connect(url, options.insecure).await?),
+        })
+    }
+    /// Makes path of file on server from URL
+    pub fn remote_path(url: &Url) -> Result<String> {
+        match Protocol::of(url.as_str()) {
+            Protocol::Sftp => crate::sftp::remote_path(url),
+            Protocol::File => url
+                .to_file_path()
+                .ok()
+                .and_then(|path| path.to_str().map(str::to_owned))
+                .ok_or_else(|| anyhow!("{}: URL doesn't refer to local file", url)),
+            _ => crate::ftp::remote_path(url),
+        }
+    }
+    /// Finds size of file, if server reports it
+    pub async fn size(&mut self, path: &str) -> Result<Option<u64>> {
+        match self {
+            Session::Ftp(session) => session.size(path).await,
+            Session::Sftp(session) => session.size(path).await,
+            Session::Local => {
+                let meta = fs::metadata(path)
+                    .await
+                    .with_context(|| format!("{}: cannot access file", path))?;
+                Ok(Some(meta.len()))
+            }
+        }
+    }
+    /// Starts transfer of file, from specified offset if server supports resuming
+    pub async fn retrieve(&mut self, path: &str, offset: u64) -> Result<Transfer> {
+        match self {
+            Session::Ftp(session) => session.retrieve(path, offset).await,
+            Session::Sftp(session) => session.retrieve(path, offset).await,
+            Session::Local => {
+                let mut file = fs::File::open(path)
+                    .await
+                    .with_context(|| format!("{}: cannot open file", path))?;
+                file.seek(SeekFrom::Start(offset)).await?;
+                Ok(Transfer {
+                    data: Box::pin(file),
+                    offset,
+                })
+            }
+        }
+    }
+    /// Waits for server to confirm that the whole file was sent, once data is read
+    pub async fn finish(&mut self) -> Result<()> {
+        match self {
+            Session::Ftp(session) => session.finish().await,
+            Session::Sftp(_) | Session::Local => Ok(()),
+        }
+    }
+    /// Ends session
+    pub async fn quit(self) {
+        match self {
+            Session::Ftp(session) => session.quit().await,
+            Session::Sftp(session) => session.quit().await,
+            Session::Local => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Protocol;
+
+    #[test]
+    fn protocols() {
+        assert_eq!(Protocol::of("https://host/file"), Protocol::Http);
+        assert_eq!(Protocol::of("FTPS://host/file"), Protocol::Ftp);
+        assert_eq!(Protocol::of("sftp://user@host/file"), Protocol::Sftp);
+        assert_eq!(
+            Protocol::of("oci://registry/repo@sha256:00"),
+            Protocol::Http
+        );
+        assert!(Protocol::of("http://host/sftp://").has_validators());
+        assert!(!Protocol::of("ftp://host/file").has_validators());
+        assert_eq!(Protocol::of("file:///srv/file"), Protocol::File);
+        assert_eq!(Protocol::of("/srv/file"), Protocol::File);
+        assert_eq!(Protocol::of("//host/file"), Protocol::Http);
+        assert_eq!(
+            Protocol::parse_url("/srv/my%20file").unwrap().as_str(),
+            "file:///srv/my%20file"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+use anyhow::{bail, Result};
+
+use crate::har::Har;
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, AUTHORIZATION, COOKIE, LOCATION, PROXY_AUTHORIZATION,
+        WWW_AUTHENTICATE,
+    },
+    redirect::Policy,
+    Client, ClientBuilder, RequestBuilder, Response, Url,
+};
+
+/// Max number of redirects followed for single request, by default
+pub const MAX_REDIRECTS: usize = 10;
+
+/// Single step of redirect chain: requested URL and status of response to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hop {
+    pub url: String,
+    pub status: u16,
+}
+
+/// Redirect chain of the latest redirected request made by job
+#[derive(Debug, Default)]
+pub struct Trail(Mutex<Vec<Hop>>);
+
+impl Trail {
+    /// Returns recorded chain, empty if no request was redirected
+    pub fn hops(&self) -> Vec<Hop> {
+        self.0.lock().unwrap().clone()
+    }
+}
+/// Creates HTTP client which doesn't follow redirects by itself, to be used with `send`
+///
+/// # Arguments
+/// * builder - client configuration, like socket options
+pub fn client(builder: ClientBuilder) -> Client {
+    builder
+        .redirect(Policy::none())
+        .build()
+        .expect("HTTP client without redirects can be built")
+}
+/// Sends request, following redirects manually so each hop can be recorded
+///
+/// # Arguments
+/// * request - request to send; its client must not follow redirects by itself
+/// * trail - where redirect chain is recorded, if request is redirected at all
+/// * har - where each exchange is logged, if anywhere
+/// * max_redirects - how many redirects may be followed; with 0, redirect fails request
+///
+/// Credentials aren't sent to origins other than the original one
+pub async fn send(
+    request: RequestBuilder,
+    trail: &Trail,
+    har: Option<&Har>,
+    max_redirects: usize,
+) -> Result<Response> {
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    let mut hops = Vec::new();
+    loop {
+        // Requests without body can always be cloned
+        let next = request.try_clone();
+        let logged = har.map(|har| (har, Har::describe(&request), SystemTime::now()));
+        let started = Instant::now();
+        let response = client.execute(request).await?;
+        if let Some((har, request, time)) = logged {
+            har.record(request, &response, time, started.elapsed());
+        }
+        let hop = Hop {
+            url: response.url().to_string(),
+            status: response.status().as_u16(),
+        };
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| response.url().join(value).ok());
+        match (location, next) {
+            (Some(location), Some(mut next)) if response.status().is_redirection() => {
+                hops.push(hop);
+                if max_redirects == 0 {
+                    bail!(
+                        "{}: redirected to {}, but redirects aren't followed",
+                        hops[0].url,
+                        location
+                    );
+                }
+                if hops.len() > max_redirects {
+                    bail!("{}: more than {} redirects", hops[0].url, max_redirects);
+                }
+                let previous = next.url().clone();
+                remove_sensitive_headers(next.headers_mut(), &location, &previous);
+                *next.url_mut() = location;
+                request = next;
+            }
+            _ => {
+                if !hops.is_empty() {
+                    hops.push(hop);
+                    *trail.0.lock().unwrap() = hops;
+                }
+                return Ok(response);
+            }
+        }
+    }
+}
+
+/// Removes headers which carry credentials when request is redirected to another origin,
+/// i.e. to other host, port or scheme, like HTTPS to HTTP downgrade on the same host
+fn remove_sensitive_headers(headers: &mut HeaderMap, next: &Url, previous: &Url) {
+    let cross_origin = next.host_str() != previous.host_str()
+        || next.port_or_known_default() != previous.port_or_known_default()
+        || next.scheme() != previous.scheme();
+    if cross_origin {
+        headers.remove(AUTHORIZATION);
+        headers.remove(COOKIE);
+        headers.remove(HeaderName::from_static("cookie2"));
+        headers.remove(PROXY_AUTHORIZATION);
+        headers.remove(WWW_AUTHENTICATE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client, remove_sensitive_headers, send, Hop, Trail, MAX_REDIRECTS};
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION};
+    use reqwest::{Client, Url};
+    use tokio::runtime::Builder;
+    use tokio::sync::oneshot::channel;
+    use warp::{http::Response, Filter};
+
+    #[test]
+    fn follow_redirects() {
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                // Stub server which bounces requests through tracker
+                let redirect = |status: u16, location: &'static str| {
+                    move || {
+                        Response::builder()
+                            .status(status)
+                            .header("location", location)
+                            .body("")
+                    }
+                };
+                let routes = warp::path!("start")
+                    .map(redirect(302, "/track"))
+                    .or(warp::path!("track").map(redirect(307, "final")))
+                    .or(warp::path!("final").map(|| "data"))
+                    .or(warp::path!("loop").map(redirect(301, "/loop")));
+                let (tx, rx) = channel();
+                let (addr, server) =
+                    warp::serve(routes).bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+                        rx.await.ok();
+                    });
+                let jh = tokio::spawn(server);
+                let url = |path| format!("http://127.0.0.1:{}/{}", addr.port(), path);
+                let client = client(Client::builder());
+
+                let trail = Trail::default();
+                let response = send(client.get(url("final")), &trail, None, MAX_REDIRECTS)
+                    .await
+                    .unwrap();
+                assert_eq!(response.text().await.unwrap(), "data");
+                assert!(trail.hops().is_empty());
+
+                let response = send(client.get(url("start")), &trail, None, 2)
+                    .await
+                    .unwrap();
+                assert_eq!(response.text().await.unwrap(), "data");
+                let hop = |path, status| Hop {
+                    url: url(path),
+                    status,
+                };
+                assert_eq!(
+                    trail.hops(),
+                    [hop("start", 302), hop("track", 307), hop("final", 200)]
+                );
+
+                assert!(send(client.get(url("loop")), &trail, None, MAX_REDIRECTS)
+                    .await
+                    .is_err());
+                let err = send(client.get(url("start")), &trail, None, 1)
+                    .await
+                    .unwrap_err();
+                assert!(err.to_string().contains("more than 1 redirects"));
+                let err = send(client.get(url("start")), &trail, None, 0)
+                    .await
+                    .unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    format!(
+                        "{}: redirected to {}, but redirects aren't followed",
+                        url("start"),
+                        url("track")
+                    )
+                );
+
+                let _ = tx.send(());
+                let _ = jh.await;
+            });
+    }
+
+    #[test]
+    fn credentials_on_redirects() {
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                // Two servers on the same host, which tell credentials they received
+                let echo = warp::path!("echo")
+                    .and(warp::header::optional::<String>("authorization"))
+                    .and(warp::header::optional::<String>("cookie"))
+                    .map(|auth: Option<String>, cookie: Option<String>| {
+                        format!("{:?} {:?}", auth, cookie)
+                    });
+                let (other_tx, other_rx) = channel::<()>();
+                let (other, server) =
+                    warp::serve(echo).bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+                        other_rx.await.ok();
+                    });
+                let other_jh = tokio::spawn(server);
+                let elsewhere = format!("http://127.0.0.1:{}/echo", other.port());
+                let routes = warp::path!("same")
+                    .map(|| {
+                        Response::builder()
+                            .status(302)
+                            .header("location", "/echo")
+                            .body("")
+                    })
+                    .or(warp::path!("other").map(move || {
+                        Response::builder()
+                            .status(302)
+                            .header("location", elsewhere.as_str())
+                            .body("")
+                    }))
+                    .or(echo);
+                let (tx, rx) = channel::<()>();
+                let (addr, server) =
+                    warp::serve(routes).bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+                        rx.await.ok();
+                    });
+                let jh = tokio::spawn(server);
+                let client = client(Client::builder());
+                let get = |path| {
+                    let trail = Trail::default();
+                    let request = client
+                        .get(format!("http://127.0.0.1:{}/{}", addr.port(), path))
+                        .header(AUTHORIZATION, "Bearer token")
+                        .header(COOKIE, "session=1");
+                    async move {
+                        let response = send(request, &trail, None, MAX_REDIRECTS).await.unwrap();
+                        response.text().await.unwrap()
+                    }
+                };
+
+                // Credentials are kept within the same origin
+                assert_eq!(
+                    get("same").await,
+                    "Some(\"Bearer token\") Some(\"session=1\")"
+                );
+                // Same host on other port is other origin, so it gets neither
+                assert_eq!(get("other").await, "None None");
+
+                let _ = tx.send(());
+                let _ = jh.await;
+                let _ = other_tx.send(());
+                let _ = other_jh.await;
+            });
+    }
+
+    #[test]
+    fn sensitive_headers() {
+        let credentials = || {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer token"));
+            headers.insert(COOKIE, HeaderValue::from_static("session=1"));
+            headers.insert("cookie2", HeaderValue::from_static("$Version=1"));
+            headers.insert(
+                PROXY_AUTHORIZATION,
+                HeaderValue::from_static("Basic cHJveHk="),
+            );
+            headers
+        };
+        let redirect = |from: &str, to: &str| {
+            let mut headers = credentials();
+            remove_sensitive_headers(
+                &mut headers,
+                &Url::parse(to).unwrap(),
+                &Url::parse(from).unwrap(),
+            );
+            headers
+        };
+        // Default port is the same as explicit one
+        assert_eq!(redirect("https://a/x", "https://a:443/y"), credentials());
+        // Downgrade to HTTP on the same host, other port or other host strip all credentials
+        assert!(redirect("https://a/x", "http://a/x").is_empty());
+        assert!(redirect("https://a/x", "https://a:8443/x").is_empty());
+        assert!(redirect("https://a/x", "https://b/x").is_empty());
+    }
+}
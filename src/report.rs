@@ -0,0 +1,691 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use url::Url;
+
+use httpdl::{
+    downloader::{Progress, SkipReason},
+    failure::FailureKind,
+    redirects::Hop,
+    resume::ResumeStats,
+    timeline::Timeline,
+    timestamp::format_timestamp,
+};
+
+/// Final state of single download job
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Job is still running
+    Running,
+    /// Job finished successfully, with number of bytes downloaded
+    Finished(u64),
+    /// Job failed, with error description and failure stage
+    Failed(String, FailureKind),
+    /// Job was cancelled before completion
+    Cancelled,
+    /// Job wasn't performed, because there was nothing to do, with reason
+    Skipped(SkipReason),
+    /// Job wasn't attempted, because it couldn't succeed, with reason description
+    Deferred(String),
+    /// Remote file changed during job, with status of rejected conditional request
+    Changed(u16),
+}
+
+/// Record of single download job
+#[derive(Debug)]
+pub struct JobRecord {
+    pub url: String,
+    pub name: String,
+    pub outcome: Outcome,
+    /// Redirect chain which led to actual data, if job was redirected
+    pub redirects: Vec<Hop>,
+    /// Number of failed attempts which were retried
+    pub retries: usize,
+    /// URLs which served copies not matching expected checksum, in order they were tried
+    pub corrupted: Vec<String>,
+    /// Partial data job's attempts resumed from or threw away
+    pub resumed: ResumeStats,
+    /// Time job finished successfully or was skipped, if it did
+    pub completed_at: Option<SystemTime>,
+    /// Path in fallback directory job downloaded into, if destination couldn't be written
+    pub relocated: Option<PathBuf>,
+}
+
+/// Collects outcomes of download jobs from notification stream
+///
+/// Used to print run summary and to write machine-readable report
+#[derive(Debug, Default)]
+pub struct Report {
+    jobs: BTreeMap<usize, JobRecord>,
+    /// Accounting tags of entries, by entry index
+    groups: HashMap<usize, String>,
+    /// Times by which entries should complete, by entry index
+    deadlines: BTreeMap<usize, SystemTime>,
+    /// Whether machine-readable report leaves out details which vary between runs
+    reproducible: bool,
+    /// Bytes received over time, if they're reported
+    timeline: Option<Arc<Timeline>>,
+}
+
+impl Report {
+    /// Creates report which also accounts downloaded bytes per group
+    ///
+    /// # Arguments
+    /// * groups - pairs of entry index and its group tag; entries without tag may be omitted
+    pub fn with_groups(groups: impl IntoIterator<Item = (usize, String)>) -> Report {
+        Report {
+            groups: groups.into_iter().collect(),
+            ..Report::default()
+        }
+    }
+    /// Makes report also tell which entries didn't complete by their deadlines
+    ///
+    /// # Arguments
+    /// * deadlines - pairs of entry index and its deadline; entries without one may be omitted
+    pub fn with_deadlines(
+        self,
+        deadlines: impl IntoIterator<Item = (usize, SystemTime)>,
+    ) -> Report {
+        Report {
+            deadlines: deadlines.into_iter().collect(),
+            ..self
+        }
+    }
+    /// Makes machine-readable report also list bytes received per time bucket
+    pub fn with_timeline(self, timeline: Arc<Timeline>) -> Report {
+        Report {
+            timeline: Some(timeline),
+            ..self
+        }
+    }
+    /// Makes machine-readable report the same for runs with the same outcomes,
+    /// leaving out retries and missed deadlines, which depend on timing
+    pub fn reproducible(self) -> Report {
+        Report {
+            reproducible: true,
+            ..self
+        }
+    }
+    /// Updates job record according to progress notification
+    pub fn record(&mut self, index: usize, url: &str, name: &str, status: &Progress) {
+        let job = self.jobs.entry(index).or_insert_with(|| JobRecord {
+            url: url.to_owned(),
+            name: name.to_owned(),
+            outcome: Outcome::Running,
+            redirects: Vec::new(),
+            retries: 0,
+            corrupted: Vec::new(),
+            resumed: ResumeStats::default(),
+            completed_at: None,
+            relocated: None,
+        });
+        job.outcome = match status {
+            Progress::Started => Outcome::Running,
+            Progress::Finished(Ok(bytes)) => Outcome::Finished(*bytes),
+            Progress::Finished(Err(err)) => {
+                Outcome::Failed(err.to_string(), FailureKind::classify(err))
+            }
+            Progress::Cancelled => Outcome::Cancelled,
+            Progress::Skipped(reason) => Outcome::Skipped(*reason),
+            Progress::Deferred(err) => Outcome::Deferred(err.to_string()),
+            Progress::Changed(status) => Outcome::Changed(*status),
+            Progress::Redirected(hops) => {
+                job.redirects = hops.clone();
+                return;
+            }
+            Progress::Relocated { path, .. } => {
+                job.relocated = Some(path.clone());
+                return;
+            }
+            Progress::Throttled(_)
+            | Progress::Received { .. }
+            | Progress::Conflict { .. }
+            | Progress::Saved(_) => return,
+            Progress::Retrying { attempt, .. } => {
+                job.retries = *attempt;
+                return;
+            }
+            Progress::Corrupted { url, .. } => {
+                job.corrupted.push(url.clone());
+                return;
+            }
+            Progress::Resumed(stats) => {
+                job.resumed = *stats;
+                return;
+            }
+        };
+        if matches!(job.outcome, Outcome::Finished(_) | Outcome::Skipped(_)) {
+            job.completed_at = Some(SystemTime::now());
+        }
+    }
+    /// Finds entries which didn't complete by their deadlines: ones which completed late,
+    /// and ones whose deadline passed while they're not completed
+    fn missed_deadlines(&self) -> Vec<usize> {
+        let now = SystemTime::now();
+        self.deadlines
+            .iter()
+            .filter(|&(index, &deadline)| {
+                let completed_at = self.jobs.get(index).and_then(|job| job.completed_at);
+                completed_at.unwrap_or(now) > deadline
+            })
+            .map(|(&index, _)| index)
+            .collect()
+    }
+    /// Checks whether job with specified index has been started
+    pub fn started(&self, index: usize) -> bool {
+        self.jobs.contains_key(&index)
+    }
+    /// Checks whether job with specified index needs no further work,
+    /// i.e. it has finished successfully or was skipped
+    pub fn completed(&self, index: usize) -> bool {
+        matches!(
+            self.jobs.get(&index),
+            Some(JobRecord {
+                outcome: Outcome::Finished(_) | Outcome::Skipped(_),
+                ..
+            })
+        )
+    }
+    /// Counts jobs by outcome: finished, failed, cancelled or still running, skipped and deferred
+    fn counts(&self) -> (usize, usize, usize, usize, usize) {
+        self.jobs.values().fold(
+            (0, 0, 0, 0, 0),
+            |(ok, failed, cancelled, skipped, deferred), job| match job.outcome {
+                Outcome::Finished(_) => (ok + 1, failed, cancelled, skipped, deferred),
+                Outcome::Failed(..) | Outcome::Changed(_) => {
+                    (ok, failed + 1, cancelled, skipped, deferred)
+                }
+                Outcome::Running | Outcome::Cancelled => {
+                    (ok, failed, cancelled + 1, skipped, deferred)
+                }
+                Outcome::Skipped(_) => (ok, failed, cancelled, skipped + 1, deferred),
+                Outcome::Deferred(_) => (ok, failed, cancelled, skipped, deferred + 1),
+            },
+        )
+    }
+    /// Sums bytes downloaded by finished jobs, per group tag
+    fn group_bytes(&self) -> BTreeMap<&str, u64> {
+        let mut totals = BTreeMap::new();
+        for (index, job) in &self.jobs {
+            if let (Some(group), Outcome::Finished(bytes)) = (self.groups.get(index), &job.outcome)
+            {
+                *totals.entry(group.as_str()).or_default() += bytes;
+            }
+        }
+        totals
+    }
+    /// Counts failed jobs by source host and failure stage
+    fn host_failures(&self) -> BTreeMap<String, BTreeMap<FailureKind, usize>> {
+        let mut failures = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for job in self.jobs.values() {
+            if let Outcome::Failed(_, kind) = job.outcome {
+                *failures
+                    .entry(host_of(&job.url))
+                    .or_default()
+                    .entry(kind)
+                    .or_default() += 1;
+            }
+        }
+        failures
+    }
+    /// Sums partial data reused and downloaded again, in total and per source host,
+    /// so hosts which break resume stand out
+    fn resume_totals(&self) -> (ResumeStats, BTreeMap<String, ResumeStats>) {
+        let mut total = ResumeStats::default();
+        let mut hosts = BTreeMap::<_, ResumeStats>::new();
+        for job in self.jobs.values().filter(|job| !job.resumed.is_empty()) {
+            total.add(job.resumed);
+            hosts.entry(host_of(&job.url)).or_default().add(job.resumed);
+        }
+        (total, hosts)
+    }
+    /// Human-readable one-line summary of the run
+    ///
+    /// # Arguments
+    /// * pending - number of jobs which were never started
+    pub fn summary(&self, pending: usize) -> String {
+        let (ok, failed, cancelled, skipped, deferred) = self.counts();
+        let bytes: u64 = self
+            .jobs
+            .values()
+            .map(|job| match job.outcome {
+                Outcome::Finished(bytes) => bytes,
+                _ => 0,
+            })
+            .sum();
+        let mut summary = format!(
+            "{} finished, {} failed, {} bytes downloaded",
+            ok, failed, bytes
+        );
+        if skipped > 0 {
+            summary += &format!(", {} skipped", skipped);
+        }
+        if deferred > 0 {
+            summary += &format!(", {} deferred", deferred);
+        }
+        let missed = self.missed_deadlines().len();
+        if missed > 0 {
+            summary += &format!(", {} missed deadline", missed);
+        }
+        let (resumed, _) = self.resume_totals();
+        if !resumed.is_empty() {
+            summary += &format!(
+                ", {} bytes resumed, {} bytes re-downloaded",
+                resumed.reused, resumed.redownloaded
+            );
+            if resumed.retried > 0 {
+                summary += &format!(", {} bytes kept by retries", resumed.retried);
+            }
+        }
+        if cancelled > 0 || pending > 0 {
+            summary += &format!("; {} cancelled, {} not started", cancelled, pending);
+        }
+        let groups = self.group_bytes();
+        if !groups.is_empty() {
+            let groups: Vec<_> = groups
+                .iter()
+                .map(|(group, bytes)| format!("{} {} bytes", group, bytes))
+                .collect();
+            summary += &format!("; by group: {}", groups.join(", "));
+        }
+        let failures = self.host_failures();
+        if !failures.is_empty() {
+            let hosts: Vec<_> = failures
+                .iter()
+                .map(|(host, kinds)| {
+                    let kinds: Vec<_> = kinds
+                        .iter()
+                        .map(|(kind, count)| format!("{} {}", count, kind))
+                        .collect();
+                    format!("{} {}", host, kinds.join(", "))
+                })
+                .collect();
+            summary += &format!("; failures: {}", hosts.join("; "));
+        }
+        summary
+    }
+    /// Machine-readable report of the run
+    ///
+    /// # Arguments
+    /// * interrupted - whether run was stopped before all jobs completed
+    /// * pending - number of jobs which were never started
+    pub fn to_json(&self, interrupted: bool, pending: usize) -> Value {
+        let (ok, failed, cancelled, skipped, deferred) = self.counts();
+        let missed = match self.reproducible {
+            true => Vec::new(),
+            false => self.missed_deadlines(),
+        };
+        let failures: BTreeMap<_, BTreeMap<_, _>> = self
+            .host_failures()
+            .into_iter()
+            .map(|(host, kinds)| {
+                let kinds = kinds
+                    .into_iter()
+                    .map(|(kind, count)| (kind.to_string(), count));
+                (host, kinds.collect())
+            })
+            .collect();
+        let jobs: Vec<_> = self
+            .jobs
+            .iter()
+            .map(|(index, job)| {
+                let mut record = json!({
+                    "index": index,
+                    "url": job.url,
+                    "name": job.name,
+                });
+                if let Some(group) = self.groups.get(index) {
+                    record["group"] = json!(group);
+                }
+                match &job.outcome {
+                    Outcome::Running => record["status"] = json!("running"),
+                    Outcome::Finished(bytes) => {
+                        record["status"] = json!("finished");
+                        record["bytes"] = json!(bytes);
+                    }
+                    Outcome::Failed(err, kind) => {
+                        record["status"] = json!("failed");
+                        record["error"] = json!(err);
+                        record["failure"] = json!(kind.to_string());
+                    }
+                    Outcome::Cancelled => record["status"] = json!("cancelled"),
+                    Outcome::Skipped(reason) => {
+                        record["status"] = json!("skipped");
+                        record["reason"] = json!(reason.name());
+                    }
+                    Outcome::Deferred(reason) => {
+                        record["status"] = json!("deferred");
+                        record["reason"] = json!(reason);
+                    }
+                    Outcome::Changed(status) => {
+                        record["status"] = json!("changed");
+                        record["http_status"] = json!(status);
+                    }
+                }
+                if job.retries > 0 && !self.reproducible {
+                    record["retries"] = json!(job.retries);
+                }
+                if missed.contains(index) {
+                    record["deadline_missed"] = json!(true);
+                }
+                if !job.corrupted.is_empty() {
+                    record["corrupted_by"] = json!(job.corrupted);
+                }
+                if !job.resumed.is_empty() && !self.reproducible {
+                    record["resume"] = resume_json(job.resumed);
+                }
+                if !job.redirects.is_empty() {
+                    let hops: Vec<_> = job
+                        .redirects
+                        .iter()
+                        .map(|hop| json!({"url": hop.url, "status": hop.status}))
+                        .collect();
+                    record["redirects"] = json!(hops);
+                }
+                if let Some(path) = &job.relocated {
+                    record["relocated_to"] = json!(path.display().to_string());
+                }
+                record
+            })
+            .collect();
+        let mut report = json!({
+            "interrupted": interrupted,
+            "finished": ok,
+            "failed": failed,
+            "cancelled": cancelled,
+            "skipped": skipped,
+            "deferred": deferred,
+            "missed_deadlines": missed.len(),
+            "pending": pending,
+            "groups": self.group_bytes(),
+            "failures": failures,
+            "jobs": jobs,
+        });
+        if self.reproducible {
+            report.as_object_mut().unwrap().remove("missed_deadlines");
+        }
+        // Partial data depends on how previous runs ended, so it isn't reproducible
+        let (resumed, hosts) = self.resume_totals();
+        if !resumed.is_empty() && !self.reproducible {
+            let mut resume = resume_json(resumed);
+            resume["hosts"] = hosts
+                .into_iter()
+                .map(|(host, stats)| (host, resume_json(stats)))
+                .collect();
+            report["resume"] = resume;
+        }
+        if let Some(timeline) = &self.timeline {
+            let series: Vec<_> = timeline
+                .series()
+                .into_iter()
+                .map(|(start, bytes)| json!({ "start": format_timestamp(start), "bytes": bytes }))
+                .collect();
+            report["bandwidth"] = json!({
+                "bucket_secs": timeline.bucket().as_secs(),
+                "series": series,
+            });
+        }
+        report
+    }
+}
+
+/// Finds host of job's source URL, for accounting per host; URL without host stands for itself
+fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_else(|| url.to_owned())
+}
+/// Describes amounts of partial data as JSON object
+fn resume_json(stats: ResumeStats) -> Value {
+    json!({
+        "reused": stats.reused,
+        "redownloaded": stats.redownloaded,
+        "retried": stats.retried,
+    })
+}
+/// Reads sizes of files downloaded by previous run from its report, by source URL
+///
+/// Only finished jobs are accounted; resumed ones report only bytes received by that run
+pub fn recorded_sizes(report_file: &str) -> Result<HashMap<String, u64>> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(report_file)?)?;
+    let jobs = json["jobs"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    Ok(jobs
+        .iter()
+        .filter(|job| job["status"] == "finished")
+        .filter_map(|job| Some((job["url"].as_str()?.to_owned(), job["bytes"].as_u64()?)))
+        .collect())
+}
+/// Reads destination names of jobs which completed in previous run from its report,
+/// i.e. finished or skipped ones, in list order
+pub fn completed_names(report_file: &str) -> Result<Vec<String>> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(report_file)?)?;
+    let jobs = json["jobs"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    Ok(jobs
+        .iter()
+        .filter(|job| job["status"] == "finished" || job["status"] == "skipped")
+        .filter_map(|job| Some(job["name"].as_str()?.to_owned()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{completed_names, recorded_sizes, Report};
+    use anyhow::anyhow;
+    use httpdl::{
+        downloader::{Progress, SkipReason},
+        redirects::Hop,
+        resume::ResumeStats,
+    };
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn deadline_misses() {
+        let now = SystemTime::now();
+        let (past, future) = (now - Duration::from_secs(60), now + Duration::from_secs(60));
+        let mut report = Report::default().with_deadlines([(0, future), (1, past), (2, past)]);
+        report.record(0, "http://a/0", "zero", &Progress::Finished(Ok(10)));
+        report.record(1, "http://a/1", "one", &Progress::Finished(Ok(10)));
+        report.record(3, "http://a/3", "three", &Progress::Finished(Ok(10)));
+        // Entry which wasn't even started missed its deadline too
+        assert_eq!(
+            report.summary(1),
+            "3 finished, 0 failed, 30 bytes downloaded, 2 missed deadline; \
+             0 cancelled, 1 not started"
+        );
+        let json = report.to_json(false, 1);
+        assert_eq!(json["missed_deadlines"], 2);
+        assert!(json["jobs"][0].get("deadline_missed").is_none());
+        assert_eq!(json["jobs"][1]["deadline_missed"], true);
+
+        let mut report = report.reproducible();
+        let error = anyhow!("connection reset");
+        report.record(
+            0,
+            "http://a/0",
+            "zero",
+            &Progress::Retrying {
+                attempt: 1,
+                error,
+                delay: Duration::from_secs(1),
+            },
+        );
+        let json = report.to_json(false, 1);
+        assert!(json.get("missed_deadlines").is_none());
+        assert!(json["jobs"][0].get("retries").is_none());
+        assert!(json["jobs"][1].get("deadline_missed").is_none());
+    }
+
+    #[test]
+    fn bandwidth_series() {
+        use httpdl::clock::ManualClock;
+        use httpdl::timeline::Timeline;
+        use std::sync::Arc;
+        use std::time::UNIX_EPOCH;
+
+        let clock = Arc::new(ManualClock::new());
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_040);
+        let timeline = Arc::new(Timeline::new(Duration::from_secs(60), clock.clone(), start));
+        timeline.record(10);
+        clock.advance(Duration::from_secs(60));
+        timeline.record(20);
+        let report = Report::default().with_timeline(timeline);
+        let json = report.to_json(false, 0);
+        assert_eq!(json["bandwidth"]["bucket_secs"], 60);
+        assert_eq!(
+            json["bandwidth"]["series"],
+            serde_json::json!([
+                { "start": "2023-11-14T22:14:00.000Z", "bytes": 10 },
+                { "start": "2023-11-14T22:15:00.000Z", "bytes": 20 },
+            ])
+        );
+        assert!(Report::default()
+            .to_json(false, 0)
+            .get("bandwidth")
+            .is_none());
+    }
+
+    #[test]
+    fn collect_outcomes() {
+        let mut report = Report::with_groups([(0, "infra".to_owned())]);
+        report.record(0, "http://a/0", "zero", &Progress::Started);
+        report.record(1, "http://a/1", "one", &Progress::Started);
+        report.record(2, "http://a/2", "two", &Progress::Started);
+        let hops = vec![
+            Hop {
+                url: "http://a/0".to_owned(),
+                status: 302,
+            },
+            Hop {
+                url: "http://cdn/0".to_owned(),
+                status: 200,
+            },
+        ];
+        report.record(0, "http://a/0", "zero", &Progress::Redirected(hops));
+        report.record(
+            1,
+            "http://a/1",
+            "one",
+            &Progress::Relocated {
+                path: "/fallback/one".into(),
+                error: anyhow!("permission denied"),
+            },
+        );
+        report.record(
+            0,
+            "http://a/0",
+            "zero",
+            &Progress::Resumed(ResumeStats {
+                reused: 60,
+                redownloaded: 40,
+                retried: 0,
+            }),
+        );
+        report.record(0, "http://a/0", "zero", &Progress::Finished(Ok(100)));
+        report.record(
+            1,
+            "http://a/1",
+            "one",
+            &Progress::Retrying {
+                attempt: 1,
+                error: anyhow!("reset"),
+                delay: Duration::from_secs(1),
+            },
+        );
+        report.record(
+            1,
+            "http://a/1",
+            "one",
+            &Progress::Corrupted {
+                url: "http://a/1".to_owned(),
+                error: anyhow!("sha256 checksum mismatch"),
+            },
+        );
+        report.record(
+            1,
+            "http://a/1",
+            "one",
+            &Progress::Finished(Err(anyhow!("boom"))),
+        );
+        report.record(2, "http://a/2", "two", &Progress::Cancelled);
+        report.record(
+            5,
+            "http://a/5",
+            "five",
+            &Progress::Skipped(SkipReason::Unchanged),
+        );
+        report.record(6, "http://a/6", "six", &Progress::Started);
+        report.record(
+            6,
+            "http://a/6",
+            "six",
+            &Progress::Deferred(anyhow!("dependency one didn't complete")),
+        );
+
+        assert!(report.completed(0));
+        assert!(!report.completed(1));
+        assert!(!report.completed(3));
+        assert!(report.completed(5));
+        assert!(!report.completed(6));
+        assert!(report.started(2));
+        assert!(!report.started(3));
+        assert_eq!(
+            report.summary(4),
+            "1 finished, 1 failed, 100 bytes downloaded, 1 skipped, 1 deferred, \
+             60 bytes resumed, 40 bytes re-downloaded; \
+             1 cancelled, 4 not started; \
+             by group: infra 100 bytes; failures: a 1 other"
+        );
+
+        let json = report.to_json(true, 4);
+        assert_eq!(json["interrupted"], true);
+        assert_eq!(json["jobs"][0]["bytes"], 100);
+        assert_eq!(json["jobs"][1]["error"], "boom");
+        assert_eq!(json["jobs"][1]["failure"], "other");
+        assert_eq!(
+            json["jobs"][1]["corrupted_by"],
+            serde_json::json!(["http://a/1"])
+        );
+        assert_eq!(json["jobs"][0]["resume"]["reused"], 60);
+        assert_eq!(json["resume"]["redownloaded"], 40);
+        assert_eq!(json["resume"]["hosts"]["a"]["reused"], 60);
+        assert_eq!(json["failures"]["a"]["other"], 1);
+        assert_eq!(json["jobs"][2]["status"], "cancelled");
+        assert_eq!(json["skipped"], 1);
+        assert_eq!(json["deferred"], 1);
+        assert_eq!(json["jobs"][3]["reason"], "unchanged");
+        assert_eq!(json["jobs"][4]["status"], "deferred");
+        assert_eq!(json["jobs"][4]["reason"], "dependency one didn't complete");
+        assert_eq!(json["groups"]["infra"], 100);
+        assert_eq!(json["jobs"][0]["group"], "infra");
+        assert_eq!(json["jobs"][0]["redirects"][1]["url"], "http://cdn/0");
+        assert_eq!(json["jobs"][0]["redirects"][0]["status"], 302);
+        assert!(json["jobs"][1].get("redirects").is_none());
+        assert_eq!(json["jobs"][1]["relocated_to"], "/fallback/one");
+        assert!(json["jobs"][0].get("relocated_to").is_none());
+        assert_eq!(json["jobs"][1]["retries"], 1);
+        assert!(json["jobs"][0].get("retries").is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let report_file = dir.path().join("report.json");
+        std::fs::write(&report_file, json.to_string()).unwrap();
+        let sizes = recorded_sizes(report_file.to_str().unwrap()).unwrap();
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes["http://a/0"], 100);
+        assert_eq!(
+            completed_names(report_file.to_str().unwrap()).unwrap(),
+            ["zero", "five"]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+
+use crate::timestamp::parse_timestamp;
+
+/// Permissions given to downloaded files on Unix, regardless of umask
+#[cfg(unix)]
+const FILE_MODE: u32 = 0o644;
+
+/// Normalization of downloaded files, so runs with the same inputs produce identical trees
+///
+/// Files get the same modification time, and the same permissions regardless of umask
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reproducible {
+    /// Modification time given to files
+    pub mtime: SystemTime,
+}
+
+impl Reproducible {
+    /// Takes modification time from `SOURCE_DATE_EPOCH` variable, as reproducible builds do
+    pub fn from_env() -> Result<Reproducible> {
+        let epoch = std::env::var("SOURCE_DATE_EPOCH")
+            .context("SOURCE_DATE_EPOCH must be set for reproducible downloads")?;
+        Ok(Reproducible {
+            mtime: parse_timestamp(&epoch).context("malformed SOURCE_DATE_EPOCH")?,
+        })
+    }
+    /// Sets modification time and permissions of file
+    pub async fn normalize(&self, path: &Path) -> Result<()> {
+        let owned = path.to_owned();
+        let mtime = self.mtime;
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let file = std::fs::File::options().write(true).open(owned)?;
+            let mut permissions = file.metadata()?.permissions();
+            #[cfg(unix)]
+            std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, FILE_MODE);
+            #[cfg(not(unix))]
+            permissions.set_readonly(false);
+            file.set_permissions(permissions)?;
+            file.set_modified(mtime)?;
+            Ok(())
+        })
+        .await?
+        .with_context(|| format!("{}: cannot normalize file", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reproducible;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn normalize_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path, b"data").unwrap();
+        let reproducible = Reproducible {
+            mtime: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        };
+
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(reproducible.normalize(&path))
+            .unwrap();
+        let meta = std::fs::metadata(&path).unwrap();
+        assert_eq!(meta.modified().unwrap(), reproducible.mtime);
+        #[cfg(unix)]
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o777,
+            0o644
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+use tokio::io::AsyncWrite;
+use tokio::task::spawn_blocking;
+
+use crate::digest::Digest;
+
+/// Distance between two consecutive checkpoints, in bytes
+pub const CHECKPOINT_INTERVAL: u64 = 1024 * 1024;
+
+/// Returns path of partially downloaded file for specified destination
+pub fn part_path(dest_path: &Path) -> PathBuf {
+    let mut path = dest_path.as_os_str().to_owned();
+    path.push(".part");
+    PathBuf::from(path)
+}
+/// Returns path of partial file kept apart from the main one, for data of alternative origin,
+/// like cache or peer, so failure of the alternative doesn't touch the main partial data
+pub fn side_part_path(part_path: &Path, origin: &str) -> PathBuf {
+    let mut path = part_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(origin);
+    PathBuf::from(path)
+}
+/// Returns path of checkpoints sidecar for specified partial file
+fn checkpoints_path(part_path: &Path) -> PathBuf {
+    let mut path = part_path.as_os_str().to_owned();
+    path.push(".ckpt");
+    PathBuf::from(path)
+}
+/// Removes partially downloaded file along with its checkpoints, if they exist
+///
+/// Blocks, so it can be used where download is dropped midway
+pub fn discard(part_path: &Path) {
+    let _ = fs::remove_file(checkpoints_path(part_path));
+    let _ = fs::remove_file(part_path);
+}
+
+/// Amounts of partial data found by download attempts, by whether it was resumed from
+///
+/// Data of previous run is found by the first attempt only; data which later attempts
+/// keep was received by failed attempts of the same run, so it's counted separately
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResumeStats {
+    /// Bytes of partial file left by previous run and kept, so they weren't downloaded again
+    pub reused: u64,
+    /// Bytes of partial file thrown away, either unverified or rejected by server
+    /// ignoring range, so they're downloaded again
+    pub redownloaded: u64,
+    /// Bytes received by failed attempts and kept by retries
+    pub retried: u64,
+}
+
+impl ResumeStats {
+    /// Whether there was any partial data at all
+    pub fn is_empty(&self) -> bool {
+        self.reused == 0 && self.redownloaded == 0 && self.retried == 0
+    }
+    /// Adds amounts of another job
+    pub fn add(&mut self, other: ResumeStats) {
+        self.reused += other.reused;
+        self.redownloaded += other.redownloaded;
+        self.retried += other.retried;
+    }
+    /// Adds amounts found by download attempt of the same job
+    ///
+    /// # Arguments
+    /// * found - partial data attempt found, as counted by checkpoints
+    /// * first - whether it's the first attempt, which finds data of previous run;
+    ///   data kept by later ones counts as retried
+    pub fn add_attempt(&mut self, found: ResumeStats, first: bool) {
+        match first {
+            true => self.reused += found.reused,
+            false => self.retried += found.reused,
+        }
+        self.redownloaded += found.redownloaded;
+    }
+}
+
+/// Set of verified prefixes of partially downloaded file
+///
+/// Each checkpoint is a pair of offset and BLAKE3 hash of all file data before that offset.
+/// Checkpoints are recorded every `interval` bytes into sidecar file,
+/// so interrupted download can be resumed from the last checkpoint
+/// whose hash still matches file contents.
+pub struct Checkpoints {
+    /// Path of checkpoints sidecar file
+    path: PathBuf,
+    /// Distance between checkpoints
+    interval: u64,
+    /// Hash of file data written so far
+    hasher: blake3::Hasher,
+    /// Number of bytes written so far
+    offset: u64,
+    /// Number of bytes of partial file thrown away so far, on restore or reset
+    discarded: u64,
+}
+
+impl Checkpoints {
+    /// Restores checkpoints of specified partial file
+    ///
+    /// # Arguments
+    /// * part_path - partially downloaded file; created if it doesn't exist
+    /// * interval - distance between checkpoints, in bytes
+    ///
+    /// Verifies existing file prefix against recorded checkpoints,
+    /// then truncates file and checkpoints list to the last matching checkpoint.
+    /// Data without checkpoint is never trusted and is discarded.
+    pub async fn restore(part_path: &Path, interval: u64) -> Result<Checkpoints> {
+        let part_path = part_path.to_owned();
+        spawn_blocking(move || Checkpoints::restore_blocking(&part_path, interval)).await?
+    }
+
+    fn restore_blocking(part_path: &Path, interval: u64) -> Result<Checkpoints> {
+        let path = checkpoints_path(part_path);
+        let mut hasher = blake3::Hasher::new();
+        let mut offset = 0u64;
+        let mut valid = String::new();
+        let present = fs::metadata(part_path).map_or(0, |meta| meta.len());
+        // Walk checkpoints in order, hashing file segment by segment, until first mismatch
+        if let (Ok(mut part), Ok(text)) = (File::open(part_path), fs::read_to_string(&path)) {
+            for line in text.lines() {
+                let (next_offset, hash) = match line.split_once(' ') {
+                    Some((next_offset, hash)) => match next_offset.parse::<u64>() {
+                        Ok(next_offset) if next_offset > offset => (next_offset, hash),
+                        _ => break,
+                    },
+                    None => break,
+                };
+                let mut segment = hasher.clone();
+                let len = next_offset - offset;
+                if io::copy(&mut (&mut part).take(len), &mut segment)? != len
+                    || segment.finalize().to_hex().as_str() != hash
+                {
+                    break;
+                }
+                hasher = segment;
+                offset = next_offset;
+                valid.push_str(line);
+                valid.push('\n');
+            }
+        }
+        // Drop everything past last good checkpoint
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(part_path)?
+            .set_len(offset)?;
+        fs::write(&path, valid)?;
+
+        Ok(Checkpoints {
+            path,
+            interval,
+            hasher,
+            offset,
+            discarded: present.saturating_sub(offset),
+        })
+    }
+    /// Number of verified bytes in partial file
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    /// Amounts of partial data reused and thrown away, once transfer starts at current offset
+    pub fn stats(&self) -> ResumeStats {
+        ResumeStats {
+            reused: self.offset,
+            redownloaded: self.discarded,
+            retried: 0,
+        }
+    }
+    /// Discards all checkpoints and truncates partial file to zero length
+    pub async fn reset(&mut self, part_path: &Path) -> Result<()> {
+        self.discarded += self.offset;
+        tokio::fs::write(&self.path, "").await?;
+        tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(part_path)
+            .await?
+            .set_len(0)
+            .await?;
+        self.hasher = blake3::Hasher::new();
+        self.offset = 0;
+        Ok(())
+    }
+    /// Removes checkpoints sidecar, once download is either complete or discarded
+    pub async fn remove(self) -> Result<()> {
+        tokio::fs::remove_file(&self.path).await?;
+        Ok(())
+    }
+    /// Number of bytes which can be written before next checkpoint is reached
+    fn until_next(&self) -> u64 {
+        self.interval - self.offset % self.interval
+    }
+    /// Accounts written data, records checkpoint if interval boundary is reached
+    fn advance(&mut self, data: &[u8]) -> io::Result<()> {
+        self.hasher.update(data);
+        self.offset += data.len() as u64;
+        if !data.is_empty() && self.offset.is_multiple_of(self.interval) {
+            let mut file = OpenOptions::new().append(true).open(&self.path)?;
+            writeln!(file, "{} {}", self.offset, self.hasher.finalize().to_hex())?;
+        }
+        Ok(())
+    }
+}
+
+/// Writer adapter which records checkpoints of all data passing through it
+pub struct CheckpointWriter<'a, W: ?Sized> {
+    inner: &'a mut W,
+    checkpoints: &'a mut Checkpoints,
+}
+
+impl<'a, W: AsyncWrite + Unpin + ?Sized> CheckpointWriter<'a, W> {
+    pub fn new(inner: &'a mut W, checkpoints: &'a mut Checkpoints) -> CheckpointWriter<'a, W> {
+        CheckpointWriter { inner, checkpoints }
+    }
+    /// Total number of bytes in partial file, including restored prefix
+    pub fn offset(&self) -> u64 {
+        self.checkpoints.offset()
+    }
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> AsyncWrite for CheckpointWriter<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        // Never write across checkpoint boundary, so checkpoints land exactly on interval
+        let len = (buf.len() as u64).min(this.checkpoints.until_next()) as usize;
+        let poll = Pin::new(&mut *this.inner).poll_write(cx, &buf[..len]);
+        match poll {
+            Poll::Ready(Ok(written)) => match this.checkpoints.advance(&buf[..written]) {
+                Ok(()) => Poll::Ready(Ok(written)),
+                Err(e) => Poll::Ready(Err(e)),
+            },
+            poll => poll,
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Feeds first `len` bytes of specified file into hasher
+///
+/// Used to continue checksum computation when download is resumed
+pub async fn hash_prefix(
+    path: &Path,
+    len: u64,
+    mut digest: Box<dyn Digest>,
+) -> Result<Box<dyn Digest>> {
+    let path = path.to_owned();
+    spawn_blocking(move || {
+        let mut file = File::open(path)?.take(len);
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            match file.read(&mut buf)? {
+                0 => return Ok(digest),
+                n => digest.update(&buf[..n]),
+            }
+        }
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checkpoints_path, part_path, CheckpointWriter, Checkpoints, ResumeStats};
+    use rand::{thread_rng, RngCore};
+    use std::fs;
+    use std::path::Path;
+    use tokio::io::AsyncWriteExt;
+    use tokio_test::block_on;
+
+    const INTERVAL: u64 = 1000;
+
+    // Writes data into partial file through checkpoint writer, starting from scratch
+    async fn write_part(part: &Path, data: &[u8]) {
+        let mut checkpoints = Checkpoints::restore(part, INTERVAL).await.unwrap();
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(part)
+            .await
+            .unwrap();
+        let mut writer = CheckpointWriter::new(&mut file, &mut checkpoints);
+        writer.write_all(data).await.unwrap();
+        writer.flush().await.unwrap();
+    }
+
+    #[test]
+    fn restore_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = part_path(&dir.path().join("file"));
+        let mut data = vec![0u8; 3500];
+        thread_rng().fill_bytes(&mut data);
+
+        block_on(async {
+            write_part(&part, &data).await;
+            // Data after last checkpoint isn't trusted
+            let mut checkpoints = Checkpoints::restore(&part, INTERVAL).await.unwrap();
+            assert_eq!(checkpoints.offset(), 3000);
+            assert_eq!(fs::read(&part).unwrap(), &data[..3000]);
+            assert_eq!(
+                checkpoints.stats(),
+                ResumeStats {
+                    reused: 3000,
+                    redownloaded: 500,
+                    retried: 0,
+                }
+            );
+            // Partial data rejected by server is downloaded again as well
+            checkpoints.reset(&part).await.unwrap();
+            assert_eq!(
+                checkpoints.stats(),
+                ResumeStats {
+                    reused: 0,
+                    redownloaded: 3500,
+                    retried: 0,
+                }
+            );
+        });
+    }
+
+    #[test]
+    fn restore_corrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = part_path(&dir.path().join("file"));
+        let mut data = vec![0u8; 3500];
+        thread_rng().fill_bytes(&mut data);
+
+        block_on(async {
+            write_part(&part, &data).await;
+            // Corrupt byte in second segment, so only the first checkpoint remains valid
+            let mut corrupted = fs::read(&part).unwrap();
+            corrupted[1500] ^= 0xff;
+            fs::write(&part, corrupted).unwrap();
+
+            let checkpoints = Checkpoints::restore(&part, INTERVAL).await.unwrap();
+            assert_eq!(checkpoints.offset(), 1000);
+            assert_eq!(fs::read(&part).unwrap(), &data[..1000]);
+            assert_eq!(
+                fs::read_to_string(checkpoints_path(&part))
+                    .unwrap()
+                    .lines()
+                    .count(),
+                1
+            );
+        });
+    }
+
+    #[test]
+    fn restore_without_checkpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = part_path(&dir.path().join("file"));
+        // Partial file without sidecar is discarded completely
+        fs::write(&part, [1u8; 2000]).unwrap();
+
+        block_on(async {
+            let checkpoints = Checkpoints::restore(&part, INTERVAL).await.unwrap();
+            assert_eq!(checkpoints.offset(), 0);
+            assert_eq!(fs::metadata(&part).unwrap().len(), 0);
+        });
+    }
+}
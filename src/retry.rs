@@ -0,0 +1,277 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, ErrorKind};
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+/// Longest delay between attempts, whatever number of attempts was made
+const MAX_DELAY: Duration = Duration::from_secs(300);
+/// Number of attempts made for jobs whose servers ask to come back later,
+/// even if retries are disabled or fewer are allowed
+const BUSY_ATTEMPTS: usize = 3;
+
+/// How failed transfers are retried
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Max number of attempts per job, and per segment of segmented download,
+    /// including the first one; 1 disables retries
+    pub max_attempts: usize,
+    /// Delay after first failed attempt; doubled after each next one
+    pub base_delay: Duration,
+    /// Fraction of delay, from 0 to 1, which is randomized, so many jobs failed
+    /// at once don't retry all at the same moment
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::from_secs(1),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Computes delay before next attempt
+    ///
+    /// # Arguments
+    /// * attempt - number of attempts failed so far, starting from 1
+    ///
+    /// Delay grows exponentially, up to 5 minutes, and is then reduced
+    /// by random part of jitter fraction
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16) as u32;
+        let delay = self.base_delay.saturating_mul(1 << exponent).min(MAX_DELAY);
+        delay.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * random_fraction())
+    }
+    /// Decides whether failed attempt is retried
+    ///
+    /// # Arguments
+    /// * attempt - number of attempts failed so far, starting from 1
+    /// * error - why the latest attempt failed
+    /// * max_wait - longest delay server may ask for with `Retry-After`
+    ///
+    /// # Returns
+    /// Delay before next attempt, or `None` if error isn't retried. Busy server
+    /// is given as much time as it asks for, up to `max_wait`, instead of usual delay
+    pub fn next(
+        &self,
+        attempt: usize,
+        error: &anyhow::Error,
+        max_wait: Duration,
+    ) -> Option<Duration> {
+        if let Some(busy) = Busy::find(error) {
+            return (attempt < self.max_attempts.max(BUSY_ATTEMPTS))
+                .then(|| busy.retry_after.min(max_wait));
+        }
+        (attempt < self.max_attempts && is_transient(error)).then(|| self.delay(attempt))
+    }
+}
+
+/// Server answered 429 or 503 and told with `Retry-After` header when to come back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Busy {
+    /// Status of response
+    pub status: u16,
+    /// How long server asked to wait
+    pub retry_after: Duration,
+}
+
+impl Busy {
+    /// Checks whether response tells client to come back later
+    ///
+    /// # Arguments
+    /// * status - status of response
+    /// * headers - headers of response
+    /// * now - current time, which HTTP date in `Retry-After` is counted from
+    pub fn check(status: StatusCode, headers: &HeaderMap, now: SystemTime) -> Option<Busy> {
+        if !matches!(status.as_u16(), 429 | 503) {
+            return None;
+        }
+        let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+        let retry_after = match value.parse::<u64>() {
+            Ok(secs) => Duration::from_secs(secs),
+            Err(_) => httpdate::parse_http_date(value)
+                .ok()?
+                .duration_since(now)
+                .unwrap_or_default(),
+        };
+        Some(Busy {
+            status: status.as_u16(),
+            retry_after,
+        })
+    }
+    /// Finds busy response among causes of error
+    pub fn find(err: &anyhow::Error) -> Option<Busy> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<Busy>())
+            .copied()
+    }
+}
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "server is busy (HTTP {}), asked to retry in {:.1}s",
+            self.status,
+            self.retry_after.as_secs_f64()
+        )
+    }
+}
+
+impl std::error::Error for Busy {}
+/// Checks whether error is worth retrying, i.e. is caused by network
+/// or server condition which may go away by itself
+///
+/// Those are connection failures and timeouts, 5xx responses and connections
+/// broken in the middle of transfer. Errors like 4xx responses or data
+/// which doesn't match expectations aren't retried
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+            return is_transient_request(err);
+        }
+        if let Some(err) = cause.downcast_ref::<io::Error>() {
+            // Response body errors are wrapped into I/O ones while streaming
+            if let Some(err) = err.get_ref().and_then(|err| err.downcast_ref()) {
+                return is_transient_request(err);
+            }
+            return matches!(
+                err.kind(),
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::ConnectionRefused
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::TimedOut
+                    | ErrorKind::UnexpectedEof
+            );
+        }
+        false
+    })
+}
+/// Checks whether HTTP client error is worth retrying
+fn is_transient_request(err: &reqwest::Error) -> bool {
+    match err.status() {
+        Some(status) => status.is_server_error(),
+        None => err.is_connect() || err.is_timeout() || err.is_body() || err.is_request(),
+    }
+}
+/// Returns pseudo-random number in `0..1` range
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_transient, Busy, RetryPolicy, MAX_DELAY};
+    use anyhow::anyhow;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use reqwest::StatusCode;
+    use std::io::{self, ErrorKind};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn retry_delays() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(1),
+            jitter: 0.0,
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(100), MAX_DELAY);
+
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        for _ in 0..100 {
+            let delay = policy.delay(2);
+            assert!(delay > Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn transient_errors() {
+        let reset = anyhow::Error::from(io::Error::from(ErrorKind::ConnectionReset));
+        assert!(is_transient(&reset));
+        assert!(is_transient(&reset.context("while downloading")));
+        assert!(!is_transient(&anyhow::Error::from(io::Error::from(
+            ErrorKind::PermissionDenied
+        ))));
+        assert!(!is_transient(&anyhow!("expected 10 bytes, got 9")));
+    }
+
+    #[test]
+    fn busy_servers() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_445_412_480);
+        let headers = |value| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_static(value));
+            headers
+        };
+        let busy = |status, value| Busy::check(status, &headers(value), now);
+        assert_eq!(
+            busy(StatusCode::SERVICE_UNAVAILABLE, "120"),
+            Some(Busy {
+                status: 503,
+                retry_after: Duration::from_secs(120)
+            })
+        );
+        assert_eq!(
+            busy(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Wed, 21 Oct 2015 07:28:30 GMT"
+            ),
+            Some(Busy {
+                status: 429,
+                retry_after: Duration::from_secs(30)
+            })
+        );
+        assert_eq!(
+            busy(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Wed, 21 Oct 2015 07:00:00 GMT"
+            ),
+            Some(Busy {
+                status: 429,
+                retry_after: Duration::ZERO
+            })
+        );
+        assert_eq!(busy(StatusCode::SERVICE_UNAVAILABLE, "soon"), None);
+        assert_eq!(busy(StatusCode::INTERNAL_SERVER_ERROR, "120"), None);
+        assert_eq!(
+            Busy::check(StatusCode::SERVICE_UNAVAILABLE, &HeaderMap::new(), now),
+            None
+        );
+
+        // Busy server is waited for as long as it asks, up to limit, even without retries
+        let policy = RetryPolicy::default();
+        let max_wait = Duration::from_secs(60);
+        let error = anyhow::Error::from(Busy {
+            status: 429,
+            retry_after: Duration::from_secs(120),
+        })
+        .context("while downloading");
+        assert_eq!(policy.next(1, &error, max_wait), Some(max_wait));
+        assert_eq!(policy.next(3, &error, max_wait), None);
+        let reset = anyhow::Error::from(io::Error::from(ErrorKind::ConnectionReset));
+        assert_eq!(policy.next(1, &reset, max_wait), None);
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            jitter: 0.0,
+            ..policy
+        };
+        assert_eq!(
+            policy.next(1, &reset, max_wait),
+            Some(Duration::from_secs(1))
+        );
+    }
+}
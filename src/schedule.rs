@@ -0,0 +1,346 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+use anyhow::{bail, Result};
+use url::Url;
+
+use crate::list::Entry;
+
+/// Queues of entries which hand them out to workers once entries they depend on are completed
+///
+/// Each host has its own queue, and each worker has home queue it picks entries from first,
+/// so workers are spread over hosts and slow host doesn't hold up all of them,
+/// even if list interleaves it with fast ones. Worker whose home queue has nothing to pick
+/// steals from the longest queue.
+///
+/// Entries of each queue are picked in list order, skipping ones whose dependencies
+/// aren't completed yet. Dependency which isn't part of the run is considered completed
+/// if its destination exists, e.g. it was downloaded by previous run.
+///
+/// Entries with deadlines are picked before all others, the nearest deadline first,
+/// whichever queue they're in
+pub struct Schedule {
+    state: Mutex<State>,
+}
+
+/// Schedule state, guarded by mutex
+struct State {
+    /// Entries not started yet, by their indices, by host in order of its first entry
+    queues: Vec<BTreeMap<usize, Entry>>,
+    /// Whether entry completed successfully, by destination name; `None` if it's not done yet
+    outcomes: HashMap<String, Option<bool>>,
+    /// Number of entries not started yet which have deadlines
+    deadlines: usize,
+    /// Number of started jobs which haven't completed yet
+    running: usize,
+    /// Lanes waiting for dependencies to complete
+    wakers: Vec<Waker>,
+}
+
+impl Schedule {
+    /// Creates schedule of entries
+    ///
+    /// # Arguments
+    /// * entries - entries of the run, with their indices, which are distinct
+    /// * dest_dir - destination directory, where dependencies outside the run are looked for
+    pub fn new(entries: Vec<(usize, Entry)>, dest_dir: &Path) -> Schedule {
+        let mut outcomes: HashMap<_, _> = entries
+            .iter()
+            .map(|(_, entry)| (entry.name.clone(), None))
+            .collect();
+        for (_, entry) in &entries {
+            for name in &entry.after {
+                if !outcomes.contains_key(name) {
+                    outcomes.insert(name.clone(), Some(dest_dir.join(name).exists()));
+                }
+            }
+        }
+        let deadlines = entries
+            .iter()
+            .filter(|(_, entry)| entry.deadline.is_some())
+            .count();
+        let mut hosts = HashMap::new();
+        let mut queues: Vec<BTreeMap<_, _>> = Vec::new();
+        for (i, entry) in entries {
+            let host = Url::parse(&entry.url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_owned));
+            let queue = *hosts.entry(host).or_insert_with(|| {
+                queues.push(BTreeMap::new());
+                queues.len() - 1
+            });
+            queues[queue].insert(i, entry);
+        }
+        Schedule {
+            state: Mutex::new(State {
+                queues,
+                outcomes,
+                deadlines,
+                running: 0,
+                wakers: Vec::new(),
+            }),
+        }
+    }
+    /// Picks next entry whose dependencies are completed
+    ///
+    /// # Arguments
+    /// * worker - number of worker which asks for entry, which decides its home queue
+    /// * accept - which entries can be picked at all, so lanes can share single schedule
+    ///
+    /// # Returns
+    /// Entry to start, or `None` if there are no more acceptable entries.
+    /// If nothing runs while remaining entries wait for each other, the earliest of them
+    /// is handed out anyway, so it fails and the cycle is broken
+    pub fn poll_next(
+        &self,
+        cx: &mut Context<'_>,
+        worker: usize,
+        accept: impl Fn(&Entry) -> bool,
+    ) -> Poll<Option<(usize, Entry)>> {
+        let mut state = self.state.lock().unwrap();
+        let State {
+            queues,
+            outcomes,
+            deadlines,
+            running,
+            wakers,
+        } = &mut *state;
+        // Home queue goes first, then others from the longest one
+        let home = worker % queues.len().max(1);
+        let mut order: Vec<_> = (0..queues.len()).filter(|&queue| queue != home).collect();
+        order.sort_by_key(|&queue| Reverse(queues[queue].len()));
+        if home < queues.len() {
+            order.insert(0, home);
+        }
+        let acceptable = || {
+            order.iter().flat_map(|&queue| {
+                queues[queue]
+                    .iter()
+                    .filter(|(_, entry)| accept(entry))
+                    .map(move |(&i, entry)| (queue, i, entry))
+            })
+        };
+        // Queues are ordered, so the earliest entry is the first acceptable one of some queue
+        let Some(first) = order
+            .iter()
+            .filter_map(|&queue| {
+                queues[queue]
+                    .iter()
+                    .find(|(_, entry)| accept(entry))
+                    .map(|(&i, _)| (queue, i))
+            })
+            .min_by_key(|&(_, i)| i)
+        else {
+            return Poll::Ready(None);
+        };
+        let mut ready = acceptable().filter(|(.., entry)| {
+            entry
+                .after
+                .iter()
+                .all(|name| outcomes.get(name).is_some_and(Option::is_some))
+        });
+        // Only entries with deadlines need all ready ones to be looked through
+        let ready = match *deadlines {
+            0 => ready.next(),
+            _ => ready.min_by_key(|(.., entry)| (entry.deadline.is_none(), entry.deadline)),
+        }
+        .map(|(queue, i, _)| (queue, i));
+        let (queue, i) = match (ready, *running) {
+            (Some(ready), _) => ready,
+            (None, 0) => first,
+            (None, _) => {
+                wakers.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+        };
+        *running += 1;
+        let next = queues[queue].remove_entry(&i);
+        if next
+            .as_ref()
+            .is_some_and(|(_, entry)| entry.deadline.is_some())
+        {
+            *deadlines -= 1;
+        }
+        Poll::Ready(next)
+    }
+    /// Checks that all dependencies of entry completed successfully
+    pub fn check(&self, entry: &Entry) -> Result<()> {
+        let state = self.state.lock().unwrap();
+        for name in &entry.after {
+            match state.outcomes.get(name).copied().flatten() {
+                Some(true) => {}
+                Some(false) => bail!("dependency {} didn't complete", name),
+                None => bail!(
+                    "dependency {} can't complete, entries depend on each other",
+                    name
+                ),
+            }
+        }
+        Ok(())
+    }
+    /// Records outcome of started entry, letting entries which depend on it start
+    pub fn finish(&self, name: &str, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.running -= 1;
+        state.outcomes.insert(name.to_owned(), Some(success));
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Schedule;
+    use crate::list::Entry;
+    use futures::task::noop_waker;
+    use std::task::{Context, Poll};
+
+    fn entry(name: &str, after: &[&str]) -> (usize, Entry) {
+        let entry = Entry {
+            after: after.iter().map(|name| name.to_string()).collect(),
+            ..Entry::new("http://a/", name)
+        };
+        (0, entry)
+    }
+
+    fn indexed(entries: Vec<(usize, Entry)>) -> Vec<(usize, Entry)> {
+        entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, entry))| (i, entry))
+            .collect()
+    }
+
+    fn next(schedule: &Schedule) -> Poll<Option<String>> {
+        next_for(schedule, 0)
+    }
+
+    fn next_for(schedule: &Schedule, worker: usize) -> Poll<Option<String>> {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        schedule
+            .poll_next(&mut cx, worker, |_| true)
+            .map(|next| next.map(|(_, entry)| entry.name))
+    }
+
+    #[test]
+    fn dependency_order() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("present"), "").unwrap();
+        let schedule = Schedule::new(
+            indexed(vec![
+                entry("artifact", &["signature"]),
+                entry("signature", &[]),
+                entry("other", &["present"]),
+                entry("broken", &["missing"]),
+            ]),
+            dir.path(),
+        );
+        // Entry waits for its dependency, others go ahead
+        assert_eq!(next(&schedule), Poll::Ready(Some("signature".to_owned())));
+        assert_eq!(next(&schedule), Poll::Ready(Some("other".to_owned())));
+        assert_eq!(next(&schedule), Poll::Ready(Some("broken".to_owned())));
+        assert_eq!(next(&schedule), Poll::Pending);
+        schedule.finish("signature", true);
+        assert_eq!(next(&schedule), Poll::Ready(Some("artifact".to_owned())));
+        assert_eq!(next(&schedule), Poll::Ready(None));
+        // Dependencies outside the run are satisfied only by existing files
+        assert!(schedule.check(&entry("other", &["present"]).1).is_ok());
+        assert!(schedule.check(&entry("broken", &["missing"]).1).is_err());
+        schedule.finish("signature", false);
+        assert!(schedule
+            .check(&entry("artifact", &["signature"]).1)
+            .is_err());
+    }
+
+    #[test]
+    fn dependency_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let schedule = Schedule::new(
+            indexed(vec![entry("a", &["b"]), entry("b", &["a"])]),
+            dir.path(),
+        );
+        // Nothing runs, so one of entries is released to fail
+        assert_eq!(next(&schedule), Poll::Ready(Some("a".to_owned())));
+        assert!(schedule.check(&entry("a", &["b"]).1).is_err());
+        assert_eq!(next(&schedule), Poll::Pending);
+        schedule.finish("a", false);
+        assert_eq!(next(&schedule), Poll::Ready(Some("b".to_owned())));
+    }
+
+    #[test]
+    fn host_queues() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = [
+            ("http://slow/1", "s1"),
+            ("http://fast/1", "f1"),
+            ("http://slow/2", "s2"),
+            ("http://fast/2", "f2"),
+            ("http://slow/3", "s3"),
+            ("http://slow/4", "s4"),
+        ];
+        let schedule = Schedule::new(
+            entries
+                .iter()
+                .enumerate()
+                .map(|(i, (url, name))| (i, Entry::new(*url, *name)))
+                .collect(),
+            dir.path(),
+        );
+        let ready = |name: &str| Poll::Ready(Some(name.to_owned()));
+        // Workers start on different hosts
+        assert_eq!(next_for(&schedule, 0), ready("s1"));
+        assert_eq!(next_for(&schedule, 1), ready("f1"));
+        assert_eq!(next_for(&schedule, 1), ready("f2"));
+        // Worker whose host ran out steals from the longest queue
+        assert_eq!(next_for(&schedule, 1), ready("s2"));
+        assert_eq!(next_for(&schedule, 0), ready("s3"));
+        assert_eq!(next_for(&schedule, 3), ready("s4"));
+        assert_eq!(next_for(&schedule, 0), Poll::Ready(None));
+    }
+
+    #[test]
+    fn deadline_order() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = tempfile::tempdir().unwrap();
+        let at = |secs| Some(UNIX_EPOCH + Duration::from_secs(secs));
+        let entries = [
+            ("http://a/1", "plain", None),
+            ("http://b/1", "late", at(200)),
+            ("http://a/2", "soon", at(100)),
+            ("http://a/3", "blocked", at(50)),
+        ];
+        let schedule = Schedule::new(
+            entries
+                .into_iter()
+                .enumerate()
+                .map(|(i, (url, name, deadline))| {
+                    let after = match name {
+                        "blocked" => vec!["plain".to_owned()],
+                        _ => Vec::new(),
+                    };
+                    let entry = Entry {
+                        deadline,
+                        after,
+                        ..Entry::new(url, name)
+                    };
+                    (i, entry)
+                })
+                .collect(),
+            dir.path(),
+        );
+        let ready = |name: &str| Poll::Ready(Some(name.to_owned()));
+        // Nearer deadline goes first, even from other host's queue,
+        // while entry waiting for dependency doesn't hold others up
+        assert_eq!(next(&schedule), ready("soon"));
+        assert_eq!(next(&schedule), ready("late"));
+        assert_eq!(next(&schedule), ready("plain"));
+        schedule.finish("plain", true);
+        assert_eq!(next(&schedule), ready("blocked"));
+    }
+}
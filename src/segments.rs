@@ -0,0 +1,156 @@
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+/// Smallest segment which is worth separate connection in `auto` mode, in bytes
+const MIN_SEGMENT_SIZE: u64 = 1024 * 1024;
+/// Largest number of segments per file chosen in `auto` mode
+const MAX_AUTO_SEGMENTS: usize = 16;
+/// In `auto` mode, each segment should take roughly this long, in seconds
+const TARGET_SEGMENT_TIME: f64 = 5.0;
+/// Per-connection throughput assumed until first transfer is measured, in bytes per second
+const DEFAULT_THROUGHPUT: f64 = 1024.0 * 1024.0;
+/// Weight of the newest sample in throughput moving average
+const THROUGHPUT_WEIGHT: f64 = 0.25;
+
+/// Number of ranged connections used to download single file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Segments {
+    /// Same number of segments for every file; 1 disables segmentation
+    Fixed(usize),
+    /// Number of segments chosen per file, from its size and measured throughput
+    Auto,
+}
+
+impl Segments {
+    /// Chooses number of segments for file
+    ///
+    /// # Arguments
+    /// * len - file size, in bytes
+    /// * throughput - measured per-connection throughput, in bytes per second, if known
+    ///
+    /// In `auto` mode, file is split so each segment takes a few seconds to download
+    /// at measured throughput, but segments are never smaller than 1 MiB
+    pub fn count(self, len: u64, throughput: Option<f64>) -> usize {
+        let count = match self {
+            Segments::Fixed(count) => count,
+            Segments::Auto => {
+                let segment_size = (throughput.unwrap_or(DEFAULT_THROUGHPUT) * TARGET_SEGMENT_TIME)
+                    .max(MIN_SEGMENT_SIZE as f64);
+                ((len as f64 / segment_size) as usize).clamp(1, MAX_AUTO_SEGMENTS)
+            }
+        };
+        // Empty segments make no sense
+        count.min(len.try_into().unwrap_or(usize::MAX)).max(1)
+    }
+}
+
+impl FromStr for Segments {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Segments> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Segments::Auto);
+        }
+        match s
+            .parse()
+            .with_context(|| format!("{}: expected number or auto", s))?
+        {
+            0 => bail!("number of segments must be positive"),
+            count => Ok(Segments::Fixed(count)),
+        }
+    }
+}
+impl fmt::Display for Segments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Segments::Fixed(count) => write!(f, "{}", count),
+            Segments::Auto => f.write_str("auto"),
+        }
+    }
+}
+/// Splits range `0..len` into specified number of consecutive, nearly equal ranges
+pub fn split(len: u64, count: usize) -> Vec<Range<u64>> {
+    let count = count as u64;
+    (0..count)
+        .map(|i| len * i / count..len * (i + 1) / count)
+        .collect()
+}
+
+/// Running estimate of per-connection throughput, shared by all jobs
+#[derive(Debug, Default)]
+pub struct Throughput(Mutex<Option<f64>>);
+
+impl Throughput {
+    /// Accounts finished transfer made over single connection
+    pub fn record(&self, bytes: u64, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if bytes == 0 || secs == 0.0 {
+            return;
+        }
+        let sample = bytes as f64 / secs;
+        let mut average = self.0.lock().unwrap();
+        *average = Some(match *average {
+            Some(average) => average + (sample - average) * THROUGHPUT_WEIGHT,
+            None => sample,
+        });
+    }
+    /// Current estimate, in bytes per second, if any transfer was measured
+    pub fn estimate(&self) -> Option<f64> {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split, Segments, Throughput, MIN_SEGMENT_SIZE};
+    use assert_matches::assert_matches;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_segments() {
+        assert_eq!("4".parse::<Segments>().unwrap(), Segments::Fixed(4));
+        assert_eq!("Auto".parse::<Segments>().unwrap(), Segments::Auto);
+        assert_matches!("0".parse::<Segments>(), Err(_));
+        assert_matches!("-1".parse::<Segments>(), Err(_));
+        assert_matches!("many".parse::<Segments>(), Err(_));
+    }
+
+    #[test]
+    fn segment_count() {
+        assert_eq!(Segments::Fixed(4).count(100, None), 4);
+        assert_eq!(Segments::Fixed(4).count(2, None), 2);
+        assert_eq!(Segments::Fixed(4).count(0, None), 1);
+        // Small files aren't split
+        assert_eq!(Segments::Auto.count(MIN_SEGMENT_SIZE, None), 1);
+        // Slow connections get more segments than fast ones
+        let len = 100 * MIN_SEGMENT_SIZE;
+        let slow = Segments::Auto.count(len, Some(MIN_SEGMENT_SIZE as f64));
+        let fast = Segments::Auto.count(len, Some(10.0 * MIN_SEGMENT_SIZE as f64));
+        assert!(slow > fast && fast > 1);
+        // But never too many
+        assert_eq!(Segments::Auto.count(len, Some(1.0)), 16);
+    }
+
+    #[test]
+    fn split_ranges() {
+        assert_eq!(split(10, 3), [0..3, 3..6, 6..10]);
+        assert_eq!(split(4, 2), [0..2, 2..4]);
+    }
+
+    #[test]
+    fn throughput_average() {
+        let throughput = Throughput::default();
+        assert_eq!(throughput.estimate(), None);
+        throughput.record(1000, Duration::from_secs(1));
+        assert_eq!(throughput.estimate(), Some(1000.0));
+        throughput.record(5000, Duration::from_secs(1));
+        assert_eq!(throughput.estimate(), Some(2000.0));
+        throughput.record(0, Duration::from_secs(1));
+        assert_eq!(throughput.estimate(), Some(2000.0));
+    }
+}
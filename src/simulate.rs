@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{Clock, ManualClock};
+use crate::token_bucket::TokenBucket;
+
+/// Time step of simulation
+const TICK: Duration = Duration::from_millis(10);
+
+/// Conditions under which download run is simulated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Simulation {
+    /// Number of concurrent downloads
+    pub threads_num: usize,
+    /// Max overall download speed, in bytes per second; 0 means no limit
+    pub speed_limit: usize,
+    /// Assumed throughput of single connection, in bytes per second
+    pub connection_speed: usize,
+}
+
+/// Estimated outcome of simulated run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Estimate {
+    /// How long the whole run takes
+    pub duration: Duration,
+    /// Number of files accounted by simulation
+    pub files: usize,
+    /// Total size of accounted files, in bytes
+    pub bytes: u64,
+    /// Number of files left out, because their size isn't known
+    pub unknown: usize,
+}
+
+impl Simulation {
+    /// Estimates how long downloading files of specified sizes takes
+    ///
+    /// # Arguments
+    /// * sizes - sizes of files in list order, `None` where size isn't known
+    ///
+    /// Files are scheduled in list order into concurrency slots, like real run does,
+    /// and share speed limit through token bucket driven by simulated clock
+    pub fn run(&self, sizes: impl IntoIterator<Item = Option<u64>>) -> Estimate {
+        let mut estimate = Estimate::default();
+        let mut queue = Vec::new();
+        for size in sizes {
+            match size {
+                Some(size) => {
+                    estimate.files += 1;
+                    estimate.bytes += size;
+                    queue.push(size);
+                }
+                None => estimate.unknown += 1,
+            }
+        }
+        let clock = Arc::new(ManualClock::new());
+        let started = clock.now();
+        let mut bucket = TokenBucket::with_clock(self.speed_limit, self.speed_limit, clock.clone());
+        // Bytes per tick one connection is able to transfer, at least one to keep progressing
+        let per_tick = ((self.connection_speed as f64 * TICK.as_secs_f64()) as u64).max(1);
+        let mut queue = queue.into_iter();
+        let mut active: Vec<u64> = Vec::new();
+        loop {
+            active.retain(|remaining| *remaining > 0);
+            while active.len() < self.threads_num.max(1) {
+                match queue.next() {
+                    Some(size) => active.push(size),
+                    None => break,
+                }
+            }
+            if active.is_empty() {
+                break;
+            }
+            clock.advance(TICK);
+            for remaining in &mut active {
+                let wanted = per_tick.min(*remaining);
+                let taken = bucket.take(wanted.try_into().unwrap_or(usize::MAX)) as u64;
+                *remaining -= taken;
+            }
+        }
+        estimate.duration = clock.now() - started;
+        estimate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Estimate, Simulation};
+    use std::time::Duration;
+
+    #[test]
+    fn simulated_runs() {
+        let unlimited = Simulation {
+            threads_num: 2,
+            speed_limit: 0,
+            connection_speed: 1000,
+        };
+        // Two files at once, each taking a second, then the third one
+        assert_eq!(
+            unlimited.run([Some(1000), Some(1000), None, Some(500)]),
+            Estimate {
+                duration: Duration::from_millis(1500),
+                files: 3,
+                bytes: 2500,
+                unknown: 1,
+            }
+        );
+        // Speed limit is shared by all connections
+        let limited = Simulation {
+            speed_limit: 1000,
+            ..unlimited
+        };
+        let estimate = limited.run([Some(1000), Some(1000)]);
+        assert!(estimate.duration >= Duration::from_secs(2));
+        assert!(estimate.duration < Duration::from_millis(2100));
+
+        assert_eq!(unlimited.run([]).duration, Duration::ZERO);
+    }
+}
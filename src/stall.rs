@@ -0,0 +1,204 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::{sleep, Instant, Sleep};
+
+/// Longest time between checks of speed while no data arrives
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of points speed is averaged over, per window
+const SAMPLES_PER_WINDOW: u32 = 16;
+
+/// Lowest acceptable transfer speed, like curl's low-speed limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LowSpeed {
+    /// Minimum speed, in bytes per second
+    pub min: u64,
+    /// How long average speed must stay below minimum for transfer to be aborted
+    pub time: Duration,
+}
+
+/// Reader which fails with `TimedOut` error once data arrives slower than minimum speed,
+/// as moving average over the last `time` spent waiting for it
+///
+/// Only time spent waiting for data counts, so pauses speed limiter makes between reads
+/// aren't mistaken for stalls
+pub struct StallGuard<R> {
+    inner: R,
+    low_speed: Option<LowSpeed>,
+    /// Total time spent waiting for data so far
+    waited: Duration,
+    /// Total number of bytes read so far
+    read: u64,
+    /// Points of waiting time and bytes read, covering the last window
+    samples: VecDeque<(Duration, u64)>,
+    /// When current wait for data started being accounted, if reader is waiting
+    pending_since: Option<Instant>,
+    /// Wakes reader up to check speed while no data arrives
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> StallGuard<R> {
+    /// Wraps reader; without minimum speed, reader is passed through as is
+    pub fn new(inner: R, low_speed: Option<LowSpeed>) -> StallGuard<R> {
+        StallGuard {
+            inner,
+            low_speed,
+            waited: Duration::ZERO,
+            read: 0,
+            samples: VecDeque::from([(Duration::ZERO, 0)]),
+            pending_since: None,
+            timer: None,
+        }
+    }
+    /// Accounts time waited for data up to now, then checks average speed over the last window
+    ///
+    /// # Arguments
+    /// * waiting - whether reader keeps waiting for data after now
+    fn check(&mut self, low_speed: LowSpeed, waiting: bool) -> io::Result<()> {
+        let now = Instant::now();
+        if let Some(since) = self.pending_since {
+            self.waited += now - since;
+        }
+        self.pending_since = waiting.then_some(now);
+        let granularity = low_speed.time / SAMPLES_PER_WINDOW;
+        if self
+            .samples
+            .back()
+            .is_none_or(|&(waited, _)| self.waited >= waited + granularity)
+        {
+            self.samples.push_back((self.waited, self.read));
+        }
+        // The oldest point kept is the last one at or before window start
+        let window_start = self.waited.saturating_sub(low_speed.time);
+        while self.samples.len() > 1 && self.samples[1].0 <= window_start {
+            self.samples.pop_front();
+        }
+        if self.waited < low_speed.time {
+            return Ok(());
+        }
+        let (start, bytes) = self.samples[0];
+        let speed = (self.read - bytes) as f64 / (self.waited - start).as_secs_f64();
+        if speed < low_speed.min as f64 {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "transfer stalled, slower than {} bytes/s for {:.1}s",
+                    low_speed.min,
+                    low_speed.time.as_secs_f64()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for StallGuard<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let Some(low_speed) = this.low_speed else {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        };
+        let filled = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                let len = buf.filled().len() - filled;
+                this.read += len as u64;
+                this.timer = None;
+                // Transfer which has just completed isn't stalled
+                match len {
+                    0 => Poll::Ready(Ok(())),
+                    _ => Poll::Ready(this.check(low_speed, false)),
+                }
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => {
+                if let Err(err) = this.check(low_speed, true) {
+                    return Poll::Ready(Err(err));
+                }
+                let interval = (low_speed.time / 4).min(CHECK_INTERVAL);
+                let timer = this.timer.get_or_insert_with(|| Box::pin(sleep(interval)));
+                if timer.as_mut().poll(cx).is_ready() {
+                    timer.as_mut().reset(Instant::now() + interval);
+                    let _ = timer.as_mut().poll(cx);
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LowSpeed, StallGuard};
+    use std::io::ErrorKind;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn stalled_transfers() {
+        let low_speed = LowSpeed {
+            min: 1000,
+            time: Duration::from_millis(200),
+        };
+        Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(async {
+                // Server which sends a bit and then goes silent, keeping connection open
+                let (reader, mut server) = tokio::io::duplex(64);
+                server.write_all(b"data").await.unwrap();
+                let mut guard = StallGuard::new(reader, Some(low_speed));
+                let started = std::time::Instant::now();
+                let err = guard.read_to_end(&mut Vec::new()).await.unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::TimedOut);
+                assert!(started.elapsed() < Duration::from_secs(2));
+
+                // Short pause within fast transfer doesn't drag average below minimum
+                let reader = tokio_test::io::Builder::new()
+                    .read(&[0; 1000])
+                    .wait(Duration::from_millis(100))
+                    .read(&[0; 1000])
+                    .build();
+                let mut guard = StallGuard::new(reader, Some(low_speed));
+                let mut data = Vec::new();
+                guard.read_to_end(&mut data).await.unwrap();
+                assert_eq!(data.len(), 2000);
+
+                // Pauses of reading side, like ones of speed limiter, don't count
+                let reader = tokio_test::io::Builder::new()
+                    .read(b"data")
+                    .read(b"more")
+                    .build();
+                let mut guard = StallGuard::new(reader, Some(low_speed));
+                let mut buf = [0; 4];
+                guard.read_exact(&mut buf).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                guard.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"more");
+
+                // Without minimum speed, slow data is fine
+                let reader = tokio_test::io::Builder::new()
+                    .read(b"data")
+                    .wait(Duration::from_millis(300))
+                    .read(b"more")
+                    .build();
+                let mut data = Vec::new();
+                StallGuard::new(reader, None)
+                    .read_to_end(&mut data)
+                    .await
+                    .unwrap();
+                assert_eq!(data, b"datamore");
+            });
+    }
+}
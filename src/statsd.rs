@@ -0,0 +1,83 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+/// Prefix of all metric names sent by application
+const PREFIX: &str = "httpdl";
+
+/// Minimalistic StatsD client, sends metrics as UDP datagrams
+///
+/// Delivery is fire-and-forget, as StatsD protocol intends:
+/// any errors during sending are silently ignored,
+/// so metrics collector being down never affects downloads
+pub struct Statsd {
+    socket: UdpSocket,
+}
+
+impl Statsd {
+    /// Creates client which sends metrics to specified `host:port`
+    ///
+    /// Socket is bound to address of the same family as collector's one, IPv4 or IPv6
+    pub fn connect(addr: &str) -> Result<Statsd> {
+        let target = addr
+            .to_socket_addrs()?
+            .next()
+            .with_context(|| format!("{}: address cannot be resolved", addr))?;
+        let local: SocketAddr = match target {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+        Ok(Statsd { socket })
+    }
+    /// Increments counter by specified value
+    pub fn count(&self, name: &str, value: u64) {
+        self.send(&format!("{}.{}:{}|c", PREFIX, name, value));
+    }
+    /// Records timer value, in milliseconds
+    pub fn timing(&self, name: &str, value: Duration) {
+        self.send(&format!("{}.{}:{}|ms", PREFIX, name, value.as_millis()));
+    }
+
+    fn send(&self, metric: &str) {
+        let _ = self.socket.send(metric.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Statsd;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    #[test]
+    fn send_metrics() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let client = Statsd::connect(&server.local_addr().unwrap().to_string()).unwrap();
+
+        client.count("bytes", 1024);
+        client.timing("job.duration", Duration::from_millis(1500));
+
+        let mut buf = [0u8; 256];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"httpdl.bytes:1024|c");
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"httpdl.job.duration:1500|ms");
+
+        // IPv6 collector, where host has IPv6 loopback
+        if let Ok(server) = UdpSocket::bind("[::1]:0") {
+            server
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let client = Statsd::connect(&server.local_addr().unwrap().to_string()).unwrap();
+            client.count("bytes", 1);
+            let len = server.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], b"httpdl.bytes:1|c");
+        }
+    }
+}
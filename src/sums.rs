@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use reqwest::{Client, StatusCode};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::digest::{Algorithm, Checksum};
+use crate::hashdb::HashDb;
+use crate::list::Entry;
+use crate::resume::hash_prefix;
+use crate::retry::RetryPolicy;
+
+/// Max size of request head served endpoint accepts
+const MAX_HEAD: usize = 8 * 1024;
+
+/// Document served by verification endpoint
+pub struct Document {
+    /// Value of `Content-Type` header
+    pub content_type: &'static str,
+    /// Document contents
+    pub body: Vec<u8>,
+}
+
+/// Builds checksum manifest of downloaded files, in format of `sha256sum` and similar tools
+///
+/// # Arguments
+/// * dest_dir - directory where files reside
+/// * names - names of files, relative to directory
+/// * algo - checksum algorithm
+/// * hash_db - database of checksums; files recorded in it aren't hashed,
+///   and checksums of the other ones are recorded
+///
+/// # Returns
+/// Manifest with line per file, or error if any of files can't be read
+pub async fn manifest(
+    dest_dir: &Path,
+    names: impl IntoIterator<Item = impl AsRef<str>>,
+    algo: Algorithm,
+    hash_db: Option<&HashDb>,
+) -> Result<String> {
+    let mut manifest = String::new();
+    for name in names {
+        let name = name.as_ref();
+        let path = dest_dir.join(name);
+        let recorded = match hash_db {
+            Some(hash_db) => hash_db.checksum(&path, algo).await,
+            None => None,
+        };
+        let digest = match recorded {
+            Some(checksum) => checksum.value,
+            None => {
+                let len = tokio::fs::metadata(&path).await?.len();
+                let digest = hash_prefix(&path, len, algo.hasher()).await?.finalize();
+                if let Some(hash_db) = hash_db {
+                    let checksum = Checksum {
+                        algorithm: algo,
+                        value: digest.clone(),
+                    };
+                    hash_db.record(&path, Some(checksum), None).await?;
+                }
+                digest
+            }
+        };
+        manifest += &format!("{}  {}\n", hex::encode(digest), name);
+    }
+    Ok(manifest)
+}
+/// Name under which manifest of specified algorithm is usually published, like `SHA256SUMS`
+pub fn manifest_name(algo: Algorithm) -> String {
+    format!("{}SUMS", algo.name().to_uppercase())
+}
+/// Finds checksum of file in manifest, in format of `sha256sum` and similar tools,
+/// or in BSD format like `SHA256 (name) = <hex>`
+///
+/// # Arguments
+/// * text - manifest contents
+/// * name - name of file; paths in manifest may have directories before it
+/// * algo - checksum algorithm
+///
+/// # Returns
+/// Checksum of file, or the only checksum of manifest which names no file;
+/// `None` if there's no such line, or its checksum is malformed
+pub fn find_checksum(text: &str, name: &str, algo: Algorithm) -> Option<Checksum> {
+    let lines: Vec<_> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    let bsd_prefix = format!("{} (", algo.name().to_uppercase());
+    for line in &lines {
+        let (hex, path) = match line.strip_prefix(&bsd_prefix) {
+            Some(rest) => match rest.rsplit_once(") = ") {
+                Some((path, hex)) => (hex, path),
+                None => continue,
+            },
+            // Asterisk marks file which was hashed in binary mode
+            None => match line.split_once(char::is_whitespace) {
+                Some((hex, path)) => (hex, path.trim_start().trim_start_matches('*')),
+                None => (*line, ""),
+            },
+        };
+        let matches = path == name
+            || path.ends_with(&format!("/{}", name))
+            || (path.is_empty() && lines.len() == 1);
+        if matches {
+            return Checksum::parse(algo, hex).ok();
+        }
+    }
+    None
+}
+/// Checksum manifests fetched from servers, shared by jobs so each one is fetched once
+pub(crate) struct RemoteSums {
+    /// Algorithm of manifests looked for next to files, if they're looked for
+    discover: Option<Algorithm>,
+    /// How failed fetches are retried
+    retry: RetryPolicy,
+    /// Longest delay server may ask for with `Retry-After`
+    max_retry_after: Duration,
+    /// Manifest contents by URL, `None` if server doesn't have it; each URL is fetched
+    /// by one job at a time, while others wait for it, and failures aren't remembered
+    fetched: Mutex<HashMap<String, Arc<Fetched>>>,
+}
+
+/// Manifest which is fetched once, `None` if server doesn't have it
+type Fetched = OnceCell<Option<Arc<str>>>;
+
+impl RemoteSums {
+    /// Creates empty cache of manifests
+    ///
+    /// # Arguments
+    /// * discover - look for manifest of this algorithm, like `SHA256SUMS`, next to files
+    ///   of entries which have no checksum
+    /// * retry - how failed fetches are retried, same as jobs
+    /// * max_retry_after - longest delay server may ask for with `Retry-After`
+    pub fn new(
+        discover: Option<Algorithm>,
+        retry: RetryPolicy,
+        max_retry_after: Duration,
+    ) -> RemoteSums {
+        RemoteSums {
+            discover,
+            retry,
+            max_retry_after,
+            fetched: Mutex::default(),
+        }
+    }
+    /// Finds expected checksum of entry's file in remote manifest
+    ///
+    /// # Returns
+    /// Checksum from manifest specified by entry, or from the one found next to file;
+    /// `None` if entry specifies none and none was found.
+    /// Fails if manifest specified by entry can't be fetched or doesn't list file
+    pub async fn checksum(&self, client: &Client, entry: &Entry) -> Result<Option<Checksum>> {
+        let Ok(url) = Url::parse(&entry.url) else {
+            return Ok(None);
+        };
+        // Manifests name files as they're stored, not as they're encoded in URLs
+        let name = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .map(|name| percent_encoding::percent_decode_str(name).decode_utf8_lossy())
+            .unwrap_or_default();
+        if let Some(checksum_url) = &entry.checksum_url {
+            let text = match self.fetch(client, &checksum_url.url).await {
+                Ok(Some(text)) => text,
+                Ok(None) => bail!("{}: checksum file can't be fetched", checksum_url.url),
+                Err(err) => {
+                    return Err(err).with_context(|| format!("checksum file {}", checksum_url.url))
+                }
+            };
+            return match find_checksum(&text, &name, checksum_url.algorithm) {
+                Some(checksum) => Ok(Some(checksum)),
+                None => bail!("{}: no checksum of {}", checksum_url.url, name),
+            };
+        }
+        let Some(algo) = self.discover else {
+            return Ok(None);
+        };
+        if !matches!(url.scheme(), "http" | "https") {
+            return Ok(None);
+        }
+        // Discovery is best effort, so missing manifest means file isn't verified
+        let sums_url = url.join(&manifest_name(algo))?;
+        match self.fetch(client, sums_url.as_str()).await {
+            Ok(Some(text)) => Ok(find_checksum(&text, &name, algo)),
+            _ => Ok(None),
+        }
+    }
+    /// Fetches manifest, unless it was fetched before, retrying transient failures
+    ///
+    /// # Returns
+    /// Manifest contents, or `None` if server doesn't have it.
+    /// Fails if all attempts failed; next call tries again
+    async fn fetch(&self, client: &Client, url: &str) -> Result<Option<Arc<str>>> {
+        let cell = self
+            .fetched
+            .lock()
+            .unwrap()
+            .entry(url.to_owned())
+            .or_default()
+            .clone();
+        let text = cell
+            .get_or_try_init(|| async {
+                let mut attempt = 1;
+                loop {
+                    match fetch_once(client, url).await {
+                        Ok(text) => return Ok(text),
+                        Err(err) => match self.retry.next(attempt, &err, self.max_retry_after) {
+                            Some(delay) => tokio::time::sleep(delay).await,
+                            None => return Err(err),
+                        },
+                    }
+                    attempt += 1;
+                }
+            })
+            .await?;
+        Ok(text.clone())
+    }
+}
+/// Fetches manifest with single request
+///
+/// # Returns
+/// Manifest contents, or `None` if server doesn't have it
+async fn fetch_once(client: &Client, url: &str) -> Result<Option<Arc<str>>> {
+    let response = client.get(url).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(Arc::from(response.error_for_status()?.text().await?)))
+}
+/// Serves documents over plain HTTP, read-only, until future is dropped
+///
+/// # Arguments
+/// * listener - socket to accept connections on
+/// * documents - documents by request path, like `/SHA256SUMS`
+///
+/// Only `GET` and `HEAD` requests are served, one per connection
+pub async fn serve(listener: TcpListener, documents: HashMap<String, Document>) -> Result<()> {
+    let documents = Arc::new(documents);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let documents = documents.clone();
+        // Misbehaving peer affects only its own connection
+        tokio::spawn(async move {
+            let _ = respond(stream, &documents).await;
+        });
+    }
+}
+/// Reads single request from connection and answers it
+async fn respond(mut stream: TcpStream, documents: &HashMap<String, Document>) -> Result<()> {
+    let Some((method, target)) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    // Query doesn't select anything
+    let path = target.split('?').next().unwrap_or_default();
+    let head = method == "HEAD";
+    let (status, document) = match (method.as_str(), documents.get(path)) {
+        ("GET" | "HEAD", Some(document)) => ("200 OK", Some(document)),
+        ("GET" | "HEAD", None) => ("404 Not Found", None),
+        _ => ("405 Method Not Allowed", None),
+    };
+    let (content_type, body) = match document {
+        Some(document) => (document.content_type, document.body.as_slice()),
+        None => ("text/plain", status.as_bytes()),
+    };
+    write_head(&mut stream, status, content_type, body.len() as u64).await?;
+    if !head {
+        stream.write_all(body).await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+/// Reads head of HTTP request from connection
+///
+/// # Returns
+/// Request method and target, or `None` if connection was closed or head is too large
+pub(crate) async fn read_request(stream: &mut TcpStream) -> Result<Option<(String, String)>> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || head.len() + read > MAX_HEAD {
+            return Ok(None);
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let target = request_line.next().unwrap_or_default().to_owned();
+    Ok(Some((method, target)))
+}
+/// Writes head of HTTP response; connection is closed after response body
+pub(crate) async fn write_head(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    len: u64,
+) -> Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, len
+    );
+    stream.write_all(head.as_bytes()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_checksum, manifest, manifest_name, serve, Document, RemoteSums};
+    use crate::digest::{Algorithm, Checksum};
+    use crate::hashdb::HashDb;
+    use crate::retry::RetryPolicy;
+    use reqwest::Client;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn serve_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a"), b"abc").unwrap();
+        fs::write(dir.path().join("sub/b"), b"").unwrap();
+        assert_eq!(manifest_name(Algorithm::Sha256), "SHA256SUMS");
+
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let sums = manifest(dir.path(), ["a", "sub/b"], Algorithm::Md5, None)
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    sums,
+                    "900150983cd24fb0d6963f7d28e17f72  a\n\
+                     d41d8cd98f00b204e9800998ecf8427e  sub/b\n"
+                );
+                assert!(manifest(dir.path(), ["missing"], Algorithm::Md5, None)
+                    .await
+                    .is_err());
+                // Checksums are recorded, and recorded ones are used as is
+                let hash_db = HashDb::open(dir.path().join("sums.db")).unwrap();
+                let recorded = manifest(dir.path(), ["a", "sub/b"], Algorithm::Md5, Some(&hash_db))
+                    .await
+                    .unwrap();
+                assert_eq!(recorded, sums);
+                let forged = Checksum::parse(Algorithm::Md5, &"0".repeat(32)).unwrap();
+                hash_db
+                    .record(&dir.path().join("a"), Some(forged), None)
+                    .await
+                    .unwrap();
+                let recorded = manifest(dir.path(), ["a"], Algorithm::Md5, Some(&hash_db))
+                    .await
+                    .unwrap();
+                assert_eq!(recorded, format!("{}  a\n", "0".repeat(32)));
+
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let base = format!("http://{}", listener.local_addr().unwrap());
+                let document = Document {
+                    content_type: "text/plain",
+                    body: sums.clone().into_bytes(),
+                };
+                let server = tokio::spawn(serve(
+                    listener,
+                    HashMap::from([("/MD5SUMS".to_owned(), document)]),
+                ));
+                let client = Client::new();
+                let response = client.get(format!("{}/MD5SUMS", base)).send().await;
+                assert_eq!(response.unwrap().text().await.unwrap(), sums);
+                let response = client.head(format!("{}/MD5SUMS", base)).send().await;
+                assert_eq!(
+                    response.unwrap().headers()["content-length"],
+                    sums.len().to_string().as_str()
+                );
+                let response = client.get(format!("{}/other", base)).send().await;
+                assert_eq!(response.unwrap().status(), 404);
+                let response = client.delete(format!("{}/MD5SUMS", base)).send().await;
+                assert_eq!(response.unwrap().status(), 405);
+                server.abort();
+            });
+    }
+
+    #[test]
+    fn find_in_manifest() {
+        let md5 = |hex| Some(Checksum::parse(Algorithm::Md5, hex).unwrap());
+        let gnu = "900150983cd24fb0d6963f7d28e17f72  a\n\
+                   d41d8cd98f00b204e9800998ecf8427e *dist/b.tar.gz\n";
+        assert_eq!(
+            find_checksum(gnu, "a", Algorithm::Md5),
+            md5("900150983cd24fb0d6963f7d28e17f72")
+        );
+        assert_eq!(
+            find_checksum(gnu, "b.tar.gz", Algorithm::Md5),
+            md5("d41d8cd98f00b204e9800998ecf8427e")
+        );
+        assert_eq!(find_checksum(gnu, "c", Algorithm::Md5), None);
+        let bsd = "MD5 (a) = 900150983cd24fb0d6963f7d28e17f72\n";
+        assert_eq!(
+            find_checksum(bsd, "a", Algorithm::Md5),
+            md5("900150983cd24fb0d6963f7d28e17f72")
+        );
+        // Manifest of single file may have bare checksum
+        let bare = "900150983cd24fb0d6963f7d28e17f72\n";
+        assert_eq!(
+            find_checksum(bare, "a", Algorithm::Md5),
+            md5("900150983cd24fb0d6963f7d28e17f72")
+        );
+        assert_eq!(find_checksum(gnu, "a", Algorithm::Sha256), None);
+    }
+
+    #[test]
+    fn fetch_manifests() {
+        use warp::{http::StatusCode, Filter};
+
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                // Manifest which fails every other request, and one which doesn't exist
+                let requests = Arc::new(AtomicUsize::new(0));
+                let missed = Arc::new(AtomicUsize::new(0));
+                let counter = requests.clone();
+                let flaky = warp::path("flaky").map(move || {
+                    match counter.fetch_add(1, Ordering::SeqCst) % 2 {
+                        0 => warp::reply::with_status("", StatusCode::INTERNAL_SERVER_ERROR),
+                        _ => warp::reply::with_status("abc  a\n", StatusCode::OK),
+                    }
+                });
+                let counter = missed.clone();
+                let missing = warp::path("missing").map(move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    warp::reply::with_status("", StatusCode::NOT_FOUND)
+                });
+                let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+                let (addr, server) = warp::serve(flaky.or(missing)).bind_with_graceful_shutdown(
+                    ([127, 0, 0, 1], 0),
+                    async {
+                        rx.await.ok();
+                    },
+                );
+                let jh = tokio::spawn(server);
+                let url = |name| format!("http://127.0.0.1:{}/{}", addr.port(), name);
+                let client = Client::new();
+
+                // Failure isn't remembered, so next job fetches manifest again
+                let sums = RemoteSums::new(None, RetryPolicy::default(), Duration::ZERO);
+                assert!(sums.fetch(&client, &url("flaky")).await.is_err());
+                let text = sums.fetch(&client, &url("flaky")).await.unwrap();
+                assert_eq!(text.as_deref(), Some("abc  a\n"));
+                assert_eq!(requests.load(Ordering::SeqCst), 2);
+                // Missing manifest is remembered, as is fetched one
+                assert_eq!(sums.fetch(&client, &url("missing")).await.unwrap(), None);
+                assert_eq!(sums.fetch(&client, &url("missing")).await.unwrap(), None);
+                assert!(sums.fetch(&client, &url("flaky")).await.is_ok());
+                assert_eq!(requests.load(Ordering::SeqCst), 2);
+                assert_eq!(missed.load(Ordering::SeqCst), 1);
+
+                // Transient failure is retried within one fetch, concurrent ones wait for it
+                let retry = RetryPolicy {
+                    max_attempts: 2,
+                    base_delay: Duration::from_millis(10),
+                    jitter: 0.0,
+                };
+                let sums = RemoteSums::new(None, retry, Duration::ZERO);
+                let flaky = url("flaky");
+                let (first, second) =
+                    tokio::join!(sums.fetch(&client, &flaky), sums.fetch(&client, &flaky));
+                assert_eq!(first.unwrap().as_deref(), Some("abc  a\n"));
+                assert_eq!(second.unwrap().as_deref(), Some("abc  a\n"));
+                assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+                drop(client);
+                tx.send(()).unwrap();
+                jh.await.unwrap();
+            });
+    }
+}
@@ -0,0 +1,243 @@
+//! Helpers shared by unit tests of several modules
+
+use openssl::asn1::Asn1Time;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+use openssl::x509::{X509NameBuilder, X509};
+use rand::{thread_rng, RngCore};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot::{channel, Sender};
+use tokio::task::{spawn, JoinHandle};
+use warp::Filter;
+
+/// Writes file of specified size filled with random bytes, returns its contents
+pub fn write_random_file(path: &Path, size: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; size];
+    thread_rng().fill_bytes(&mut buf);
+
+    let file = File::create(path).unwrap();
+    let mut file = BufWriter::new(file);
+
+    file.write_all(&buf).unwrap();
+    file.flush().unwrap();
+    buf
+}
+
+/// Makes data whose bytes differ from neighbours, so misplaced ranges show up
+pub fn pattern_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// `Range` and `If-Range` headers of requests received by stub server
+pub type RangeLog = Arc<Mutex<Vec<(Option<String>, Option<String>)>>>;
+
+/// Spawns stub web server which breaks connection after part of body, on full requests only;
+/// range requests get the rest of data in full
+///
+/// Data is served under any name with strong entity tag, except for `plain` one, which has
+/// no validators, `shifted` one, whose ranges start one byte later than requested, and `flaky`
+/// one, whose first range request fails with 503.
+/// Must be called from within tokio runtime. Returns server port, log of requests' ranges,
+/// shutdown signal sender and server's join handle
+pub fn spawn_cutting_server(
+    data: Vec<u8>,
+    cut: usize,
+) -> (u16, RangeLog, Sender<()>, JoinHandle<()>) {
+    use futures::StreamExt;
+    use warp::{http::Response, hyper::Body};
+
+    let requests = RangeLog::default();
+    let requested = requests.clone();
+    let route = warp::path::param::<String>()
+        .and(warp::header::optional::<String>("range"))
+        .and(warp::header::optional::<String>("if-range"))
+        .map(
+            move |name: String, range: Option<String>, if_range: Option<String>| {
+                let ranged = {
+                    let mut requested = requested.lock().unwrap();
+                    requested.push((range.clone(), if_range));
+                    requested
+                        .iter()
+                        .filter(|(range, _)| range.is_some())
+                        .count()
+                };
+                let mut builder = Response::builder().header("content-length", data.len());
+                if name != "plain" {
+                    builder = builder.header("etag", "\"v1\"");
+                }
+                let Some(range) = range else {
+                    // Delay lets received part reach the client before connection breaks
+                    let body =
+                        Body::wrap_stream(futures::stream::iter([Ok(data[..cut].to_vec())]).chain(
+                            futures::stream::once(async {
+                                tokio::time::sleep(Duration::from_millis(100)).await;
+                                Err(std::io::Error::other("closed"))
+                            }),
+                        ));
+                    return builder.body(body).unwrap();
+                };
+                if name == "flaky" && ranged == 1 {
+                    return Response::builder().status(503).body(Body::empty()).unwrap();
+                }
+                let mut start: usize = range
+                    .trim_start_matches("bytes=")
+                    .trim_end_matches('-')
+                    .parse()
+                    .unwrap();
+                if name == "shifted" {
+                    start += 1;
+                }
+                Response::builder()
+                    .status(206)
+                    .header(
+                        "content-range",
+                        format!("bytes {}-{}/{}", start, data.len() - 1, data.len()),
+                    )
+                    .header("content-length", data.len() - start)
+                    .body(Body::from(data[start..].to_vec()))
+                    .unwrap()
+            },
+        );
+    let (tx, rx) = channel();
+    let (addr, server) =
+        warp::serve(route).bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+            rx.await.ok();
+        });
+    (addr.port(), requests, tx, spawn(server))
+}
+
+/// Spawns stub web server which serves files from specified directory under `/files`
+///
+/// Must be called from within tokio runtime. Returns server port,
+/// shutdown signal sender and server's join handle
+pub fn spawn_server(root: PathBuf) -> (u16, Sender<()>, JoinHandle<()>) {
+    // Routes for all files in source test directory
+    let routes = warp::path("files").and(warp::fs::dir(root));
+    // Construct shutdown channel
+    let (tx, rx) = channel();
+    // Construct server future
+    let (addr, server) =
+        warp::serve(routes).bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async {
+            rx.await.ok();
+        });
+    // Spawn the server into a runtime
+    (addr.port(), tx, spawn(server))
+}
+
+/// Makes certificate for `localhost`, signed by issuer, or self-signed CA one without issuer
+pub fn certificate(name: &str, issuer: Option<(&X509, &PKey<Private>)>) -> (X509, PKey<Private>) {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let subject = subject.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    match issuer {
+        None => {
+            builder.set_issuer_name(&subject).unwrap();
+            let ca = BasicConstraints::new().critical().ca().build().unwrap();
+            builder.append_extension(ca).unwrap();
+            builder.sign(&key, MessageDigest::sha256()).unwrap();
+        }
+        Some((issuer, issuer_key)) => {
+            builder.set_issuer_name(issuer.subject_name()).unwrap();
+            let names = SubjectAlternativeName::new()
+                .dns("localhost")
+                .build(&builder.x509v3_context(Some(issuer), None))
+                .unwrap();
+            builder.append_extension(names).unwrap();
+            builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+        }
+    }
+    (builder.build(), key)
+}
+
+/// Spawns stub FTP server which serves files of directory in passive mode,
+/// to user `alice` with password `secret` only
+///
+/// # Returns
+/// Port server listens on
+pub async fn spawn_ftp_server(root: PathBuf) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(serve_ftp(stream, root.clone()));
+        }
+    });
+    port
+}
+
+async fn serve_ftp(stream: TcpStream, root: PathBuf) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    writer
+        .write_all(b"220-Stub FTP\r\n220 ready\r\n")
+        .await
+        .unwrap();
+    let (mut data, mut offset) = (None, 0);
+    while let Ok(Some(line)) = lines.next_line().await {
+        let (command, arg) = line.split_once(' ').unwrap_or((&line, ""));
+        let reply = match command {
+            "USER" if arg == "alice" => "331 password please".to_owned(),
+            "USER" => "530 unknown user".to_owned(),
+            "PASS" if arg == "secret" => "230 logged in".to_owned(),
+            "PASS" => "530 wrong password".to_owned(),
+            "TYPE" => "200 binary".to_owned(),
+            "SIZE" => match std::fs::metadata(root.join(arg)) {
+                Ok(meta) => format!("213 {}", meta.len()),
+                Err(_) => "550 no such file".to_owned(),
+            },
+            "EPSV" => "500 not supported".to_owned(),
+            "PASV" => {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let port = listener.local_addr().unwrap().port();
+                data = Some(listener);
+                format!("227 passive (127,0,0,1,{},{})", port / 256, port % 256)
+            }
+            "REST" => {
+                offset = arg.parse().unwrap();
+                "350 restarting".to_owned()
+            }
+            "RETR" => match (std::fs::read(root.join(arg)), data.take()) {
+                (Ok(content), Some(listener)) => {
+                    writer.write_all(b"150 sending\r\n").await.unwrap();
+                    let (mut conn, _) = listener.accept().await.unwrap();
+                    conn.write_all(&content[offset as usize..]).await.unwrap();
+                    drop(conn);
+                    offset = 0;
+                    "226 done".to_owned()
+                }
+                _ => "550 no such file".to_owned(),
+            },
+            "QUIT" => {
+                writer.write_all(b"221 bye\r\n").await.unwrap();
+                return;
+            }
+            _ => "502 not implemented".to_owned(),
+        };
+        writer
+            .write_all(format!("{}\r\n", reply).as_bytes())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::clock::Clock;
+
+/// Amounts of data received during run, summed per time bucket of fixed length
+///
+/// Buckets are aligned to wall clock, e.g. one-minute buckets start at whole minutes,
+/// so series can be checked against time-of-day limits and schedules
+#[derive(Debug)]
+pub struct Timeline {
+    /// Length of bucket
+    bucket: Duration,
+    clock: Arc<dyn Clock>,
+    /// Point in time timeline was created at, by clock and by wall clock
+    origin: (Instant, SystemTime),
+    /// Bytes received, by number of bucket since UNIX epoch
+    buckets: Mutex<BTreeMap<u64, u64>>,
+}
+
+impl Timeline {
+    /// Creates empty timeline
+    ///
+    /// # Arguments
+    /// * bucket - length of bucket, at least a second
+    /// * clock - clock time is measured with
+    /// * start - wall clock time corresponding to clock's current time
+    pub fn new(bucket: Duration, clock: Arc<dyn Clock>, start: SystemTime) -> Timeline {
+        Timeline {
+            bucket: bucket.max(Duration::from_secs(1)),
+            origin: (clock.now(), start),
+            clock,
+            buckets: Mutex::new(BTreeMap::new()),
+        }
+    }
+    /// Length of bucket
+    pub fn bucket(&self) -> Duration {
+        self.bucket
+    }
+    /// Accounts bytes received right now
+    pub fn record(&self, bytes: u64) {
+        let now = self.origin.1 + (self.clock.now() - self.origin.0);
+        let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let index = since_epoch.as_secs() / self.bucket.as_secs();
+        *self.buckets.lock().unwrap().entry(index).or_default() += bytes;
+    }
+    /// Lists buckets from the first one with data to the last one, idle ones included
+    ///
+    /// # Returns
+    /// Start time of each bucket, with number of bytes received during it
+    pub fn series(&self) -> Vec<(SystemTime, u64)> {
+        let buckets = self.buckets.lock().unwrap();
+        let (Some((&first, _)), Some((&last, _))) =
+            (buckets.first_key_value(), buckets.last_key_value())
+        else {
+            return Vec::new();
+        };
+        (first..=last)
+            .map(|index| {
+                let start = UNIX_EPOCH + Duration::from_secs(index * self.bucket.as_secs());
+                (start, buckets.get(&index).copied().unwrap_or(0))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timeline;
+    use crate::clock::ManualClock;
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn buckets() {
+        let clock = Arc::new(ManualClock::new());
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_090);
+        let timeline = Timeline::new(Duration::from_secs(60), clock.clone(), start);
+        assert!(timeline.series().is_empty());
+        timeline.record(100);
+        clock.advance(Duration::from_secs(5));
+        timeline.record(50);
+        // Bucket boundary is at whole minute, not 60 seconds after start
+        clock.advance(Duration::from_secs(10));
+        timeline.record(7);
+        clock.advance(Duration::from_secs(120));
+        timeline.record(1);
+        let minute = |n: u64| UNIX_EPOCH + Duration::from_secs(1_700_000_040 + n * 60);
+        assert_eq!(
+            timeline.series(),
+            [
+                (minute(0), 150),
+                (minute(1), 7),
+                (minute(2), 0),
+                (minute(3), 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+/// Parses point in time given either as number of seconds since UNIX epoch,
+/// or as RFC 3339 timestamp like `2024-02-29T12:34:56Z` or `2024-02-29T14:34:56.5+02:00`
+pub fn parse_timestamp(s: &str) -> Result<SystemTime> {
+    if !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit()) {
+        let secs = s
+            .parse()
+            .with_context(|| format!("{}: invalid timestamp", s))?;
+        return Ok(UNIX_EPOCH + Duration::from_secs(secs));
+    }
+    parse_rfc3339(s).with_context(|| {
+        format!(
+            "{}: invalid timestamp, expected seconds since epoch or one like 2024-02-29T12:34:56Z",
+            s
+        )
+    })
+}
+/// Parses RFC 3339 timestamp, with `T` or space between date and time
+fn parse_rfc3339(s: &str) -> Option<SystemTime> {
+    let number = |field: &str| -> Option<i64> {
+        match field.bytes().all(|c| c.is_ascii_digit()) && !field.is_empty() {
+            true => field.parse().ok(),
+            false => None,
+        }
+    };
+    let (date, time) = s.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-').map(number);
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    // Offset from UTC follows time, in seconds
+    let (time, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, 0),
+        None => {
+            let pos = time.rfind(['+', '-'])?;
+            let (hours, minutes) = time[pos + 1..].split_once(':')?;
+            let offset = number(hours)? * 3600 + number(minutes)? * 60;
+            match &time[pos..pos + 1] {
+                "+" => (&time[..pos], offset),
+                _ => (&time[..pos], -offset),
+            }
+        }
+    };
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => {
+            number(fraction)?;
+            // Digits beyond nanoseconds are dropped
+            let digits = &fraction[..fraction.len().min(9)];
+            (
+                time,
+                digits.parse::<u32>().ok()? * 10u32.pow(9 - digits.len() as u32),
+            )
+        }
+        None => (time, 0),
+    };
+    let mut time = time.splitn(3, ':').map(number);
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    // Leap second is accepted, and falls on the next one
+    if second > 60 {
+        return None;
+    }
+    let secs =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let secs = u64::try_from(secs).ok()?;
+    Some(UNIX_EPOCH + Duration::new(secs, fraction))
+}
+/// Converts civil date to days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+/// Formats time as ISO 8601 timestamp in UTC, with milliseconds
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    // Converts days since epoch to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_timestamp, parse_timestamp};
+    use assert_matches::assert_matches;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_123);
+        assert_eq!(format_timestamp(time), "2024-02-29T12:34:56.123Z");
+
+        assert_eq!(parse_timestamp("2024-02-29T12:34:56.123Z").unwrap(), time);
+        assert_eq!(
+            parse_timestamp("2024-02-29T14:34:56.123+02:00").unwrap(),
+            time
+        );
+        assert_eq!(
+            parse_timestamp("2024-02-29 07:04:56.123-05:30").unwrap(),
+            time
+        );
+        assert_eq!(
+            parse_timestamp("1709210096").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_709_210_096)
+        );
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z").unwrap(), UNIX_EPOCH);
+        assert_matches!(parse_timestamp("2024-02-29"), Err(_));
+        assert_matches!(parse_timestamp("2024-13-01T00:00:00Z"), Err(_));
+        assert_matches!(parse_timestamp("2024-02-29T12:34:56"), Err(_));
+        assert_matches!(parse_timestamp("1969-12-31T23:59:59Z"), Err(_));
+        assert_matches!(parse_timestamp("-5"), Err(_));
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use reqwest::{Certificate, ClientBuilder, Identity};
+
+/// TLS settings of connections to servers, for servers with certificates issued by private CA
+/// or ones which require client certificate
+#[derive(Clone, Default)]
+pub struct TlsOptions {
+    /// Certificates of CAs trusted on top of system ones
+    pub ca_certs: Vec<Certificate>,
+    /// Client certificate along with its private key, presented to servers which ask for it
+    pub identity: Option<Identity>,
+    /// Whether server certificates aren't verified at all
+    pub insecure: bool,
+}
+
+impl TlsOptions {
+    /// Reads CA certificates from PEM file, which may contain several of them
+    pub fn load_ca_certs(path: &Path) -> Result<Vec<Certificate>> {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("{}: cannot read CA certificate", path.display()))?;
+        Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("{}: malformed CA certificate", path.display()))
+    }
+    /// Reads client certificate and its private key from PEM files;
+    /// key must be in PKCS#8 format, i.e. `BEGIN PRIVATE KEY`
+    pub fn load_identity(cert: &Path, key: &Path) -> Result<Identity> {
+        let read = |path: &Path| {
+            std::fs::read(path).with_context(|| format!("{}: cannot read file", path.display()))
+        };
+        Identity::from_pkcs8_pem(&read(cert)?, &read(key)?).with_context(|| {
+            format!(
+                "{}, {}: malformed client certificate or key",
+                cert.display(),
+                key.display()
+            )
+        })
+    }
+    /// Applies settings to HTTP client configuration
+    pub fn configure(&self, builder: ClientBuilder) -> ClientBuilder {
+        let builder = self.ca_certs.iter().fold(builder, |builder, cert| {
+            builder.add_root_certificate(cert.clone())
+        });
+        let builder = match &self.identity {
+            Some(identity) => builder.identity(identity.clone()),
+            None => builder,
+        };
+        builder.danger_accept_invalid_certs(self.insecure)
+    }
+}
+
+impl fmt::Debug for TlsOptions {
+    /// Certificates and keys aren't printed, only whether they're present
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsOptions")
+            .field("ca_certs", &self.ca_certs.len())
+            .field("identity", &self.identity.is_some())
+            .field("insecure", &self.insecure)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TlsOptions;
+    use crate::test_utils::certificate;
+    use assert_matches::assert_matches;
+    use openssl::ssl::{SslAcceptor, SslMethod};
+    use reqwest::Client;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn private_ca() {
+        let (ca, ca_key) = certificate("Test CA", None);
+        let (cert, key) = certificate("localhost", Some((&ca, &ca_key)));
+        let dir = tempfile::tempdir().unwrap();
+        let ca_path = dir.path().join("ca.pem");
+        std::fs::write(&ca_path, ca.to_pem().unwrap()).unwrap();
+
+        // Server answers each connection with the same response
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = acceptor.accept(stream.unwrap()) else {
+                    continue;
+                };
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+                );
+            }
+        });
+
+        let url = format!("https://localhost:{}/", port);
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let get = |tls: TlsOptions| {
+                    let client = tls.configure(Client::builder()).build().unwrap();
+                    let url = url.clone();
+                    async move { client.get(url).send().await?.text().await }
+                };
+                assert_matches!(get(TlsOptions::default()).await, Err(_));
+                let trusted = TlsOptions {
+                    ca_certs: TlsOptions::load_ca_certs(&ca_path).unwrap(),
+                    ..TlsOptions::default()
+                };
+                assert_eq!(get(trusted).await.unwrap(), "hello");
+                let insecure = TlsOptions {
+                    insecure: true,
+                    ..TlsOptions::default()
+                };
+                assert_eq!(get(insecure).await.unwrap(), "hello");
+            });
+    }
+
+    #[test]
+    fn client_identity() {
+        let (ca, ca_key) = certificate("Test CA", None);
+        let (cert, key) = certificate("client", Some((&ca, &ca_key)));
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("client.pem");
+        let key_path = dir.path().join("client.key");
+        std::fs::write(&cert_path, cert.to_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+        assert!(TlsOptions::load_identity(&cert_path, &key_path).is_ok());
+        assert!(TlsOptions::load_identity(&key_path, &cert_path).is_err());
+        assert!(TlsOptions::load_ca_certs(&dir.path().join("missing.pem")).is_err());
+        let tls = TlsOptions {
+            insecure: true,
+            ..TlsOptions::default()
+        };
+        assert_eq!(
+            format!("{:?}", tls),
+            "TlsOptions { ca_certs: 0, identity: false, insecure: true }"
+        );
+    }
+}
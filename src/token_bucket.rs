@@ -1,11 +1,14 @@
 use std::cmp;
 use std::time::{Duration, Instant};
 
+use crate::clock::{Clock, SystemClock};
+
 /// A bucket of tokens which renews itself with time
 ///
 /// Used to generate time-constrained quota for some repeatable process,
-/// like copying data from one stream to another
-pub struct TokenBucket {
+/// like copying data from one stream to another.
+/// Time is measured by specified clock, system one by default
+pub struct TokenBucket<C = SystemClock> {
     /// How many tokens are generated per second
     fill_rate: usize,
     /// Maximum number of tokens in bucket
@@ -14,6 +17,8 @@ pub struct TokenBucket {
     remaining: f64,
     /// Last time tokens were taken from bucket
     timestamp: Instant,
+    /// Source of current time
+    clock: C,
 }
 /// Convert time duration to seconds, with nanoseconds as fraction
 fn duration_seconds(d: Duration) -> f64 {
@@ -32,13 +37,31 @@ impl TokenBucket {
     ///
     /// # Arguments
     /// * rate - how many tokens are generated per second;
-    ///     set to 0 to make bucket unlimited
+    ///   set to 0 to make bucket unlimited
     /// * capacity - how many tokens can bucket hold; can be 0 if fill rate is 0 too
     ///
     /// # Panics
     /// Panics if rate argument != 0 while capacity == 0
     ///
     pub fn with_capacity(rate: usize, capacity: usize) -> TokenBucket {
+        TokenBucket::with_clock(rate, capacity, SystemClock)
+    }
+}
+
+impl<C: Clock> TokenBucket<C> {
+    /// Creates new token bucket with specified fill rate and capacity, which measures time
+    /// with specified clock
+    ///
+    /// # Arguments
+    /// * rate - how many tokens are generated per second;
+    ///   set to 0 to make bucket unlimited
+    /// * capacity - how many tokens can bucket hold; can be 0 if fill rate is 0 too
+    /// * clock - source of current time
+    ///
+    /// # Panics
+    /// Panics if rate argument != 0 while capacity == 0
+    ///
+    pub fn with_clock(rate: usize, capacity: usize, clock: C) -> TokenBucket<C> {
         if rate != 0 && capacity == 0 {
             panic!("Cannot construct token bucket with nonzero rate and zero capacity");
         }
@@ -46,7 +69,8 @@ impl TokenBucket {
             fill_rate: rate,
             capacity,
             remaining: 0f64,
-            timestamp: Instant::now(),
+            timestamp: clock.now(),
+            clock,
         }
     }
     /// Attempts to take specified amount of tokens from bucket
@@ -69,7 +93,7 @@ impl TokenBucket {
         }
         // 1. Add to bucket rate / delta
         let delta = {
-            let now = Instant::now();
+            let now = self.clock.now();
             now - std::mem::replace(&mut self.timestamp, now)
         };
         let delta_fill = duration_seconds(delta) * (self.fill_rate as f64);
@@ -79,6 +103,89 @@ impl TokenBucket {
         self.remaining = (self.remaining - (taken as f64)).max(0f64);
         taken
     }
+    /// Returns how many tokens are generated per second; 0 means bucket is unlimited
+    pub fn rate(&self) -> usize {
+        self.fill_rate
+    }
+    /// Changes fill rate and capacity of bucket, keeping tokens accumulated so far
+    ///
+    /// # Arguments
+    /// * rate - how many tokens are generated per second; 0 makes bucket unlimited
+    /// * capacity - how many tokens can bucket hold; can be 0 if fill rate is 0 too
+    ///
+    /// # Panics
+    /// Panics if rate argument != 0 while capacity == 0
+    pub fn set_rate(&mut self, rate: usize, capacity: usize) {
+        if rate != 0 && capacity == 0 {
+            panic!("Cannot set nonzero rate and zero capacity of token bucket");
+        }
+        match self.fill_rate {
+            // Unlimited bucket doesn't accumulate anything, so it starts empty like a new one
+            0 => {
+                self.remaining = 0f64;
+                self.timestamp = self.clock.now();
+            }
+            // Tokens generated so far were generated at old rate
+            _ => {
+                self.take(0);
+            }
+        }
+        self.fill_rate = rate;
+        self.capacity = capacity;
+        self.remaining = self.remaining.min(capacity as f64);
+    }
+    /// Returns how many tokens can be taken right now, without taking them;
+    /// `usize::MAX` if bucket is unlimited
+    pub fn available(&mut self) -> usize {
+        match self.fill_rate {
+            0 => usize::MAX,
+            _ => {
+                self.take(0);
+                self.remaining.floor() as usize
+            }
+        }
+    }
+    /// Returns tokens which were taken but not used back into bucket, up to its capacity
+    ///
+    /// # Arguments
+    /// * amount - number of tokens to return
+    pub fn put_back(&mut self, amount: usize) {
+        self.remaining = (self.remaining + amount as f64).min(self.capacity as f64);
+    }
+    /// Computes how long it takes until specified amount of tokens can be taken
+    ///
+    /// # Arguments
+    /// * amount - number of tokens needed; amounts over capacity are capped by it
+    ///
+    /// # Returns
+    /// Time to wait; zero if tokens are available right now, or bucket is unlimited
+    pub fn wait(&self, amount: usize) -> Duration {
+        if self.fill_rate == 0 {
+            return Duration::ZERO;
+        }
+        let elapsed = duration_seconds(self.clock.now() - self.timestamp);
+        let present = (self.remaining + elapsed * self.fill_rate as f64).min(self.capacity as f64);
+        let needed = amount.min(self.capacity) as f64 - present;
+        match needed > 0.0 {
+            true => Duration::from_secs_f64(needed / self.fill_rate as f64),
+            false => Duration::ZERO,
+        }
+    }
+    /// Takes specified amount of tokens, waiting until they're available
+    ///
+    /// Instead of polling, sleeps exactly as long as bucket needs to refill
+    ///
+    /// # Returns
+    /// Number of tokens retrieved, which is requested amount capped by capacity
+    pub async fn acquire(&mut self, amount: usize) -> usize {
+        loop {
+            let wait = self.wait(amount);
+            if wait.is_zero() {
+                return self.take(amount);
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -87,6 +194,7 @@ mod tests {
     use std::time::Duration;
 
     use super::TokenBucket;
+    use crate::clock::ManualClock;
 
     fn get_random(limit: usize) -> usize {
         use rand::Rng;
@@ -129,4 +237,55 @@ mod tests {
 
         assert_eq!((delta - tb.remaining).floor() as usize, taken);
     }
+
+    #[test]
+    fn test_take_with_clock() {
+        let clock = std::sync::Arc::new(ManualClock::new());
+        let mut tb = TokenBucket::with_clock(1_000, 2_000, clock.clone());
+
+        assert_eq!(tb.take(100), 0);
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(tb.take(100), 100);
+        assert_eq!(tb.take(500), 150);
+        // Bucket doesn't hold more than its capacity
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(tb.take(5_000), 2_000);
+        // Unused tokens can be returned
+        tb.put_back(500);
+        assert_eq!(tb.take(1_000), 500);
+        // Waiting time accounts for tokens refilled so far, and is capped by capacity
+        assert_eq!(tb.wait(500), Duration::from_millis(500));
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(tb.wait(500), Duration::from_millis(400));
+        assert_eq!(tb.wait(50), Duration::ZERO);
+        assert_eq!(tb.wait(5_000), Duration::from_millis(1_900));
+    }
+
+    #[test]
+    fn test_set_rate() {
+        let clock = std::sync::Arc::new(ManualClock::new());
+        let mut tb = TokenBucket::with_clock(1_000, 1_000, clock.clone());
+        clock.advance(Duration::from_millis(500));
+        // Tokens accumulated at old rate are kept, then new rate applies
+        tb.set_rate(100, 100);
+        assert_eq!(tb.take(1_000), 100);
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(tb.take(1_000), 50);
+        // Unlimited bucket gives everything, and starts empty once limited again
+        tb.set_rate(0, 0);
+        assert_eq!(tb.take(1_000), 1_000);
+        clock.advance(Duration::from_secs(1));
+        tb.set_rate(200, 200);
+        assert_eq!(tb.take(1_000), 0);
+    }
+
+    #[test]
+    fn test_acquire() {
+        let mut tb = TokenBucket::new(10_000);
+        let started = std::time::Instant::now();
+        let taken = tokio_test::block_on(tb.acquire(1_000));
+        // Bucket starts empty, so acquiring waits until it refills
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!(taken, 1_000);
+    }
 }
@@ -0,0 +1,25 @@
+use std::fmt::Debug;
+use std::pin::Pin;
+
+use tokio::io::AsyncWrite;
+
+use crate::list::Entry;
+
+/// Writer which downloaded data goes into, on its way to destination file
+pub type BoxWriter<'a> = Pin<Box<dyn AsyncWrite + Send + 'a>>;
+
+/// Post-processing of downloaded data, applied between network stream and destination file,
+/// e.g. decryption, decompression or hashing
+///
+/// Expected size and checksum of entry describe received data, before transform.
+/// Transformed output doesn't match received data byte by byte, so such downloads
+/// aren't resumed and aren't split into segments
+pub trait Transform: Debug + Send + Sync {
+    /// Whether entry's data should be transformed; all entries are by default
+    fn applies(&self, _entry: &Entry) -> bool {
+        true
+    }
+    /// Wraps writer of destination file, so data written into returned writer is processed
+    /// and passed into inner one; returned writer is shut down once all data is written
+    fn wrap<'a>(&self, inner: BoxWriter<'a>) -> BoxWriter<'a>;
+}
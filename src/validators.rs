@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use reqwest::header::{
+    HeaderMap, HeaderValue, CONTENT_LENGTH, ETAG, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    IF_UNMODIFIED_SINCE, LAST_MODIFIED,
+};
+use serde_json::{json, Value};
+use tokio::fs;
+
+/// Server-provided properties which identify specific version of remote file
+///
+/// Saved next to downloaded file, so later runs can tell whether remote file changed
+/// without downloading or hashing it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// Entity tag
+    pub etag: Option<String>,
+    /// Last modification time, as sent by server
+    pub last_modified: Option<String>,
+    /// File size, in bytes
+    pub length: Option<u64>,
+}
+
+/// Returns path of validators sidecar for specified destination
+pub fn validators_path(dest_path: &Path) -> PathBuf {
+    let mut path = dest_path.as_os_str().to_owned();
+    path.push(".meta");
+    PathBuf::from(path)
+}
+
+impl Validators {
+    /// Extracts validators from response headers
+    pub fn from_headers(headers: &HeaderMap) -> Validators {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            length: header(CONTENT_LENGTH).and_then(|value| value.parse().ok()),
+        }
+    }
+    /// Builds request headers which make server reject request if file isn't this version
+    ///
+    /// Strong entity tag is preferred, since weak one never satisfies `If-Match`;
+    /// modification time is used otherwise
+    pub fn preconditions(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = |value: &str| HeaderValue::from_str(value).ok();
+        match (&self.etag, &self.last_modified) {
+            (Some(etag), _) if !etag.starts_with("W/") => {
+                if let Some(etag) = value(etag) {
+                    headers.insert(IF_MATCH, etag);
+                }
+            }
+            (_, Some(modified)) => {
+                if let Some(modified) = value(modified) {
+                    headers.insert(IF_UNMODIFIED_SINCE, modified);
+                }
+            }
+            _ => {}
+        }
+        headers
+    }
+    /// Builds request headers which make server answer 304 Not Modified if file is still this version
+    ///
+    /// Unlike `If-Match`, `If-None-Match` uses weak comparison, so any entity tag will do;
+    /// modification time is used only if there's no tag
+    pub fn revalidation(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = |value: &str| HeaderValue::from_str(value).ok();
+        match (&self.etag, &self.last_modified) {
+            (Some(etag), _) => {
+                if let Some(etag) = value(etag) {
+                    headers.insert(IF_NONE_MATCH, etag);
+                }
+            }
+            (None, Some(modified)) => {
+                if let Some(modified) = value(modified) {
+                    headers.insert(IF_MODIFIED_SINCE, modified);
+                }
+            }
+            _ => {}
+        }
+        headers
+    }
+    /// Loads validators saved for specified destination, if any
+    pub async fn load(dest_path: &Path) -> Option<Validators> {
+        let text = fs::read_to_string(validators_path(dest_path)).await.ok()?;
+        let value: Value = serde_json::from_str(&text).ok()?;
+        let field = |name: &str| value.get(name)?.as_str().map(str::to_owned);
+        Some(Validators {
+            etag: field("etag"),
+            last_modified: field("last_modified"),
+            length: value.get("length").and_then(Value::as_u64),
+        })
+    }
+    /// Saves validators next to specified destination
+    pub async fn save(&self, dest_path: &Path) -> Result<()> {
+        let value = json!({
+            "etag": self.etag,
+            "last_modified": self.last_modified,
+            "length": self.length,
+        });
+        fs::write(validators_path(dest_path), value.to_string()).await?;
+        Ok(())
+    }
+    /// Checks whether both validators surely describe the same version of file
+    ///
+    /// Entity tags are compared if server sends them, modification times otherwise;
+    /// lengths must match too. Without any of those, nothing can be told for sure
+    pub fn same_as(&self, other: &Validators) -> bool {
+        let same_version = match (&self.etag, &other.etag) {
+            (Some(etag), Some(other)) => etag == other,
+            (None, None) => {
+                self.last_modified.is_some() && self.last_modified == other.last_modified
+            }
+            _ => false,
+        };
+        same_version && self.length.is_some() && self.length == other.length
+    }
+    /// Loads validators saved for specified destination, if destination is present
+    /// and still is the file they describe
+    pub async fn saved_for(dest_path: &Path) -> Option<Validators> {
+        let saved = Validators::load(dest_path).await?;
+        // Local file could've been modified or truncated since it was downloaded
+        let local_len = fs::metadata(dest_path).await.ok().map(|meta| meta.len());
+        (local_len == saved.length).then_some(saved)
+    }
+    /// Checks whether destination file is present and is the same version as remote one
+    pub async fn unchanged(&self, dest_path: &Path) -> bool {
+        Validators::saved_for(dest_path)
+            .await
+            .is_some_and(|saved| self.same_as(&saved))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Validators;
+    use reqwest::header::{IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_UNMODIFIED_SINCE};
+    use tokio::runtime::Builder;
+
+    fn validators(etag: Option<&str>, last_modified: Option<&str>, length: u64) -> Validators {
+        Validators {
+            etag: etag.map(str::to_owned),
+            last_modified: last_modified.map(str::to_owned),
+            length: Some(length),
+        }
+    }
+
+    #[test]
+    fn compare_validators() {
+        let date = Some("Wed, 21 Oct 2015 07:28:00 GMT");
+        assert!(validators(Some("\"a\""), None, 4).same_as(&validators(Some("\"a\""), date, 4)));
+        assert!(!validators(Some("\"a\""), None, 4).same_as(&validators(Some("\"b\""), None, 4)));
+        assert!(!validators(Some("\"a\""), None, 4).same_as(&validators(Some("\"a\""), None, 5)));
+        assert!(!validators(Some("\"a\""), None, 4).same_as(&validators(None, None, 4)));
+        assert!(validators(None, date, 4).same_as(&validators(None, date, 4)));
+        assert!(!validators(None, None, 4).same_as(&validators(None, None, 4)));
+    }
+
+    #[test]
+    fn build_preconditions() {
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+        let headers = validators(Some("\"a\""), Some(date), 4).preconditions();
+        assert_eq!(headers.get(IF_MATCH).unwrap(), "\"a\"");
+        assert!(headers.get(IF_UNMODIFIED_SINCE).is_none());
+        // Weak tag never matches, so modification time is used instead
+        let headers = validators(Some("W/\"a\""), Some(date), 4).preconditions();
+        assert!(headers.get(IF_MATCH).is_none());
+        assert_eq!(headers.get(IF_UNMODIFIED_SINCE).unwrap(), date);
+        assert!(validators(None, None, 4).preconditions().is_empty());
+        // Any tag is fine for revalidation
+        let headers = validators(Some("W/\"a\""), Some(date), 4).revalidation();
+        assert_eq!(headers.get(IF_NONE_MATCH).unwrap(), "W/\"a\"");
+        assert!(headers.get(IF_MODIFIED_SINCE).is_none());
+        let headers = validators(None, Some(date), 4).revalidation();
+        assert_eq!(headers.get(IF_MODIFIED_SINCE).unwrap(), date);
+    }
+
+    #[test]
+    fn saved_validators() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("file");
+        let remote = validators(Some("\"a\""), None, 4);
+
+        Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                assert!(!remote.unchanged(&dest).await);
+                std::fs::write(&dest, b"data").unwrap();
+                assert!(!remote.unchanged(&dest).await);
+                remote.save(&dest).await.unwrap();
+                assert_eq!(Validators::load(&dest).await, Some(remote.clone()));
+                assert!(remote.unchanged(&dest).await);
+                // Local modification is noticed as well
+                std::fs::write(&dest, b"changed").unwrap();
+                assert!(!remote.unchanged(&dest).await);
+            });
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read};
+
+use anyhow::{bail, Context, Result};
+
+/// Values of `${NAME}` placeholders in list files, so one list can serve as template
+/// for several versions or architectures
+///
+/// Placeholder is replaced with value defined explicitly, otherwise with environment
+/// variable of the same name. `${NAME:-text}` falls back to `text` if variable isn't set
+/// anywhere, while placeholder without fallback must refer to set variable.
+/// `$${` stands for literal `${`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Variables {
+    /// Variables defined explicitly, which take precedence over environment
+    defined: HashMap<String, String>,
+}
+
+impl Variables {
+    /// Creates variables with specified definitions, on top of environment
+    pub fn new(defined: impl IntoIterator<Item = (String, String)>) -> Variables {
+        Variables {
+            defined: defined.into_iter().collect(),
+        }
+    }
+    /// Parses variable definition like `NAME=value`
+    pub fn parse_define(spec: &str) -> Result<(String, String)> {
+        let Some((name, value)) = spec.split_once('=') else {
+            bail!("{}: expected definition like NAME=value", spec);
+        };
+        if !is_name(name) {
+            bail!("{}: invalid variable name", name);
+        }
+        Ok((name.to_owned(), value.to_owned()))
+    }
+    /// Finds value of variable
+    fn get(&self, name: &str) -> Option<String> {
+        self.defined
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+    /// Replaces all placeholders in text with values of variables
+    pub fn substitute(&self, text: &str) -> Result<String> {
+        let mut substituted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(pos) = rest.find("${") {
+            // Escaped placeholder is kept, without escaping dollar
+            if let Some(head) = rest[..pos].strip_suffix('$') {
+                substituted.push_str(head);
+                substituted.push_str("${");
+                rest = &rest[pos + 2..];
+                continue;
+            }
+            substituted.push_str(&rest[..pos]);
+            let after = &rest[pos + 2..];
+            let inner = &after[..after
+                .find('}')
+                .with_context(|| format!("{}: unterminated placeholder", &rest[pos..]))?];
+            let (name, fallback) = match inner.split_once(":-") {
+                Some((name, fallback)) => (name, Some(fallback)),
+                None => (inner, None),
+            };
+            if !is_name(name) {
+                bail!("${{{}}}: invalid variable name", inner);
+            }
+            let value = self
+                .get(name)
+                .or_else(|| fallback.map(str::to_owned))
+                .with_context(|| format!("${{{}}}: variable isn't set", name))?;
+            substituted.push_str(&value);
+            rest = &after[inner.len() + 1..];
+        }
+        substituted.push_str(rest);
+        Ok(substituted)
+    }
+    /// Wraps reader of list file, so placeholders are replaced line by line as it's read
+    pub fn reader<R: BufRead>(&self, reader: R) -> Substituted<'_, R> {
+        Substituted {
+            vars: self,
+            inner: reader,
+            line: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+/// Checks whether string is valid variable name: letters, digits and underscores,
+/// not starting with digit
+fn is_name(name: &str) -> bool {
+    !name.starts_with(|c: char| c.is_ascii_digit())
+        && !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Reader which replaces placeholders in lines of underlying one, see `Variables::reader`
+pub struct Substituted<'a, R> {
+    vars: &'a Variables,
+    inner: R,
+    /// Line being read, with placeholders replaced
+    line: Vec<u8>,
+    /// Number of bytes of line consumed so far
+    pos: usize,
+}
+
+impl<R: BufRead> Read for Substituted<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for Substituted<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.line.len() {
+            let mut line = String::new();
+            self.inner.read_line(&mut line)?;
+            let line = self
+                .vars
+                .substitute(&line)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+            self.line = line.into_bytes();
+            self.pos = 0;
+        }
+        Ok(&self.line[self.pos..])
+    }
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.line.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Variables;
+    use assert_matches::assert_matches;
+    use std::io::BufRead;
+
+    #[test]
+    fn substitute_variables() {
+        let vars = Variables::new([
+            Variables::parse_define("VERSION=1.2").unwrap(),
+            Variables::parse_define("ARCH=x86_64").unwrap(),
+        ]);
+        assert_eq!(
+            vars.substitute("http://a/${VERSION}/tool-${ARCH}.tgz tool-${VERSION}")
+                .unwrap(),
+            "http://a/1.2/tool-x86_64.tgz tool-1.2"
+        );
+        assert_eq!(
+            vars.substitute("${HTTPDL_UNSET_VAR:-dev} $${VERSION} {a,b} $x")
+                .unwrap(),
+            "dev ${VERSION} {a,b} $x"
+        );
+        // Environment is consulted for variables which aren't defined explicitly
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(vars.substitute("${PATH}").unwrap(), path);
+        assert_matches!(vars.substitute("${HTTPDL_UNSET_VAR}"), Err(_));
+        assert_matches!(vars.substitute("${VERSION"), Err(_));
+        assert_matches!(vars.substitute("${1x}"), Err(_));
+        assert_matches!(Variables::parse_define("VERSION"), Err(_));
+        assert_matches!(Variables::parse_define("A-B=1"), Err(_));
+
+        let lines: Vec<_> = vars
+            .reader("a/${VERSION}\nb/${ARCH}\n".as_bytes())
+            .lines()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines, ["a/1.2", "b/x86_64"]);
+        assert_matches!(
+            vars.reader("${NOPE_".as_bytes()).lines().next(),
+            Some(Err(_))
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use futures::future::join_all;
+use reqwest::Client;
+use tokio::net::lookup_host;
+use url::Url;
+
+/// Prepares network state before downloads start
+///
+/// # Arguments
+/// * client - HTTP client which will be used for downloads
+/// * urls - source URLs of all jobs
+/// * busiest - number of hosts with most jobs to connect to
+///
+/// Resolves every distinct host, so system resolver cache is hot when jobs start,
+/// then sends HEAD request to each of the busiest origins. Connections established
+/// this way, including TLS handshake, stay in client's pool and are reused by first jobs.
+/// Any failures are ignored, warmup is just an optimization.
+pub async fn warmup<'a>(client: &Client, urls: impl IntoIterator<Item = &'a str>, busiest: usize) {
+    let origins = busiest_origins(urls);
+    // Resolve all hosts concurrently
+    join_all(
+        origins
+            .iter()
+            .map(|(origin, _)| async move { lookup_host(authority(origin)).await }),
+    )
+    .await;
+    // Then pre-connect to the busiest ones
+    join_all(
+        origins
+            .iter()
+            .take(busiest)
+            .map(|(origin, _)| client.head(origin.as_str()).send()),
+    )
+    .await;
+}
+/// Counts jobs per HTTP(S) origin, returns origins ordered from busiest to least busy
+fn busiest_origins<'a>(urls: impl IntoIterator<Item = &'a str>) -> Vec<(String, usize)> {
+    let mut counts = HashMap::<_, usize>::new();
+    for url in urls {
+        match Url::parse(url) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+                *counts
+                    .entry(url.origin().ascii_serialization())
+                    .or_default() += 1
+            }
+            _ => {}
+        }
+    }
+    let mut origins: Vec<_> = counts.into_iter().collect();
+    origins.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    origins
+}
+/// Extracts `host:port` part of serialized origin, suitable for resolving
+fn authority(origin: &str) -> String {
+    let url = Url::parse(origin).expect("origin is always a valid URL");
+    format!(
+        "{}:{}",
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{authority, busiest_origins};
+
+    #[test]
+    fn origins_ranking() {
+        let urls = [
+            "http://b.org/1",
+            "https://a.org/1",
+            "http://b.org/2",
+            "oci://registry/image@sha256:00",
+            "https://a.org:443/2",
+            "http://b.org:8080/3",
+            "https://a.org/3",
+        ];
+        assert_eq!(
+            busiest_origins(urls),
+            [
+                ("https://a.org".to_owned(), 3),
+                ("http://b.org".to_owned(), 2),
+                ("http://b.org:8080".to_owned(), 1),
+            ]
+        );
+        assert_eq!(authority("https://a.org"), "a.org:443");
+        assert_eq!(authority("http://b.org:8080"), "b.org:8080");
+    }
+}